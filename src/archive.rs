@@ -0,0 +1,112 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::file::{FileError, PathExt};
+use crate::file::FileError::FError;
+
+/// Returns the total size of the files in `folder_path` and its subfolders, and the timestamp of the newest one, or of
+/// the folder itself when it has no files. The timestamp is in whole seconds, since that is all a tar archive keeps, so
+/// that a restored folder still matches its archive.
+pub fn get_folder_fingerprint(folder_path: &Path) -> Result<(u64, SystemTime), FileError> {
+    let folder_modified = match folder_path.metadata().and_then(|metadata| metadata.modified()) {
+        Ok(modified) => modified,
+        Err(err) => return Err(FError(vec![format!("Error reading folder {}: {}", folder_path.str(), err)]))
+    };
+    let listing = get_folder_listing(folder_path)?;
+    let total_len = listing.iter().map(|listed_file| listed_file.len).sum();
+    let newest_modified = listing.iter()
+        .map(|listed_file| UNIX_EPOCH + Duration::from_secs(listed_file.modified_sec))
+        .max();
+    Ok((total_len, truncate_to_secs(newest_modified.unwrap_or(folder_modified))))
+}
+
+/// A file in a backed up folder, as far as a tar archive keeps track of it
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ListedFile {
+    /// Relative to the backed up folder, with `/` between the folder names
+    path: String,
+    len: u64,
+    modified_sec: u64,
+}
+
+/// Lists the files in `folder_path` and its subfolders, sorted by path
+pub fn get_folder_listing(folder_path: &Path) -> Result<Vec<ListedFile>, FileError> {
+    let mut listing = Vec::new();
+    add_folder_listing(folder_path, "", &mut listing)?;
+    listing.sort();
+    Ok(listing)
+}
+
+fn add_folder_listing(folder_path: &Path, path_prefix: &str, listing: &mut Vec<ListedFile>) -> Result<(), FileError> {
+    let read_err = |err| FError(vec![format!("Error reading folder {}: {}", folder_path.str(), err)]);
+    for entry in std::fs::read_dir(folder_path).map_err(read_err)? {
+        let entry_path = entry.map_err(read_err)?.path();
+        let metadata = entry_path.metadata().map_err(read_err)?;
+        let path = format!("{}{}", path_prefix, entry_path.file_name_str());
+        if metadata.is_dir() {
+            add_folder_listing(&entry_path, &format!("{}/", path), listing)?;
+        } else {
+            let modified = metadata.modified().map_err(read_err)?;
+            let modified_sec = modified.duration_since(UNIX_EPOCH).map_or(0, |since_epoch| since_epoch.as_secs());
+            listing.push(ListedFile { path, len: metadata.len(), modified_sec });
+        }
+    }
+    Ok(())
+}
+
+/// Lists the files in the tar archive read from `archive`, sorted by path, to be compared with [`get_folder_listing`]
+pub fn get_archive_listing(archive: impl Read) -> std::io::Result<Vec<ListedFile>> {
+    let mut listing = Vec::new();
+    for entry in tar::Archive::new(archive).entries()? {
+        let entry = entry?;
+        let header = entry.header();
+        if !header.entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?.components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+                _ => None
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        listing.push(ListedFile { path, len: header.size()?, modified_sec: header.mtime()? });
+    }
+    listing.sort();
+    Ok(listing)
+}
+
+fn truncate_to_secs(time: SystemTime) -> SystemTime {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since_epoch) => UNIX_EPOCH + Duration::from_secs(since_epoch.as_secs()),
+        Err(_) => time
+    }
+}
+
+/// Writes `folder_path` and everything in it to a tar archive at `to_path`
+pub fn write_folder_archive(folder_path: &Path, to_path: &Path) -> Result<(), FileError> {
+    let archive_err = |err| FError(vec![format!("Error archiving {} to {}: {}", folder_path.str(), to_path.str(), err)]);
+    let archive_file = File::create(to_path).map_err(archive_err)?;
+    let mut archive_builder = tar::Builder::new(archive_file);
+    archive_builder.append_dir_all(".", folder_path).map_err(archive_err)?;
+    archive_builder.into_inner().map_err(archive_err)?;
+    Ok(())
+}
+
+/// Extracts the tar archive at `archive_path` into the new folder `to_folder_path`, keeping the timestamps of its files
+pub fn extract_folder_archive(archive_path: &Path, to_folder_path: &Path) -> Result<(), FileError> {
+    let extract_err = |err| FError(vec![
+        format!("Error extracting {} to {}: {}", archive_path.str(), to_folder_path.str(), err)
+    ]);
+    let archive_file = File::open(archive_path).map_err(extract_err)?;
+    std::fs::create_dir(to_folder_path).map_err(extract_err)?;
+    tar::Archive::new(archive_file).unpack(to_folder_path).map_err(extract_err)
+}
\ No newline at end of file
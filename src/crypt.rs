@@ -0,0 +1,140 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use argon2::Argon2;
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+use chacha20poly1305::aead::{Aead, OsRng};
+use chacha20poly1305::aead::rand_core::RngCore;
+use parking_lot::{const_mutex, Mutex};
+
+use crate::file::{FileError, PathExt};
+use crate::file::FileError::FError;
+
+// Identifies an encrypted backed up file, whose name is otherwise the same as an unencrypted one
+const MAGIC: &[u8; 8] = b"VALBAK\x00\x01";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const HEADER_LEN: usize = MAGIC.len() + SALT_LEN + NONCE_LEN;
+
+/// The number of bytes that encryption adds to a file
+pub const ENCRYPTION_OVERHEAD: u64 = (HEADER_LEN + TAG_LEN) as u64;
+
+// Only ever held in memory, and only for the current session
+static SESSION: Mutex<Option<Session>> = const_mutex(None);
+
+/// The passphrase entered this session, along with the keys derived from it. Deriving a key is deliberately slow, so
+/// every new backup of the session uses the same salt, and so the same key, each with a nonce of its own.
+struct Session {
+    passphrase: String,
+    encrypt_salt: [u8; SALT_LEN],
+    keys: Vec<([u8; SALT_LEN], Key)>,
+}
+
+pub fn set_session_passphrase(passphrase: String) {
+    let mut encrypt_salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut encrypt_salt);
+    *SESSION.lock() = Some(Session { passphrase, encrypt_salt, keys: Vec::new() });
+}
+
+/// Forgets the session passphrase, e.g. when it turned out to be wrong, so that it is asked for again
+pub fn clear_session_passphrase() {
+    *SESSION.lock() = None;
+}
+
+pub fn has_session_passphrase() -> bool {
+    SESSION.lock().is_some()
+}
+
+/// Checks that the session passphrase decrypts the encrypted backed up file `path`
+pub fn verify_session_passphrase(path: &Path) -> Result<(), FileError> {
+    let encrypted = match std::fs::read(path) {
+        Ok(encrypted) => encrypted,
+        Err(err) => return Err(FError(vec![format!("Error reading {}: {}", path.str(), err)]))
+    };
+    decrypt(path, &encrypted).map(|_plaintext| ())
+}
+
+/// Checks whether `path` starts with the header written by [`encrypt`]
+pub fn is_encrypted_file(path: &Path) -> bool {
+    let mut magic = [0u8; MAGIC.len()];
+    match File::open(path) {
+        Ok(mut file) => file.read_exact(&mut magic).is_ok() && &magic == MAGIC,
+        Err(_) => false
+    }
+}
+
+/// Encrypts `plaintext` with a key derived from the session passphrase, prefixed by the header needed to decrypt it
+pub fn encrypt(plaintext: &[u8]) -> Result<Vec<u8>, FileError> {
+    let (salt, key) = get_encrypt_key()?;
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+
+    let cipher = ChaCha20Poly1305::new(&key);
+    let ciphertext = match cipher.encrypt(Nonce::from_slice(&nonce), plaintext) {
+        Ok(ciphertext) => ciphertext,
+        Err(err) => return Err(FError(vec![format!("Error encrypting file: {}", err)]))
+    };
+
+    let mut encrypted = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+    encrypted.extend_from_slice(MAGIC);
+    encrypted.extend_from_slice(&salt);
+    encrypted.extend_from_slice(&nonce);
+    encrypted.extend_from_slice(&ciphertext);
+    Ok(encrypted)
+}
+
+/// Decrypts `encrypted`, as read from `path`, with a key derived from the session passphrase
+pub fn decrypt(path: &Path, encrypted: &[u8]) -> Result<Vec<u8>, FileError> {
+    if encrypted.len() < HEADER_LEN + TAG_LEN || &encrypted[..MAGIC.len()] != MAGIC {
+        return Err(FError(vec![format!("Not an encrypted backup file: {}", path.str())]));
+    }
+    let mut salt = [0u8; SALT_LEN];
+    salt.copy_from_slice(&encrypted[MAGIC.len()..MAGIC.len() + SALT_LEN]);
+    let nonce = &encrypted[MAGIC.len() + SALT_LEN..HEADER_LEN];
+
+    let cipher = ChaCha20Poly1305::new(&get_key(&salt)?);
+    match cipher.decrypt(Nonce::from_slice(nonce), &encrypted[HEADER_LEN..]) {
+        Ok(plaintext) => Ok(plaintext),
+        // Authentication fails the same way for either cause
+        Err(_) => Err(FError(vec![format!("Wrong passphrase or damaged backup file: {}", path.str())]))
+    }
+}
+
+/// The salt and key that new backups of this session are encrypted with
+fn get_encrypt_key() -> Result<([u8; SALT_LEN], Key), FileError> {
+    let encrypt_salt = match SESSION.lock().as_ref() {
+        Some(session) => session.encrypt_salt,
+        None => return Err(no_passphrase_error())
+    };
+    Ok((encrypt_salt, get_key(&encrypt_salt)?))
+}
+
+/// The key derived from the session passphrase with `salt`, which is only derived the first time it is needed
+fn get_key(salt: &[u8; SALT_LEN]) -> Result<Key, FileError> {
+    let mut session = SESSION.lock();
+    let session = match session.as_mut() {
+        Some(session) => session,
+        None => return Err(no_passphrase_error())
+    };
+    if let Some((_salt, key)) = session.keys.iter().find(|(key_salt, _key)| key_salt == salt) {
+        return Ok(*key);
+    }
+    let mut key = Key::default();
+    if let Err(err) = Argon2::default().hash_password_into(session.passphrase.as_bytes(), salt, &mut key) {
+        return Err(FError(vec![format!("Error deriving encryption key: {}", err)]));
+    }
+    session.keys.push((*salt, key));
+    Ok(key)
+}
+
+fn no_passphrase_error() -> FileError {
+    FError(vec!["No passphrase has been entered for encrypted backups".to_string()])
+}
\ No newline at end of file
@@ -0,0 +1,122 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::file::{FileError, get_backed_up_version, is_date_folder, is_internal_dest_path, PathExt};
+use crate::file::FileError::FError;
+use crate::manifest::get_manifest_path;
+use crate::settings::Settings;
+use crate::sidecar::is_sidecar_path;
+
+/// Written into `backup_dest_path` on first use, marking the folder as a Valbak backup destination
+pub const DEST_MARKER_FILE_NAME: &str = ".valbak";
+pub const DEST_MARKER_SCHEMA_VERSION: &str = "1";
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct DestMarker {
+    pub schema_version: String,
+    /// When the destination was first used, in RFC 3339 format
+    pub created: String,
+    /// The version of Valbak that first used the destination
+    pub app_version: String,
+}
+
+pub fn get_dest_marker_path(backup_dest_path: &Path) -> PathBuf {
+    backup_dest_path.join(DEST_MARKER_FILE_NAME)
+}
+
+/// Reads the marker of `backup_dest_path`, or returns `None` when Valbak has not used it yet
+pub fn read_dest_marker(backup_dest_path: &Path) -> Result<Option<DestMarker>, FileError> {
+    let marker_path = get_dest_marker_path(backup_dest_path);
+    if !marker_path.exists() {
+        return Ok(None);
+    }
+    let marker_str = match std::fs::read_to_string(&marker_path) {
+        Ok(marker_str) => marker_str,
+        Err(err) => return Err(FError(vec![format!("Error reading {}: {}", marker_path.str(), err)]))
+    };
+    match serde_json::from_str(&marker_str) {
+        Ok(marker) => Ok(Some(marker)),
+        Err(err) => Err(FError(vec![format!("Invalid backup folder marker {}: {}", marker_path.str(), err)]))
+    }
+}
+
+/// Writes the marker of `backup_dest_path` unless it already has one
+pub fn ensure_dest_marker(backup_dest_path: &Path) -> Result<(), FileError> {
+    let marker_path = get_dest_marker_path(backup_dest_path);
+    if marker_path.exists() {
+        return Ok(());
+    }
+    let marker = DestMarker {
+        schema_version: DEST_MARKER_SCHEMA_VERSION.to_string(),
+        created: Local::now().to_rfc3339(),
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+    };
+    let marker_str = match serde_json::to_string_pretty(&marker) {
+        Ok(marker_str) => marker_str,
+        Err(err) => return Err(FError(vec![format!("Error creating backup folder marker: {}", err)]))
+    };
+    if let Err(err) = std::fs::write(&marker_path, marker_str) {
+        return Err(FError(vec![format!("Error writing {}: {}", marker_path.str(), err)]));
+    }
+    info!("Marked {} as a backup folder", backup_dest_path.str());
+    Ok(())
+}
+
+/// Finds the files in the backup destination that do not look like Valbak put them there, when the destination has no
+/// marker yet. Only the destination itself and the backup folders directly inside it or inside its date folders are
+/// searched.
+pub fn find_unrelated_dest_files(settings: &Settings) -> Result<Vec<PathBuf>, FileError> {
+    let backup_dest_path = &settings.backup_dest_path;
+    if get_dest_marker_path(backup_dest_path).exists() {
+        return Ok(Vec::new());
+    }
+    let mut unrelated_files = Vec::new();
+    for dest_entry_path in read_dir_paths(backup_dest_path)? {
+        if is_internal_dest_path(settings, &dest_entry_path) || dest_entry_path == get_manifest_path(backup_dest_path) {
+            continue;
+        }
+        if !dest_entry_path.is_dir() {
+            // Backed up files are always in a backup folder
+            unrelated_files.push(dest_entry_path);
+            continue;
+        }
+        // The backup folders of the dated layout are inside date folders
+        let backed_up_folders = if is_date_folder(&dest_entry_path) {
+            read_dir_paths(&dest_entry_path)?
+        } else {
+            vec![dest_entry_path]
+        };
+        for backed_up_folder in backed_up_folders {
+            if !backed_up_folder.is_dir() {
+                unrelated_files.push(backed_up_folder);
+                continue;
+            }
+            for file_path in read_dir_paths(&backed_up_folder)? {
+                let is_backed_up_file = file_path.is_file()
+                    && (get_backed_up_version(&file_path).is_some() || is_sidecar_path(&file_path));
+                if !is_backed_up_file && !is_internal_dest_path(settings, &file_path) {
+                    unrelated_files.push(file_path);
+                }
+            }
+        }
+    }
+    Ok(unrelated_files)
+}
+
+fn read_dir_paths(dir_path: &Path) -> Result<Vec<PathBuf>, FileError> {
+    let read_dir_err = |err| FError(vec![format!("Error reading folder {}: {}", dir_path.str(), err)]);
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(dir_path).map_err(read_dir_err)? {
+        paths.push(entry.map_err(read_dir_err)?.path());
+    }
+    Ok(paths)
+}
\ No newline at end of file
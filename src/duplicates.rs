@@ -0,0 +1,111 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use crate::file::{FileError, get_backed_up_files, get_file_metadata, get_live_file_for_backed_up_file,
+    get_newest_backed_up_files, PathExt, strip_version_suffix_from_backed_up_file_path};
+use crate::file::FileError::{FError, FFatal, FWarning};
+use crate::inventory::hash_file;
+use crate::settings::Settings;
+
+/// Byte-identical backed up files of different live files
+#[derive(Clone, Debug)]
+pub struct DuplicateGroup {
+    /// The size of each of the files
+    pub size: u64,
+    /// All identical backed up files, sorted by path
+    pub backed_up_file_paths: Vec<PathBuf>,
+    /// The files in `backed_up_file_paths` that can be deleted while keeping one copy of the content. The newest
+    /// version of a live file is never redundant, so that each live file can still be restored to its last backup.
+    pub redundant_file_paths: Vec<PathBuf>,
+}
+
+/// Finds the backed up files in the destination specified by `settings` whose content is identical to that of a backed
+/// up file of another live file, e.g. the same save copied under a different name. Only files of equal size are hashed.
+/// Encrypted backups differ even when their content is the same, so they are never found. Files that cannot be read
+/// are left out, and files whose live file cannot be told count as files of their own. Both are returned as warnings
+/// along with the groups that were found.
+pub fn find_duplicate_backups(settings: &Settings) -> (Vec<DuplicateGroup>, Option<FileError>) {
+    let (backed_up_file_paths, scan_err) = get_backed_up_files(settings.clone());
+    if let Some(scan_err) = scan_err {
+        // Deleting redundant copies based on a partial scan could delete the last copy of a file
+        return (Vec::new(), Some(scan_err));
+    }
+    let (newest_backed_up_file_paths, scan_err) = get_newest_backed_up_files(settings.clone());
+    if let Some(scan_err) = scan_err {
+        return (Vec::new(), Some(scan_err));
+    }
+    let newest_backed_up_file_paths: HashSet<PathBuf> = newest_backed_up_file_paths.into_iter().collect();
+
+    let mut errs = Vec::new();
+    let mut backed_up_file_paths_by_sizes: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    for backed_up_file_path in backed_up_file_paths {
+        match get_file_metadata(&backed_up_file_path) {
+            Ok((metadata, _modified)) =>
+                backed_up_file_paths_by_sizes.entry(metadata.len()).or_default().push(backed_up_file_path),
+            Err(FWarning(mut e)) | Err(FError(mut e)) | Err(FFatal(mut e)) =>
+                errs.append(&mut e)
+        }
+    }
+
+    let mut duplicate_groups = Vec::new();
+    for (size, same_size_file_paths) in backed_up_file_paths_by_sizes {
+        if same_size_file_paths.len() < 2 {
+            continue;
+        }
+        let mut backed_up_file_paths_by_hashes: HashMap<String, Vec<PathBuf>> = HashMap::new();
+        for backed_up_file_path in same_size_file_paths {
+            match hash_file(&backed_up_file_path) {
+                Ok(hash) => backed_up_file_paths_by_hashes.entry(hash).or_default().push(backed_up_file_path),
+                Err(FWarning(mut e)) | Err(FError(mut e)) | Err(FFatal(mut e)) => errs.append(&mut e)
+            }
+        }
+        for (_hash, mut identical_file_paths) in backed_up_file_paths_by_hashes {
+            let mut live_file_paths = HashSet::new();
+            for backed_up_file_path in &identical_file_paths {
+                let live_file_path = get_live_file_for_backed_up_file(settings.clone(), backed_up_file_path.clone());
+                let live_file_path = match live_file_path {
+                    Ok(live_file_path) => live_file_path,
+                    // Told apart from the other live files by its name without the version suffix instead
+                    Err(_) => {
+                        errs.push(format!("Cannot tell which live file {} is a backup of, counting it as a file of \
+                            its own", backed_up_file_path.str()));
+                        strip_version_suffix_from_backed_up_file_path(backed_up_file_path)
+                            .unwrap_or_else(|| backed_up_file_path.clone())
+                    }
+                };
+                live_file_paths.insert(live_file_path);
+            }
+            if live_file_paths.len() < 2 {
+                // Identical versions of the same file are left to pruning
+                continue;
+            }
+            identical_file_paths.sort();
+            // Keeps a newest version when there is one, and otherwise the first file
+            let kept_file_path = identical_file_paths.iter()
+                .find(|path| newest_backed_up_file_paths.contains(*path))
+                .unwrap_or(&identical_file_paths[0])
+                .clone();
+            let redundant_file_paths = identical_file_paths.iter()
+                .filter(|path| **path != kept_file_path && !newest_backed_up_file_paths.contains(*path))
+                .cloned()
+                .collect();
+            duplicate_groups.push(DuplicateGroup {
+                size,
+                backed_up_file_paths: identical_file_paths,
+                redundant_file_paths
+            });
+        }
+    }
+    duplicate_groups.sort_by(|a, b| a.backed_up_file_paths[0].cmp(&b.backed_up_file_paths[0]));
+    if errs.is_empty() {
+        (duplicate_groups, None)
+    } else {
+        (duplicate_groups, Some(FWarning(errs)))
+    }
+}
\ No newline at end of file
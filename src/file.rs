@@ -4,22 +4,59 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Formatter};
-use std::fs::Metadata;
-use std::io::ErrorKind;
+use std::fs::{File, Metadata};
+use std::io::{BufRead, BufReader, ErrorKind};
 use std::path::{Path, PathBuf};
-use std::time::SystemTime;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::Result;
+use chrono::{Local, NaiveDate, NaiveDateTime, Timelike};
 use filetime::{FileTime, set_file_mtime};
-use glob::{glob, Pattern};
-use log::{error, info, warn};
+use glob::{glob, glob_with, MatchOptions, Pattern};
+use log::{debug, error, info, warn};
 use multimap::MultiMap;
-
-use crate::file::FileError::{FError, FWarning};
-use crate::settings::{BackupFilePattern, Settings};
-
-#[derive(thiserror::Error, Debug)]
+use parking_lot::{const_mutex, Mutex};
+
+use crate::archive::{extract_folder_archive, get_archive_listing, get_folder_fingerprint, get_folder_listing, write_folder_archive};
+use crate::crypt;
+use crate::locked_file::{get_locked_file_reader, is_locked_file_error};
+use crate::manifest::{Manifest, read_manifest, record_backup};
+use crate::mirror::mirror_backed_up_file;
+use crate::file::FileError::{FError, FFatal, FWarning};
+use crate::settings::{BackupFilePattern, BackupLayout, OverwritePolicy, RetentionPolicy, Settings, TempFileLocation, VERSION_SEPARATORS, VersionScheme};
+use crate::sidecar::{BackupMetadata, get_note_path, get_sidecar_path, is_sidecar_path, read_sidecar, write_sidecar};
+
+const TEMP_FOLDER_NAME: &str = ".valbak_tmp";
+const TEMP_FILE_PREFIX: &str = "_";
+// Holds the known good copy of each file, mirroring the backup folders, where old backups are never pruned
+const KNOWN_GOOD_FOLDER_NAME: &str = ".known_good";
+// Holds the backups deleted with `Settings::delete_to_trash`, in a date folder of the day they were deleted that mirrors
+// the destination
+const TRASH_FOLDER_NAME: &str = ".trash";
+const VERSION_TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H-%M-%S";
+/// The names of the date folders of `BackupLayout::Dated`, e.g. `2024-01-15`
+const DATE_FOLDER_FORMAT: &str = "%Y-%m-%d";
+const DATE_FOLDER_GLOB: &str = "[0-9][0-9][0-9][0-9]-[0-9][0-9]-[0-9][0-9]";
+// Marks the names of restored copies, which are never backed up themselves
+const RESTORED_COPY_MARKER: &str = ".restored-";
+const TRANSIENT_ERROR_ATTEMPTS: u32 = 5;
+const TRANSIENT_ERROR_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+/// How often a taken backup version is replaced by the next version before giving up
+const BACKUP_TARGET_ATTEMPTS: u32 = 5;
+
+/// The number of backups and restores running on any thread
+static OPERATIONS_IN_PROGRESS: AtomicUsize = AtomicUsize::new(0);
+/// The temp files and folders handed out while any backup or restore is running, which a forced quit may leave behind
+static TEMP_FILES_IN_PROGRESS: Mutex<Vec<PathBuf>> = const_mutex(Vec::new());
+// Tells apart the temp files of the same file, e.g. of a backup and a restore running at the same time
+static NEXT_TEMP_FILE_ID: AtomicUsize = AtomicUsize::new(0);
+/// The live files overwritten by restores, with their size and timestamp right after the restore, until they change
+static RESTORED_LIVE_FILES: Mutex<Vec<(PathBuf, u64, SystemTime)>> = const_mutex(Vec::new());
+
+#[derive(thiserror::Error, Clone, Debug)]
 pub enum FileError {
     FWarning(Vec<String>),
     FError(Vec<String>),
@@ -27,11 +64,132 @@ pub enum FileError {
 }
 
 impl Display for FileError {
-    fn fmt(&self, _f: &mut Formatter<'_>) -> std::fmt::Result {
-        todo!()
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FWarning(errs) | FError(errs) | FFatal(errs) => write!(f, "{}", errs.join("\n"))
+        }
+    }
+}
+
+/// Counts a backup or restore as in progress for as long as it lives
+struct OperationGuard;
+
+impl OperationGuard {
+    fn start() -> OperationGuard {
+        OPERATIONS_IN_PROGRESS.fetch_add(1, Ordering::SeqCst);
+        OperationGuard
+    }
+}
+
+impl Drop for OperationGuard {
+    fn drop(&mut self) {
+        // Holding the lock keeps the temp files of an operation starting meanwhile from being cleared
+        let mut temp_files = TEMP_FILES_IN_PROGRESS.lock();
+        if OPERATIONS_IN_PROGRESS.fetch_sub(1, Ordering::SeqCst) == 1 {
+            temp_files.clear();
+        }
+    }
+}
+
+/// Whether a backup or restore is running, which quitting now would interrupt
+pub fn is_operation_in_progress() -> bool {
+    OPERATIONS_IN_PROGRESS.load(Ordering::SeqCst) > 0
+}
+
+/// Removes the temp files and folders of the backups and restores in progress, before quitting without waiting for
+/// them. The live files and completed backups are left alone.
+pub fn remove_temp_files_in_progress() {
+    for temp_path in TEMP_FILES_IN_PROGRESS.lock().drain(..) {
+        remove_temp_path(&temp_path);
     }
 }
 
+/// Receives the progress of backups, and answers the questions a restore needs answered, on behalf of the application
+/// driving them
+pub trait BackupListener {
+    /// Shows `status` until the matching `pop_status`
+    fn push_status(&self, status: String);
+    /// Replaces the status of the latest `push_status` with `status`, e.g. to count up the progress of a long operation
+    fn set_status(&self, status: String);
+    fn pop_status(&self);
+    /// Whether to restore over `live_file_path` although it is newer than `backed_up_file_path`, when the overwrite
+    /// policy is to ask
+    fn confirm_overwrite_newer(&self, live_file_path: &Path, backed_up_file_path: &Path) -> bool;
+    /// Where to restore `backed_up_file_path` to, given the live file `live_file_path` derived for it, or `None` to skip
+    /// restoring it
+    fn confirm_restore_target(&self, backed_up_file_path: &Path, live_file_path: &Path) -> Option<PathBuf>;
+    /// Where to restore `backed_up_file_path` to when neither its sidecar nor the backup patterns tell which live file
+    /// it was backed up from, or `None` to skip restoring it
+    fn choose_unknown_restore_target(&self, backed_up_file_path: &Path) -> Option<PathBuf>;
+}
+
+/// The version suffix of a backed up file. Versions of the same scheme order from oldest to newest. Counter versions
+/// order before timestamp versions, which says nothing about which is older, so backed up files whose versions may be
+/// of different schemes are ordered with `compare_backed_up_versions` instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BackupVersion {
+    Counter(u32),
+    Timestamp(NaiveDateTime),
+}
+
+impl Display for BackupVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackupVersion::Counter(counter) => write!(f, "{}", counter),
+            BackupVersion::Timestamp(timestamp) => write!(f, "{}", timestamp.format(VERSION_TIMESTAMP_FORMAT))
+        }
+    }
+}
+
+/// Orders the backed up files `a_path` and `b_path`, with versions `a_version` and `b_version`, from oldest to newest.
+/// Versions of different schemes, i.e. from before and after `Settings::version_scheme` was switched, order by the
+/// timestamps of the files instead, which hold when the live file was last modified.
+fn compare_versions(
+    a_version: &BackupVersion, a_path: &Path, b_version: &BackupVersion, b_path: &Path
+) -> std::cmp::Ordering {
+    let is_mixed = matches!((a_version, b_version),
+        (BackupVersion::Counter(_), BackupVersion::Timestamp(_))
+        | (BackupVersion::Timestamp(_), BackupVersion::Counter(_)));
+    if is_mixed {
+        match (a_path.metadata().and_then(|metadata| metadata.modified()),
+            b_path.metadata().and_then(|metadata| metadata.modified())
+        ) {
+            (Ok(a_modified), Ok(b_modified)) if a_modified != b_modified =>
+                return a_modified.cmp(&b_modified),
+            _ => {}
+        }
+    }
+    a_version.cmp(b_version)
+}
+
+/// Orders the backed up files `a` and `b` of the same live file from oldest to newest, even when the version scheme
+/// was switched between them. Files without a version order first. Three files of both schemes may not order the same
+/// way pairwise, so lists are sorted with `sort_backed_up_versions` instead.
+pub fn compare_backed_up_versions(a: &PathBuf, b: &PathBuf) -> std::cmp::Ordering {
+    match (get_backed_up_version(a), get_backed_up_version(b)) {
+        (Some(a_version), Some(b_version)) => compare_versions(&a_version, a, &b_version, b),
+        (a_version, b_version) => a_version.cmp(&b_version)
+    }
+}
+
+/// Sorts the backed up files `paths` of the same live file from oldest to newest. When their versions are of both
+/// schemes, all files order by their timestamps first, as `compare_versions` does for two files of different schemes,
+/// so that the order does not depend on which files are compared. Files without a version order first.
+pub fn sort_backed_up_versions(paths: &mut [PathBuf]) {
+    let versions: Vec<Option<BackupVersion>> = paths.iter().map(|path| get_backed_up_version(path)).collect();
+    let is_mixed = versions.iter().any(|version| matches!(version, Some(BackupVersion::Counter(_))))
+        && versions.iter().any(|version| matches!(version, Some(BackupVersion::Timestamp(_))));
+    paths.sort_by_cached_key(|path| {
+        let version = get_backed_up_version(path);
+        let modified = if is_mixed {
+            path.metadata().and_then(|metadata| metadata.modified()).ok()
+        } else {
+            None
+        };
+        (version.is_some(), modified, version)
+    });
+}
+
 pub trait PathExt {
     fn file_name_str(&self) -> &str;
     fn str(&self) -> &str;
@@ -57,15 +215,56 @@ impl PathExt for PathBuf {
     }
 }
 
-/// Queries the filesystem and returns all live files as specified by `settings`
+/// How the file patterns in `settings` match backed up files
+fn get_match_options(settings: &Settings) -> MatchOptions {
+    MatchOptions {
+        case_sensitive: !settings.case_insensitive,
+        ..MatchOptions::new()
+    }
+}
+
+/// How the file patterns in `settings` match live files, which leaves out hidden names as `settings` say. Backed up
+/// files are matched whether hidden or not, so that the backups of hidden files are still found once they are left out.
+fn get_live_file_match_options(settings: &Settings) -> MatchOptions {
+    MatchOptions {
+        require_literal_leading_dot: !settings.include_hidden,
+        ..get_match_options(settings)
+    }
+}
+
+/// Whether `live_file_path` is hidden by its attribute rather than its name and left out as `settings` say, which
+/// happens only on Windows. Hidden names are left out by the live file match options.
+fn is_excluded_hidden_file(settings: &Settings, live_file_path: &Path) -> bool {
+    if settings.include_hidden {
+        return false;
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::MetadataExt;
+        const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+        const FILE_ATTRIBUTE_SYSTEM: u32 = 0x4;
+        return live_file_path.metadata().map_or(false, |metadata| {
+            metadata.file_attributes() & (FILE_ATTRIBUTE_HIDDEN | FILE_ATTRIBUTE_SYSTEM) != 0
+        });
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = live_file_path;
+        false
+    }
+}
+
+/// Queries the filesystem and returns all live files as specified by the enabled patterns in `settings`
 pub fn get_live_files(settings: Settings) -> Result<Vec<PathBuf>, FileError> {
     let mut live_files = Vec::new();
-    for backup_pattern in &settings.backup_patterns {
+    for backup_pattern in settings.backup_patterns.iter().filter(|backup_pattern| backup_pattern.enabled) {
         let glob_pattern = backup_pattern.source_dir.join(&backup_pattern.filename_pattern);
-        let glob_paths = match glob(glob_pattern.str()) {
-            Err(err) =>
-                // This should have already happened and been handled
-                panic!("illegal state: {}", err),
+        let glob_paths = match glob_with(glob_pattern.str(), get_live_file_match_options(&settings)) {
+            Err(err) => {
+                // Valid settings have no invalid patterns, but settings browsed without working may
+                warn!("Skipping invalid file pattern \"{}\": {}", glob_pattern.str(), err);
+                continue;
+            }
             Ok(glob_paths) =>
                 glob_paths
         };
@@ -73,6 +272,13 @@ pub fn get_live_files(settings: Settings) -> Result<Vec<PathBuf>, FileError> {
             match glob_path {
                 Err(err) =>
                     return Err(FError(vec![format!("Error reading live files: {}", err)])),
+                Ok(file_path) if is_restored_copy(&file_path) =>
+                    debug!("Skipping restored copy {}", file_path.str()),
+                Ok(file_path) if is_excluded_hidden_file(&settings, &file_path) =>
+                    debug!("Skipping hidden file {}", file_path.str()),
+                Ok(file_path) if file_path.is_dir() != backup_pattern.directory_mode =>
+                    debug!("Skipping {}, the backup pattern matches only {}", file_path.str(),
+                        if backup_pattern.directory_mode { "folders" } else { "files" }),
                 Ok(file_path) =>
                     live_files.push(file_path)
             }
@@ -81,59 +287,260 @@ pub fn get_live_files(settings: Settings) -> Result<Vec<PathBuf>, FileError> {
     Ok(live_files)
 }
 
-/// Queries the filesystem and returns all backed up files as specified by `settings`
-pub fn get_backed_up_files(settings: Settings) -> Result<Vec<PathBuf>, FileError> {
-    let mut backed_up_files = Vec::new();
-    for backup_pattern in settings.backup_patterns {
-        let backup_folder_name = backup_pattern.source_dir.file_name().unwrap();
-        let backed_up_versions_filename_pattern = backup_pattern.filename_pattern + ".*";
+/// Counts the live files currently matching `backup_pattern`, one of the patterns in `settings`
+pub fn count_live_files_for_pattern(
+    settings: &Settings, backup_pattern: &BackupFilePattern
+) -> Result<usize, FileError> {
+    get_live_files_for_pattern(settings, backup_pattern).map(|live_file_paths| live_file_paths.len())
+}
+
+/// Queries the filesystem for the live files currently matching `backup_pattern`, which need not be one of the
+/// patterns in `settings` yet, e.g. to try out a pattern before adding it. An invalid pattern is returned as an error.
+pub fn get_live_files_for_pattern(
+    settings: &Settings, backup_pattern: &BackupFilePattern
+) -> Result<Vec<PathBuf>, FileError> {
+    let glob_pattern = backup_pattern.to_path();
+    match glob_with(glob_pattern.str(), get_live_file_match_options(settings)) {
+        Err(err) =>
+            Err(FError(vec![format!("Invalid file pattern \"{}\": {}", glob_pattern.str(), err)])),
+        Ok(glob_paths) =>
+            Ok(glob_paths
+                .filter_map(|glob_path| glob_path.ok())
+                .filter(|path| !is_restored_copy(path) && !is_excluded_hidden_file(settings, path)
+                    && path.is_dir() == backup_pattern.directory_mode)
+                .collect())
+    }
+}
+
+/// Returns the space available to this user on the filesystem containing `path`, in mb
+pub fn get_free_space_mb(path: &Path) -> Result<u64, FileError> {
+    match fs2::available_space(path) {
+        Ok(free_space) => Ok(free_space / (1000 * 1000)),
+        Err(err) => Err(FError(vec![format!("Error reading free space of {}: {}", path.str(), err)]))
+    }
+}
 
-        let backed_up_versions_pattern = settings.backup_dest_path
-            .join(backup_folder_name)
-            .join(backed_up_versions_filename_pattern);
+/// Checks that files can be created in the destination folder `dest_path` by creating and removing an empty probe file,
+/// which is named like a temp file so that it is never taken for a backed up file
+pub fn probe_dest_writable(dest_path: &Path) -> Result<(), FileError> {
+    let probe_path = dest_path.join(format!("{}write_probe_{}", TEMP_FILE_PREFIX, std::process::id()));
+    if let Err(err) = File::create(&probe_path) {
+        return Err(FError(vec![format!("Cannot create files in {}: {}", dest_path.str(), err)]));
+    }
+    if let Err(err) = std::fs::remove_file(&probe_path) {
+        warn!("Error removing {}: {}", probe_path.str(), err);
+    }
+    Ok(())
+}
 
-        let glob_paths = match glob(backed_up_versions_pattern.str()) {
+/// Queries the filesystem and returns all backed up files as specified by `settings`. Any errors scanning the backup
+/// destination are returned as an `FWarning` alongside the files that were found, which may then be incomplete.
+pub fn get_backed_up_files(settings: Settings) -> (Vec<PathBuf>, Option<FileError>) {
+    let mut backed_up_files = Vec::new();
+    let mut errs = Vec::new();
+    let match_options = get_match_options(&settings);
+    for backup_pattern in &settings.backup_patterns {
+        let live_file_pattern = match Pattern::new(&backup_pattern.filename_pattern) {
+            Ok(live_file_pattern) => live_file_pattern,
             Err(err) => {
-                error!("Error scanning backed up files for {}: {}", backed_up_versions_pattern.str(), err);
+                // Valid settings have no invalid patterns, but settings browsed without working may
+                warn!("Skipping invalid file pattern \"{}\": {}", backup_pattern.filename_pattern, err);
                 continue;
             }
-            Ok(glob_paths) =>
-                glob_paths
         };
+        let backup_folder = get_backup_folder(&settings, backup_pattern);
+        for backed_up_folder_pattern in get_backed_up_folder_patterns(&settings, &backup_folder) {
+            let backed_up_versions_pattern = backed_up_folder_pattern
+                .join(backup_pattern.filename_pattern.clone() + &get_versions_glob_suffix());
 
-        for glob_path in glob_paths {
-            match glob_path {
-                Err(err) =>
-                    error!("Error reading backed up files: {}", err),
-                Ok(file_path) => {
-                    if get_backed_up_version_number(&file_path).is_some() {
-                        backed_up_files.push(file_path);
+            let glob_paths = match glob_with(backed_up_versions_pattern.str(), match_options) {
+                Err(err) => {
+                    errs.push(format!("Error scanning backed up files for {}: {}", backed_up_versions_pattern.str(), err));
+                    continue;
+                }
+                Ok(glob_paths) =>
+                    glob_paths
+            };
+
+            for glob_path in glob_paths {
+                match glob_path {
+                    Err(err) =>
+                        errs.push(format!("Error reading backed up files: {}", err)),
+                    Ok(file_path) => match strip_version_suffix_from_backed_up_file_path(&file_path) {
+                        None =>
+                            debug!("Skipping {}, it has no version suffix", file_path.str()),
+                        // The versions pattern also matches versions of other files, e.g. `world.db.old.3` for `*.db`
+                        Some(stripped_file_path)
+                            if !live_file_pattern.matches_with(stripped_file_path.file_name_str(), match_options) =>
+                            debug!("Skipping {}, it is not a version of a file matching {}", file_path.str(),
+                                backup_pattern.filename_pattern),
+                        Some(_) =>
+                            backed_up_files.push(file_path)
                     }
                 }
             }
         }
     }
-    Ok(backed_up_files)
+    if errs.is_empty() {
+        (backed_up_files, None)
+    } else {
+        (backed_up_files, Some(FWarning(errs)))
+    }
+}
+
+/// Finds the backed up files in the backup destination of `settings` that no backup pattern matches any more, e.g.
+/// because their pattern was removed. They are not pruned, and cannot be restored to where they came from without
+/// adding their pattern again, unless they have a sidecar. `backed_up_file_paths` are the files the patterns do match,
+/// as returned by `get_backed_up_files`.
+pub fn get_orphaned_backed_up_files(
+    settings: &Settings, backed_up_file_paths: &[PathBuf]
+) -> (Vec<PathBuf>, Option<FileError>) {
+    let mut orphaned_file_paths = Vec::new();
+    let mut errs = Vec::new();
+    let mut backed_up_folders = Vec::new();
+    for dest_entry_path in read_dir_paths(&settings.backup_dest_path, &mut errs) {
+        if !dest_entry_path.is_dir() || is_internal_dest_path(settings, &dest_entry_path) {
+            continue;
+        }
+        if is_date_folder(&dest_entry_path) {
+            backed_up_folders.extend(read_dir_paths(&dest_entry_path, &mut errs).into_iter()
+                .filter(|path| path.is_dir()));
+        } else {
+            backed_up_folders.push(dest_entry_path);
+        }
+    }
+    for backed_up_folder in backed_up_folders {
+        for file_path in read_dir_paths(&backed_up_folder, &mut errs) {
+            let is_backed_up_file = file_path.is_file() && !is_sidecar_path(&file_path)
+                && !is_internal_dest_path(settings, &file_path) && get_backed_up_version(&file_path).is_some();
+            if is_backed_up_file && !backed_up_file_paths.contains(&file_path) {
+                orphaned_file_paths.push(file_path);
+            }
+        }
+    }
+    if errs.is_empty() {
+        (orphaned_file_paths, None)
+    } else {
+        (orphaned_file_paths, Some(FWarning(errs)))
+    }
+}
+
+/// The paths of the entries of `dir_path`, adding any errors reading it to `errs`
+fn read_dir_paths(dir_path: &Path, errs: &mut Vec<String>) -> Vec<PathBuf> {
+    match std::fs::read_dir(dir_path) {
+        Ok(entries) => entries.filter_map(|entry| match entry {
+            Ok(entry) => Some(entry.path()),
+            Err(err) => {
+                errs.push(format!("Error reading folder {}: {}", dir_path.str(), err));
+                None
+            }
+        }).collect(),
+        Err(err) => {
+            errs.push(format!("Error reading folder {}: {}", dir_path.str(), err));
+            Vec::new()
+        }
+    }
+}
+
+/// What backing up all live files did
+#[derive(Clone, Debug, Default)]
+pub struct BackupSweep {
+    /// Each live file that was backed up along with the path of its new backed up file
+    pub backed_up_files: Vec<(PathBuf, PathBuf)>,
+    /// Live files that already had a backup
+    pub up_to_date_count: usize,
+    /// Live files that were not backed up, e.g. empty files or files skipped with a warning
+    pub skipped_count: usize,
+    /// Old versions removed after the new backups
+    pub pruned_count: usize,
+}
+
+impl Display for BackupSweep {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Backed up {} files, skipped {} already-current, pruned {} old versions.",
+            self.backed_up_files.len(), self.up_to_date_count, self.pruned_count)?;
+        if self.skipped_count > 0 {
+            write!(f, " {} other files were not backed up.", self.skipped_count)?;
+        }
+        Ok(())
+    }
 }
 
 /// Searches all live files for any that do not have a backed up version, and creates backups for such files.
-pub fn backup_all_changed_files(settings: Settings) -> Result<(), FileError> {
-    let live_file_paths = get_live_files(settings.clone())?;
+/// Returns what was done along with any errors, since some files may have been backed up before an error occurred.
+pub fn backup_all_changed_files(
+    settings: Settings, listener: &dyn BackupListener
+) -> (BackupSweep, Option<FileError>) {
+    backup_live_files(settings, listener, true)
+}
+
+/// Backs up every live file as a new version, whether or not it already has a backed up version, e.g. to establish a
+/// baseline after the backup patterns changed. Old versions are pruned as usual.
+/// Returns what was done along with any errors, since some files may have been backed up before an error occurred.
+pub fn backup_all_live_files(
+    settings: Settings, listener: &dyn BackupListener
+) -> (BackupSweep, Option<FileError>) {
+    backup_live_files(settings, listener, false)
+}
+
+fn backup_live_files(
+    settings: Settings, listener: &dyn BackupListener, skip_backed_up: bool
+) -> (BackupSweep, Option<FileError>) {
+    let mut sweep = BackupSweep::default();
+    let mut warnings = Vec::new();
+    let live_file_paths = match get_live_files(settings.clone()) {
+        Ok(live_file_paths) => live_file_paths,
+        Err(err) => return (sweep, Some(err))
+    };
     for live_file_path in live_file_paths {
-        if !live_file_has_backup(settings.clone(), live_file_path.clone())? {
-            backup_live_file(settings.clone(), live_file_path)?;
-            delete_old_backups(settings.clone())?;
+        if skip_backed_up {
+            match live_file_has_backup(settings.clone(), live_file_path.clone(), listener) {
+                Ok(true) => {
+                    sweep.up_to_date_count += 1;
+                    continue;
+                }
+                Ok(false) => {}
+                Err(err) => return (sweep, Some(err))
+            }
+        }
+        match backup_live_file(settings.clone(), live_file_path.clone(), listener) {
+            Ok(Some(backed_up_file_path)) =>
+                sweep.backed_up_files.push((live_file_path, backed_up_file_path)),
+            Ok(None) => sweep.skipped_count += 1,
+            Err(FWarning(mut file_warnings)) => {
+                // Skipped files should not stop the remaining files from being backed up
+                warnings.append(&mut file_warnings);
+                sweep.skipped_count += 1;
+                continue;
+            }
+            Err(err) => return (sweep, Some(err))
+        }
+        match delete_old_backups(settings.clone(), listener) {
+            Ok(count) => sweep.pruned_count += count,
+            Err(err) => return (sweep, Some(err))
         }
     }
-    Ok(())
+    if warnings.is_empty() {
+        (sweep, None)
+    } else {
+        (sweep, Some(FWarning(warnings)))
+    }
 }
 
 /// Determines whether the given live file path has been previously backed up.
 /// A live file is considered backed up if a version file is found that matches the live file's size and last-modified
 /// timestamp.
-pub fn live_file_has_backup(settings: Settings, live_file_path: PathBuf) -> Result<bool, FileError> {
-    // 1. Find the backup pattern related to this file
+pub fn live_file_has_backup(
+    settings: Settings, live_file_path: PathBuf, listener: &dyn BackupListener
+) -> Result<bool, FileError> {
+    listener.push_status(format!("Checking {}", live_file_path.file_name_str()));
+    let has_backup = find_backup_for_live_file(settings, live_file_path);
+    listener.pop_status();
+    has_backup
+}
 
+/// Finds the backup pattern in `settings` that `live_file_path` matches
+fn find_backup_pattern_for_live_file<'a>(settings: &'a Settings, live_file_path: &PathBuf) -> Option<&'a BackupFilePattern> {
     let live_file_folder_name = live_file_path.parent().unwrap().file_name_str();
     let mut found_backup_pattern = None;
     for backup_pattern in &settings.backup_patterns {
@@ -141,18 +548,95 @@ pub fn live_file_has_backup(settings: Settings, live_file_path: PathBuf) -> Resu
         if backup_pattern_folder_name == live_file_folder_name {
             match Pattern::new(backup_pattern.filename_pattern.as_str()) {
                 Ok(file_pattern) => {
-                    if file_pattern.matches_path(&live_file_path) {
+                    // A live file that no longer exists may be either kind
+                    let same_kind = !live_file_path.exists() || live_file_path.is_dir() == backup_pattern.directory_mode;
+                    // The file name only, since leaving out hidden files would keep the wildcards from matching
+                    // folders such as `.config` in the rest of the path
+                    let matches = file_pattern
+                        .matches_with(live_file_path.file_name_str(), get_live_file_match_options(settings))
+                        && !is_excluded_hidden_file(settings, live_file_path);
+                    if matches && same_kind {
                         found_backup_pattern = Some(backup_pattern);
                     }
                 },
-                Err(err) => {
-                    // This should have already happened and been handled
-                    panic!("illegal state: {}", err)
-                }
+                Err(err) =>
+                    // Valid settings have no invalid patterns, but settings browsed without working may
+                    warn!("Skipping invalid file pattern \"{}\": {}", backup_pattern.filename_pattern, err)
             }
         }
     }
-    let backup_pattern = match found_backup_pattern {
+    found_backup_pattern
+}
+
+/// Whether `live_file_path` matches a backup pattern in `settings` that is disabled, so that changes to it are not
+/// backed up
+pub fn is_disabled_live_file(settings: &Settings, live_file_path: &PathBuf) -> bool {
+    find_backup_pattern_for_live_file(settings, live_file_path).map_or(false, |backup_pattern| !backup_pattern.enabled)
+}
+
+/// The folder in the destination of `backup_pattern` that files matching it are backed up to. With
+/// `BackupLayout::Dated` new backups go into this folder inside a date folder instead, see
+/// `get_new_backup_folder`.
+fn get_backup_folder(settings: &Settings, backup_pattern: &BackupFilePattern) -> PathBuf {
+    backup_pattern.dest_path(&settings.backup_dest_path).join(backup_pattern.dest_subdir_name())
+}
+
+/// The folder that a new backup of a file matching `backup_pattern` is put in, as specified by the layout of `settings`
+fn get_new_backup_folder(settings: &Settings, backup_pattern: &BackupFilePattern) -> PathBuf {
+    match settings.backup_layout {
+        BackupLayout::Nested =>
+            get_backup_folder(settings, backup_pattern),
+        BackupLayout::Dated =>
+            backup_pattern.dest_path(&settings.backup_dest_path)
+                .join(Local::now().format(DATE_FOLDER_FORMAT).to_string())
+                .join(backup_pattern.dest_subdir_name())
+    }
+}
+
+/// Glob patterns of the folders that may hold versions backed up to `backup_folder`: the folder itself, and the folders
+/// of the same name in every date folder. Both layouts are searched, so that changing the layout never hides backups.
+fn get_backed_up_folder_patterns(settings: &Settings, backup_folder: &Path) -> Vec<PathBuf> {
+    vec![
+        backup_folder.to_path_buf(),
+        get_dest_path_for_backed_up_file(settings, backup_folder)
+            .join(DATE_FOLDER_GLOB)
+            .join(backup_folder.file_name_str())
+    ]
+}
+
+/// The destination folder of `settings` that holds `backed_up_file_path`: the innermost of `backup_dest_path` and the
+/// destination folders of the backup patterns that it is in, or else `backup_dest_path`
+pub fn get_dest_path_for_backed_up_file<'a>(settings: &'a Settings, backed_up_file_path: &Path) -> &'a Path {
+    settings.dest_paths().into_iter()
+        .filter(|dest_path| backed_up_file_path.starts_with(dest_path))
+        .max_by_key(|dest_path| dest_path.components().count())
+        .unwrap_or(&settings.backup_dest_path)
+}
+
+/// Whether `path` is a date folder of `BackupLayout::Dated`, e.g. `2024-01-15`
+pub fn is_date_folder(path: &Path) -> bool {
+    path.file_name().and_then(|filename| filename.to_str()).map_or(false, |filename| {
+        filename.len() == "2024-01-15".len() && NaiveDate::parse_from_str(filename, DATE_FOLDER_FORMAT).is_ok()
+    })
+}
+
+/// `backed_up_file_path` without the date folder it is in with `BackupLayout::Dated`, i.e. where the nested layout
+/// would have put it, since the versions of a file in all date folders are versions of the same file
+fn strip_date_folder(backed_up_file_path: &Path) -> PathBuf {
+    let backed_up_folder = backed_up_file_path.parent();
+    match backed_up_folder.and_then(|backed_up_folder| backed_up_folder.parent()) {
+        Some(date_folder) if is_date_folder(date_folder) => date_folder.parent().unwrap()
+            .join(backed_up_folder.unwrap().file_name_str())
+            .join(backed_up_file_path.file_name_str()),
+        _ =>
+            backed_up_file_path.to_path_buf()
+    }
+}
+
+fn find_backup_for_live_file(settings: Settings, live_file_path: PathBuf) -> Result<bool, FileError> {
+    // 1. Find the backup pattern related to this file
+
+    let backup_pattern = match find_backup_pattern_for_live_file(&settings, &live_file_path) {
         Some(pattern) => pattern,
         None => {
             return Err(FWarning( vec![
@@ -165,13 +649,26 @@ pub fn live_file_has_backup(settings: Settings, live_file_path: PathBuf) -> Resu
     //    other words, determine if the file that just changed appears to be a copy of an already backed up file.
 
     let backed_up_version_paths =
-        get_backed_up_version_paths(settings.backup_dest_path.clone(), backup_pattern.clone())?;
+        get_backed_up_version_paths(&settings, backup_pattern.clone())?;
 
-    let (live_file_metadata, live_file_modified) = get_file_metadata(&live_file_path)?;
+    let (live_file_len, live_file_modified) = get_live_file_fingerprint(&live_file_path)?;
+    if is_unchanged_since_restore(&live_file_path, live_file_len, live_file_modified) {
+        info!("{} is unchanged since it was restored", live_file_path.str());
+        return Ok(true);
+    }
 
     for backed_up_version_path in backed_up_version_paths {
         let (backed_up_file_metadata, backed_up_file_modified) = get_file_metadata(&backed_up_version_path)?;
-        if backed_up_file_metadata.len() == live_file_metadata.len() && backed_up_file_modified == live_file_modified {
+        if backed_up_file_modified != live_file_modified {
+            continue;
+        }
+        // The size of a folder archive says little about the size of the folder, so the files in it are compared
+        let is_copy = if backup_pattern.directory_mode {
+            archive_has_folder_listing(&backed_up_version_path, &live_file_path)
+        } else {
+            get_backed_up_content_len(&backed_up_version_path, &backed_up_file_metadata) == live_file_len
+        };
+        if is_copy {
             info!("{} appears to be a copy of {}", live_file_path.str(), backed_up_version_path.str());
             return Ok(true);
         }
@@ -180,266 +677,1869 @@ pub fn live_file_has_backup(settings: Settings, live_file_path: PathBuf) -> Resu
     return Ok(false);
 }
 
-/// Creates a new backup version file for `live_file_path`
-pub fn backup_live_file(settings: Settings, live_file_path: PathBuf) -> Result<(), FileError> {
-    // Copy the file and its containing folder name
-    let live_file_folder_name = live_file_path.parent().unwrap().file_name().unwrap();
+/// Whether the folder archive `backed_up_file_path` holds the same files, with the same sizes and timestamps, as the
+/// live folder `live_folder_path`. An archive that cannot be read is taken to differ, so that the folder is backed up
+/// again.
+fn archive_has_folder_listing(backed_up_file_path: &PathBuf, live_folder_path: &Path) -> bool {
+    let archive_listing = if crypt::is_encrypted_file(backed_up_file_path) {
+        read_file(backed_up_file_path)
+            .and_then(|encrypted| crypt::decrypt(backed_up_file_path, &encrypted))
+            .and_then(|archive| get_archive_listing(archive.as_slice())
+                .map_err(|err| FError(vec![format!("Error reading archive {}: {}", backed_up_file_path.str(), err)])))
+    } else {
+        File::open(backed_up_file_path)
+            .and_then(get_archive_listing)
+            .map_err(|err| FError(vec![format!("Error reading archive {}: {}", backed_up_file_path.str(), err)]))
+    };
+    match (archive_listing, get_folder_listing(live_folder_path)) {
+        (Ok(archive_listing), Ok(folder_listing)) => archive_listing == folder_listing,
+        (Err(err), _) | (_, Err(err)) => {
+            warn!("Error comparing {} with {}: {}", live_folder_path.str(), backed_up_file_path.str(), err);
+            false
+        }
+    }
+}
+
+/// Backs up `live_file_path`, returning the path of the new backed up file, or `None` when no new file was needed
+pub fn backup_live_file(
+    settings: Settings, live_file_path: PathBuf, listener: &dyn BackupListener
+) -> Result<Option<PathBuf>, FileError> {
+    let _operation_guard = OperationGuard::start();
+    // Unlike a live file that cannot be read, this keeps every other file from being backed up as well
+    if !settings.backup_dest_path.is_dir() {
+        return Err(FFatal(vec![
+            format!("Destination folder {} is no longer available", settings.backup_dest_path.str())
+        ]));
+    }
+    // Only the files backed up to it, e.g. on a removed drive, are kept from being backed up
+    if let Some(dest_path) = find_backup_pattern_for_live_file(&settings, &live_file_path)
+        .and_then(|backup_pattern| backup_pattern.dest_override.as_ref())
+    {
+        if !dest_path.is_dir() {
+            return Err(FError(vec![format!("Not backing up {}: destination folder {} is no longer available",
+                live_file_path.str(), dest_path.str())]));
+        }
+    }
+    listener.push_status(format!("Backing up {}", live_file_path.file_name_str()));
+    let result = copy_live_file_to_backup(settings.clone(), live_file_path);
+    listener.pop_status();
+    if let Ok(Some(backed_up_file_path)) = &result {
+        mirror_backed_up_file(&settings, backed_up_file_path);
+    }
+    result
+}
 
-    let backup_dest_path = settings.backup_dest_path.join(live_file_folder_name);
-    if let Err(err) = std::fs::create_dir(backup_dest_path.clone()) {
+fn copy_live_file_to_backup(settings: Settings, live_file_path: PathBuf) -> Result<Option<PathBuf>, FileError> {
+    let (live_file_metadata, _live_file_modified) = get_file_metadata(&live_file_path)?;
+    let (live_file_len, live_file_modified) = get_live_file_fingerprint(&live_file_path)?;
+    let backed_up_file_path = match get_backup_target(&settings, &live_file_path, live_file_len)? {
+        Some(path) => path,
+        None => return Ok(None)
+    };
+    let is_folder = live_file_metadata.is_dir();
+
+    let backup_dest_path = backed_up_file_path.parent().unwrap().to_path_buf();
+    let backup_folder = strip_date_folder(&backed_up_file_path).parent().unwrap().to_path_buf();
+    if settings.dedup_identical && !is_folder
+        && newest_version_is_identical(&settings, &backup_folder, &live_file_path, &live_file_metadata)?
+    {
+        return Ok(None);
+    }
+    // The date folder of the dated layout as well, if needed
+    if let Err(err) = std::fs::create_dir_all(backup_dest_path.clone()) {
         if err.kind() != ErrorKind::AlreadyExists {
             return Err(FError(vec![format!("Error copying file: {}", err)]));
         }
     }
     let live_filename = live_file_path.file_name_str();
-    let temp_backup_filename = "_".to_string() + live_filename;
+    let temp_backup_file_path = get_temp_file_path(&settings, &backup_dest_path, live_filename)?;
 
-    let temp_backup_file_path = backup_dest_path.join(temp_backup_filename);
-
-    if let Err(err) = std::fs::copy(live_file_path.clone(), temp_backup_file_path.clone()) {
+    if is_folder {
+        write_folder_backup(&settings, &live_file_path, &backup_dest_path, &temp_backup_file_path)?;
+    } else if settings.encrypt {
+        write_encrypted_copy(&settings, &live_file_path, &temp_backup_file_path)?;
+    } else if let Err(err) = copy_live_file(&settings, &live_file_path, &temp_backup_file_path) {
         return Err(FError(vec![format!("Error copying file: {}", err)]));
     }
 
-    let (live_file_metadata, _live_file_modified) = match get_file_metadata(&live_file_path) {
-        Ok((metadata, modified)) => (metadata, modified),
-        Err(err) => {
-            return Err(FError(vec![format!("{}", err)]));
-        }
-    };
-    let live_file_modified_filetime = FileTime::from_last_modification_time(&live_file_metadata);
-    if let Err(err) = set_file_mtime(temp_backup_file_path.clone(), live_file_modified_filetime) {
+    let live_file_modified_filetime = FileTime::from_system_time(live_file_modified);
+    if let Err(err) = retry_transient_errors(
+        &format!("Setting the timestamp of {}", temp_backup_file_path.str()),
+        || set_file_mtime(&temp_backup_file_path, live_file_modified_filetime)
+    ) {
         return Err(FError(vec![
             format!("Error setting backup timestamp for {}: {}", temp_backup_file_path.str(), err)
         ]));
     }
 
-    let next_version = next_backup_version(&settings, backup_dest_path.clone(), live_filename.to_string())?;
-    let backed_up_filename = format!("{}.{}", live_filename, next_version);
-    let backed_up_file_path = backup_dest_path.join(backed_up_filename);
+    if settings.preserve_permissions && !is_folder {
+        if let Err(err) = copy_file_permissions(&live_file_metadata, &temp_backup_file_path) {
+            return Err(FError(vec![
+                format!("Error setting backup permissions for {}: {}", temp_backup_file_path.str(), err)
+            ]));
+        }
+    }
+
+    let backed_up_file_path = match claim_backup_target(&settings, backed_up_file_path, live_filename) {
+        Ok(backed_up_file_path) => backed_up_file_path,
+        Err(err) => {
+            if let Err(remove_err) = std::fs::remove_file(&temp_backup_file_path) {
+                warn!("Error removing temp file {}: {}", temp_backup_file_path.str(), remove_err);
+            }
+            return Err(err);
+        }
+    };
 
     info!("Copying {} to {}", live_file_path.str(), backed_up_file_path.str());
 
-    if let Err(err) = std::fs::rename(temp_backup_file_path, backed_up_file_path) {
+    if let Err(err) = retry_transient_errors(
+        &format!("Moving {} to {}", temp_backup_file_path.str(), backed_up_file_path.str()),
+        || move_file(&temp_backup_file_path, &backed_up_file_path)
+    ) {
         return Err(FError(vec![format!("{}", err)]));
     }
+    // The backup itself succeeded, so a manifest that cannot be updated only makes the count of all versions low
+    let dest_path = get_dest_path_for_backed_up_file(&settings, &backed_up_file_path);
+    if let Err(err) = record_backup(dest_path, &backed_up_file_path) {
+        warn!("Error counting the backup in the manifest: {}", err);
+    }
+    if settings.write_sidecars {
+        let metadata = BackupMetadata {
+            original_path: live_file_path.clone(),
+            size: live_file_len,
+            modified: live_file_modified
+        };
+        // Without the sidecar a restore derives the live file from the backup patterns, as for older backups
+        if let Err(err) = write_sidecar(&backed_up_file_path, &metadata) {
+            warn!("{}", err);
+        }
+    }
 
-    Ok(())
+    Ok(Some(backed_up_file_path))
 }
 
-/// Determines the version number for the next backup of `backup_filename` in `backed_up_folder`
-fn next_backup_version(_settings: &Settings, backed_up_folder: PathBuf, backup_filename: String) -> Result<u32, FileError> {
-    let backed_up_versions_pattern = backed_up_folder
-        .join(backup_filename + ".*");
-    let backed_up_history_files = match glob(backed_up_versions_pattern.str()) {
-        Ok(history_paths) => Some(history_paths),
-        Err(err) => {
-            return Err(FError(
-                vec![format!("Error scanning backed up files for {}: {}", backed_up_versions_pattern.str(), err)]
-            ));
-        }
-    };
-    let oldest_backed_up_version = match backed_up_history_files {
-        Some(history_paths) => {
-            let mut history_number = 0u32;
-            for history_path in history_paths {
-                match history_path {
-                    Ok(history_path) => {
-                        let history_path_number = match get_backed_up_version_number(&history_path) {
-                            None =>
-                                0u32,
-                            Some(history_path_number) =>
-                                history_path_number
-                        };
-                        if history_path_number > history_number {
-                            history_number = history_path_number;
-                        }
-                    }
-                    Err(err) => {
-                        warn!("Error reading backed up history file: {}", err);
-                        continue;
-                    }
-                }
-            }
-            history_number
-        }
-        None =>
-            0u32
-    };
-    Ok(oldest_backed_up_version + 1)
+/// Archives the live folder `live_folder_path` to `to_path`, encrypting the archive when `settings` say so
+fn write_folder_backup(
+    settings: &Settings, live_folder_path: &PathBuf, backup_dest_path: &Path, to_path: &Path
+) -> Result<(), FileError> {
+    if !settings.encrypt {
+        return write_folder_archive(live_folder_path, to_path);
+    }
+    let archive_filename = format!("{}.tar", live_folder_path.file_name_str());
+    let temp_archive_path = get_temp_file_path(settings, backup_dest_path, &archive_filename)?;
+    write_folder_archive(live_folder_path, &temp_archive_path)?;
+    let result = write_encrypted_copy(settings, &temp_archive_path, to_path);
+    if let Err(err) = std::fs::remove_file(&temp_archive_path) {
+        warn!("Error removing temp file {}: {}", temp_archive_path.str(), err);
+    }
+    result
 }
 
-/// Parses `backed_up_file_path` and returns its version number
-pub fn get_backed_up_version_number(backed_up_file_path: &PathBuf) -> Option<u32> {
-    let backed_up_filename = backed_up_file_path.file_name_str();
-    match backed_up_filename.rfind(".") {
-        None => None,
-        Some(dot_index) => {
-            let backed_up_filename_suffix = &backed_up_filename[dot_index + 1..];
-            match backed_up_filename_suffix.parse::<u32>() {
-                Err(_) => None,
-                Ok(version_number) => Some(version_number)
-            }
+/// Copies `live_file_path` to `to_path`, falling back to the platform's reader for locked files when the game still
+/// holds the file open after retrying and `settings` allow it
+fn copy_live_file(settings: &Settings, live_file_path: &Path, to_path: &Path) -> std::io::Result<u64> {
+    let result = retry_transient_errors(
+        &format!("Copying {}", live_file_path.str()),
+        || std::fs::copy(live_file_path, to_path));
+    match (result, get_locked_file_reader()) {
+        (Err(err), Some(locked_file_reader)) if settings.copy_locked_files && is_locked_file_error(&err) => {
+            info!("{} is locked, copying it while it is open: {}", live_file_path.str(), err);
+            locked_file_reader.copy_locked(live_file_path, to_path)
         }
+        (result, _) =>
+            result
     }
 }
 
-/// Parses `backed_up_file_path` and returns it without any version suffix
-pub fn get_backed_up_path(backed_up_file_path: &PathBuf) -> Option<&str> {
-    let backed_up_filename = backed_up_file_path.str();
-    match backed_up_filename.rfind(".") {
-        None => None,
-        Some(dot_index) => Some(&backed_up_filename[..dot_index])
+/// Reads `live_file_path`, falling back to the platform's reader for locked files like `copy_live_file` does
+fn read_live_file(settings: &Settings, live_file_path: &Path) -> std::io::Result<Vec<u8>> {
+    let result = retry_transient_errors(
+        &format!("Reading {}", live_file_path.str()),
+        || std::fs::read(live_file_path));
+    match (result, get_locked_file_reader()) {
+        (Err(err), Some(locked_file_reader)) if settings.copy_locked_files && is_locked_file_error(&err) => {
+            info!("{} is locked, reading it while it is open: {}", live_file_path.str(), err);
+            locked_file_reader.read_locked(live_file_path)
+        }
+        (result, _) =>
+            result
     }
 }
 
-/// Parses `backed_up_file_path` and returns it without any version suffix
-pub fn strip_version_suffix_from_backed_up_file_path(backed_up_file_path: &PathBuf) -> Option<PathBuf> {
-    let backed_up_file_path_str = backed_up_file_path.str();
-    match backed_up_file_path_str.rfind(".") {
-        None => None,
-        Some(dot_index) => Some(PathBuf::from(&backed_up_file_path_str[..dot_index]))
+/// Runs `operation` until it succeeds, retrying with exponential backoff while it fails with an error that may only be
+/// temporary, such as the game still holding the file open
+fn retry_transient_errors<T>(
+    description: &str, mut operation: impl FnMut() -> std::io::Result<T>
+) -> std::io::Result<T> {
+    let mut backoff = TRANSIENT_ERROR_INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match operation() {
+            Err(err) if attempt < TRANSIENT_ERROR_ATTEMPTS && is_transient_error(&err) => {
+                warn!("{} failed on attempt {}, retrying in {}ms: {}", description, attempt, backoff.as_millis(), err);
+                std::thread::sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            }
+            result =>
+                return result
+        }
     }
 }
 
-/// Check if there exists more backed up files than is allowed by `settings` and, if there are too many, deletes the
-/// oldest backed up file until the number of files complies with the maximum specified by `settings`
-pub fn delete_old_backups(settings: Settings) -> Result<(), FileError> {
-    let mut backed_up_file_paths_by_stripped_file_paths = MultiMap::new();
+fn is_transient_error(err: &std::io::Error) -> bool {
+    #[cfg(unix)]
+    const TRANSIENT_ERRORS: [i32; 1] = [16];  // EBUSY
+    #[cfg(windows)]
+    const TRANSIENT_ERRORS: [i32; 3] = [5, 32, 33];  // ERROR_ACCESS_DENIED, ERROR_SHARING_VIOLATION, ERROR_LOCK_VIOLATION
+    err.kind() == ErrorKind::Interrupted
+        || err.raw_os_error().map_or(false, |code| TRANSIENT_ERRORS.contains(&code))
+}
 
-    let backed_up_file_paths = get_backed_up_files(settings.clone())?;
-    for backed_up_file_path in backed_up_file_paths {
-        let stripped_backed_up_file_path = match strip_version_suffix_from_backed_up_file_path(&backed_up_file_path) {
+/// Checks whether the content of the newest version of `live_file_path` backed up to `backup_folder` is identical to
+/// the live file. If so, the version takes on the live file's timestamp, so that the live file is then considered backed
+/// up instead of being backed up again.
+fn newest_version_is_identical(
+    settings: &Settings, backup_folder: &Path, live_file_path: &PathBuf, live_file_metadata: &Metadata
+) -> Result<bool, FileError> {
+    let newest_version_path =
+        match find_identical_newest_version(settings, backup_folder, live_file_path, live_file_metadata)? {
             Some(path) => path,
-            None => {
-                return Err(FWarning(vec![format!("Unable to find version suffix in {}", backed_up_file_path.str())]));
-            }
+            None => return Ok(false)
         };
-        backed_up_file_paths_by_stripped_file_paths.insert(stripped_backed_up_file_path.str().to_string(), backed_up_file_path);
+    info!("{} is identical to {}, not backing it up again", live_file_path.str(), newest_version_path.str());
+    let live_file_modified_filetime = FileTime::from_last_modification_time(live_file_metadata);
+    if let Err(err) = set_file_mtime(&newest_version_path, live_file_modified_filetime) {
+        return Err(FError(vec![
+            format!("Error setting backup timestamp for {}: {}", newest_version_path.str(), err)
+        ]));
     }
+    Ok(true)
+}
 
-    for (_stripped_path, mut backed_up_paths) in backed_up_file_paths_by_stripped_file_paths {
-        if backed_up_paths.len() > settings.backup_count as usize {
-            backed_up_paths.sort_by(|a, b| {
-                let a_num = get_backed_up_version_number(a).unwrap();
-                let b_num = get_backed_up_version_number(b).unwrap();
-                a_num.cmp(&b_num)
-            });
-            let doomed_paths = &backed_up_paths[..backed_up_paths.len() - settings.backup_count as usize];
-            doomed_paths.iter()
-                .for_each(|path| {
-                    info!("Removing {}", path.str());
-                    if let Err(err) = std::fs::remove_file(path) {
-                        error!("Error removing file {}: {}", path.str(), err);
-                    }
-                });
-        }
+/// Finds the newest version of `live_file_path` in `backup_folder`, if it has the same content as the live file
+fn find_identical_newest_version(
+    settings: &Settings, backup_folder: &Path, live_file_path: &PathBuf, live_file_metadata: &Metadata
+) -> Result<Option<PathBuf>, FileError> {
+    let newest_version_path =
+        match find_newest_version_in_backup_folder(settings, backup_folder, live_file_path.file_name_str())? {
+            Some((_version, path)) => path,
+            None => return Ok(None)
+        };
+    let (newest_version_metadata, _newest_version_modified) = get_file_metadata(&newest_version_path)?;
+    if get_backed_up_content_len(&newest_version_path, &newest_version_metadata) != live_file_metadata.len()
+        || !backed_up_file_has_same_content(&newest_version_path, live_file_path)?
+    {
+        return Ok(None);
     }
-    Ok(())
+    Ok(Some(newest_version_path))
 }
 
-/// Deletes each file found in `backed_up_file_paths`
-pub fn delete_backed_up_files(backed_up_file_paths: Vec<PathBuf>) -> Result<(), FileError> {
-    let mut errs = Vec::new();
-    for backed_up_path in backed_up_file_paths {
-        info!("Deleting backed up file {}", backed_up_path.str());
-        if let Err(err) = std::fs::remove_file(backed_up_path.clone()) {
-            errs.push(format!("Error deleting file {}: {}", backed_up_path.str(), err));
-        }
+/// Determines the path of the next backed up version of `live_file_path` without writing anything. Returns `None` if
+/// the file would be skipped.
+fn get_backup_target(
+    settings: &Settings, live_file_path: &PathBuf, live_file_len: u64
+) -> Result<Option<PathBuf>, FileError> {
+    if live_file_len == 0 && !settings.backup_empty_files {
+        // Most likely a file caught in the middle of being written
+        debug!("Skipping empty file {}", live_file_path.str());
+        return Ok(None);
     }
-    if errs.is_empty() {
-        Ok(())
-    } else {
-        Err(FError(errs))
+    if let Some(max_backup_file_size_mb) = settings.max_backup_file_size_mb {
+        if live_file_len > max_backup_file_size_mb.saturating_mul(1000 * 1000) {
+            let live_file_size_mb = live_file_len / (1000 * 1000);
+            return Err(FWarning(vec![format!("Skipping {}: {}mb exceeds the maximum backup file size of {}mb",
+                live_file_path.str(), live_file_size_mb, max_backup_file_size_mb)]));
+        }
     }
-}
-
-/// Restores each file found in `backed_up_file_paths`
-pub fn restore_backed_up_files(settings: Settings, backed_up_file_paths: Vec<PathBuf>) -> Result<(), FileError>{
-    for backed_up_path in backed_up_file_paths {
-        let backed_up_folder_path = backed_up_path.parent().unwrap();
 
-        let source_file_path = get_live_file_for_backed_up_file(settings.clone(), backed_up_path.clone())?;
-        let source_filename = source_file_path.file_name_str();
+    let backup_pattern = match find_backup_pattern_for_live_file(settings, live_file_path) {
+        Some(pattern) => pattern,
+        None =>
+            return Err(FWarning(vec![format!("Cannot find backup configuration for file {}", live_file_path.str())]))
+    };
+    let backup_folder = get_backup_folder(settings, backup_pattern);
+    let live_filename = live_file_path.file_name_str();
+    let next_version = next_backup_version(settings, backup_folder, live_filename.to_string())?;
+    let backed_up_filename = get_versioned_filename(settings, live_filename, &next_version);
+    Ok(Some(get_new_backup_folder(settings, backup_pattern).join(backed_up_filename)))
+}
 
-        let temp_source_filename = "_".to_string() + source_filename;
-        let temp_source_file_path = backed_up_folder_path.join(temp_source_filename);
+/// What backing up all live files would do
+pub struct BackupDryRun {
+    /// The backed up file that would be created for each live file without a backup
+    pub backed_up_file_paths: Vec<PathBuf>,
+    pub up_to_date_count: usize,
+    pub skipped_count: usize,
+    pub errs: Vec<String>,
+}
 
-        let (backed_up_file_metadata, _backup_file_modified) = match get_file_metadata(&backed_up_path) {
-            Ok((metadata, modified)) => (metadata, modified),
-            Err(err) => {
-                error!("{}: {}", backed_up_path.str(), err);
+/// Determines which live files `backup_all_changed_files` would back up, and where to, without writing anything
+pub fn dry_run_backup(settings: Settings) -> Result<BackupDryRun, FileError> {
+    let mut dry_run = BackupDryRun {
+        backed_up_file_paths: vec![],
+        up_to_date_count: 0,
+        skipped_count: 0,
+        errs: vec![]
+    };
+    for live_file_path in get_live_files(settings.clone())? {
+        let backup_target = match find_backup_for_live_file(settings.clone(), live_file_path.clone()) {
+            Ok(true) => {
+                dry_run.up_to_date_count += 1;
                 continue;
             }
+            Ok(false) =>
+                get_live_file_fingerprint(&live_file_path)
+                    .and_then(|(len, _modified)| get_backup_target(&settings, &live_file_path, len)),
+            Err(err) =>
+                Err(err)
         };
-        let backed_up_file_modified_filetime = FileTime::from_last_modification_time(&backed_up_file_metadata);
-
-        if let Err(err) = std::fs::copy(backed_up_path.clone(), temp_source_file_path.clone()) {
-            error!("Error copying file from {} to {}: {}",
-                backed_up_path.str(), temp_source_file_path.str(), err);
-            continue;
+        let backup_target = match backup_target {
+            // Only given the live file's timestamp rather than backed up again
+            Ok(Some(backed_up_file_path)) if settings.dedup_identical => {
+                let backup_folder = strip_date_folder(&backed_up_file_path).parent().unwrap().to_path_buf();
+                let identical_path = get_file_metadata(&live_file_path).and_then(|(metadata, _modified)| {
+                    if metadata.is_dir() {
+                        Ok(None)
+                    } else {
+                        find_identical_newest_version(&settings, &backup_folder, &live_file_path, &metadata)
+                    }
+                });
+                match identical_path {
+                    Ok(Some(_identical_path)) => {
+                        dry_run.up_to_date_count += 1;
+                        continue;
+                    }
+                    Ok(None) => Ok(Some(backed_up_file_path)),
+                    Err(err) => Err(err)
+                }
+            }
+            backup_target =>
+                backup_target
+        };
+        match backup_target {
+            Ok(Some(backed_up_file_path)) =>
+                dry_run.backed_up_file_paths.push(backed_up_file_path),
+            Ok(None) =>
+                dry_run.skipped_count += 1,
+            Err(FWarning(mut file_errs)) | Err(FError(mut file_errs)) | Err(FFatal(mut file_errs)) =>
+                dry_run.errs.append(&mut file_errs)
+        }
+    }
+    Ok(dry_run)
+}
+
+/// Determines where the intermediate copy of `filename` is written, as specified by `settings`, before it is moved
+/// into place. `backed_up_folder` is the backup folder of the file being backed up or restored.
+fn get_temp_file_path(settings: &Settings, backed_up_folder: &Path, filename: &str) -> Result<PathBuf, FileError> {
+    let temp_folder = match settings.temp_file_location {
+        TempFileLocation::BackupFolder =>
+            backed_up_folder.to_path_buf(),
+        TempFileLocation::SystemTemp =>
+            std::env::temp_dir().join("valbak"),
+        TempFileLocation::BackupDestTemp =>
+            get_dest_path_for_backed_up_file(settings, backed_up_folder).join(TEMP_FOLDER_NAME)
+    };
+    if let Err(err) = std::fs::create_dir_all(&temp_folder) {
+        return Err(FError(vec![format!("Error creating temp folder {}: {}", temp_folder.str(), err)]));
+    }
+    let temp_file_path = temp_folder.join(get_temp_filename(filename));
+    TEMP_FILES_IN_PROGRESS.lock().push(temp_file_path.clone());
+    Ok(temp_file_path)
+}
+
+/// A name for an intermediate copy of `filename` that no other copy uses, also across processes, and that does not end
+/// in a version suffix, e.g. `_world.db.1234-0.tmp`
+fn get_temp_filename(filename: &str) -> String {
+    format!("{}{}.{}-{}.tmp",
+        TEMP_FILE_PREFIX, filename, std::process::id(), NEXT_TEMP_FILE_ID.fetch_add(1, Ordering::SeqCst))
+}
+
+/// Whether `path` is one of the intermediate copies written before being moved into place, or lies in a folder of the
+/// backup destination that holds something other than backed up files
+pub fn is_internal_dest_path(settings: &Settings, path: &Path) -> bool {
+    let in_internal_folder = match path.strip_prefix(get_dest_path_for_backed_up_file(settings, path)) {
+        Ok(relative_path) => relative_path.components().next().map_or(false, |component| {
+            component.as_os_str() == TEMP_FOLDER_NAME || component.as_os_str() == KNOWN_GOOD_FOLDER_NAME
+                || component.as_os_str() == TRASH_FOLDER_NAME
+        }),
+        Err(_) => false
+    };
+    in_internal_folder || path.file_name().map_or(false, |filename| {
+        filename.to_string_lossy().starts_with(TEMP_FILE_PREFIX)
+    })
+}
+
+/// Applies the permission bits in `from_metadata` to `to_path`. On Unix the owner and group are copied as well when
+/// the process is privileged enough to change them, otherwise a warning is logged and the current ownership is kept.
+fn copy_file_permissions(from_metadata: &Metadata, to_path: &Path) -> std::io::Result<()> {
+    std::fs::set_permissions(to_path, from_metadata.permissions())?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if let Err(err) = std::os::unix::fs::chown(to_path, Some(from_metadata.uid()), Some(from_metadata.gid())) {
+            if err.kind() != ErrorKind::PermissionDenied {
+                return Err(err);
+            }
+            warn!("Not permitted to change ownership of {}, keeping current owner", to_path.str());
+        }
+    }
+    Ok(())
+}
+
+/// Returns `backed_up_file_path`, or the next free version of `live_filename` when that version was taken since it was
+/// chosen, e.g. by a live file of the same name in another source folder of the same name. Moving the new backup into
+/// place would otherwise overwrite the existing backup.
+fn claim_backup_target(
+    settings: &Settings, backed_up_file_path: PathBuf, live_filename: &str
+) -> Result<PathBuf, FileError> {
+    let backed_up_folder = backed_up_file_path.parent().unwrap().to_path_buf();
+    let backup_folder = strip_date_folder(&backed_up_file_path).parent().unwrap().to_path_buf();
+    let mut target_path = backed_up_file_path;
+    for _ in 0..BACKUP_TARGET_ATTEMPTS {
+        if !target_path.exists() {
+            return Ok(target_path);
+        }
+        let next_version = next_backup_version(settings, backup_folder.clone(), live_filename.to_string())?;
+        let next_target_path = backed_up_folder.join(get_versioned_filename(settings, live_filename, &next_version));
+        warn!("{} already exists, backing up to {} instead", target_path.str(), next_target_path.str());
+        target_path = next_target_path;
+    }
+    Err(FError(vec![format!("Not backing up {}: its next versions in {} keep being taken, e.g. by another live file \
+        of the same name", live_filename, backed_up_folder.str())]))
+}
+
+/// Moves `from_path` to `to_path`, falling back to copying and removing when the paths are on different filesystems.
+/// The last-modified timestamp is preserved either way, since it is used to match live files to their backups.
+fn move_file(from_path: &Path, to_path: &Path) -> std::io::Result<()> {
+    move_file_with(from_path, to_path, |from_path, to_path| std::fs::rename(from_path, to_path))
+}
+
+/// `move_file`, renaming with `rename`
+fn move_file_with(
+    from_path: &Path, to_path: &Path, rename: impl FnOnce(&Path, &Path) -> std::io::Result<()>
+) -> std::io::Result<()> {
+    match rename(from_path, to_path) {
+        Err(err) if is_cross_device_error(&err) => {
+            debug!("Cannot rename {} to {} across filesystems, copying instead", from_path.str(), to_path.str());
+            let from_metadata = from_path.metadata()?;
+            std::fs::copy(from_path, to_path)?;
+            set_file_mtime(to_path, FileTime::from_last_modification_time(&from_metadata))?;
+            std::fs::remove_file(from_path)
+        }
+        result =>
+            result
+    }
+}
+
+fn is_cross_device_error(err: &std::io::Error) -> bool {
+    #[cfg(unix)]
+    const CROSS_DEVICE_ERROR: i32 = 18;  // EXDEV
+    #[cfg(windows)]
+    const CROSS_DEVICE_ERROR: i32 = 17;  // ERROR_NOT_SAME_DEVICE
+    err.raw_os_error() == Some(CROSS_DEVICE_ERROR)
+}
+
+/// Finds the newest version of `backup_filename` backed up to `backup_folder`, in either layout, if there is one
+fn find_newest_version_in_backup_folder(
+    settings: &Settings, backup_folder: &Path, backup_filename: &str
+) -> Result<Option<(BackupVersion, PathBuf)>, FileError> {
+    let mut newest_version: Option<(BackupVersion, PathBuf)> = None;
+    for backed_up_folder_pattern in get_backed_up_folder_patterns(settings, backup_folder) {
+        if let Some((version, path)) = find_newest_backed_up_version(&backed_up_folder_pattern, backup_filename)? {
+            let is_newer = newest_version.as_ref().map_or(true, |(newest, newest_path)| {
+                compare_versions(&version, &path, newest, newest_path) == std::cmp::Ordering::Greater
+            });
+            if is_newer {
+                newest_version = Some((version, path));
+            }
+        }
+    }
+    Ok(newest_version)
+}
+
+/// Finds the newest backed up version of `backup_filename` in `backed_up_folder`, which may be a glob pattern matching
+/// several folders, if there is one
+fn find_newest_backed_up_version(
+    backed_up_folder: &Path, backup_filename: &str
+) -> Result<Option<(BackupVersion, PathBuf)>, FileError> {
+    let backed_up_versions_pattern = backed_up_folder
+        .join(backup_filename.to_string() + &get_versions_glob_suffix());
+    let history_paths = match glob(backed_up_versions_pattern.str()) {
+        Ok(history_paths) => history_paths,
+        Err(err) => {
+            return Err(FError(
+                vec![format!("Error scanning backed up files for {}: {}", backed_up_versions_pattern.str(), err)]
+            ));
+        }
+    };
+    let mut newest_version: Option<(BackupVersion, PathBuf)> = None;
+    for history_path in history_paths {
+        match history_path {
+            Ok(history_path) => {
+                if let Some(history_version) = get_backed_up_version(&history_path) {
+                    let is_newer = match &newest_version {
+                        None => true,
+                        Some((newest, newest_path)) =>
+                            compare_versions(&history_version, &history_path, newest, newest_path)
+                                == std::cmp::Ordering::Greater
+                    };
+                    if is_newer {
+                        newest_version = Some((history_version, history_path));
+                    }
+                }
+            }
+            Err(err) => {
+                warn!("Error reading backed up history file: {}", err);
+                continue;
+            }
+        }
+    }
+    Ok(newest_version)
+}
+
+/// Determines the version for the next backup of `backup_filename` to `backed_up_folder`, as specified by `settings`
+fn next_backup_version(
+    settings: &Settings, backed_up_folder: PathBuf, backup_filename: String
+) -> Result<BackupVersion, FileError> {
+    let newest_version = find_newest_version_in_backup_folder(settings, &backed_up_folder, &backup_filename)?
+        .map(|(version, _path)| version);
+    let next_version = match settings.version_scheme {
+        VersionScheme::Counter => {
+            let newest_counter = match newest_version {
+                Some(BackupVersion::Counter(counter)) => counter,
+                _ => 0u32
+            };
+            // Counters are never reused, even once all versions with higher counters were pruned or deleted
+            let backup_dest_path = get_dest_path_for_backed_up_file(settings, &backed_up_folder);
+            let last_counter = match read_manifest(backup_dest_path) {
+                Ok(manifest) => manifest.get_entry(backup_dest_path, &backed_up_folder.join(&backup_filename))
+                    .and_then(|entry| entry.last_counter)
+                    .unwrap_or(0),
+                Err(err) => {
+                    warn!("Error reading the last version of {} from the manifest: {}", backup_filename, err);
+                    0
+                }
+            };
+            BackupVersion::Counter(newest_counter.max(last_counter) + 1)
+        }
+        VersionScheme::Timestamp => {
+            // Truncated to whole seconds, as that is all the file name holds
+            let now = Local::now().naive_local().with_nanosecond(0).unwrap();
+            match newest_version {
+                // Keep versions unique and ordered when backing up more than once a second or when the clock goes back
+                Some(BackupVersion::Timestamp(newest_timestamp)) if newest_timestamp >= now =>
+                    BackupVersion::Timestamp(newest_timestamp + chrono::Duration::seconds(1)),
+                _ =>
+                    BackupVersion::Timestamp(now)
+            }
+        }
+    };
+    Ok(next_version)
+}
+
+/// The name of `version` of the live file `live_filename`, e.g. `world.db.3`, with the version separator of `settings`
+fn get_versioned_filename(settings: &Settings, live_filename: &str, version: &BackupVersion) -> String {
+    format!("{}{}{}", live_filename, settings.version_separator, version)
+}
+
+/// Appended to a live file name or pattern to glob its backed up versions, with any of the version separators
+fn get_versions_glob_suffix() -> String {
+    format!("[{}]*", VERSION_SEPARATORS.iter().collect::<String>())
+}
+
+/// Splits the file name of `backed_up_file_path` at its last version separator into the name of the live file and the
+/// version, or returns `None` when what follows the last separator is not a version. Only the last separator counts,
+/// since live file names may contain them themselves, e.g. `my.save.file.7` is version 7 of `my.save.file`.
+fn split_version_suffix(backed_up_file_path: &Path) -> Option<(&str, BackupVersion)> {
+    let backed_up_filename = backed_up_file_path.file_name_str();
+    // All separators are ASCII, so the suffix starts right after the separator's byte
+    let separator_index = backed_up_filename.rfind(&VERSION_SEPARATORS[..])?;
+    let (live_filename, backed_up_filename_suffix) =
+        (&backed_up_filename[..separator_index], &backed_up_filename[separator_index + 1..]);
+    if live_filename.is_empty() {
+        return None;
+    }
+    // Only digits, as `parse` also accepts a leading `+`
+    if !backed_up_filename_suffix.is_empty() && backed_up_filename_suffix.bytes().all(|b| b.is_ascii_digit()) {
+        return backed_up_filename_suffix.parse::<u32>().ok()
+            .map(|counter| (live_filename, BackupVersion::Counter(counter)));
+    }
+    match NaiveDateTime::parse_from_str(backed_up_filename_suffix, VERSION_TIMESTAMP_FORMAT) {
+        Err(_) => None,
+        Ok(timestamp) => Some((live_filename, BackupVersion::Timestamp(timestamp)))
+    }
+}
+
+/// Renames the backed up files in the backup destination of `settings` whose version separator is not the one of
+/// `settings`, e.g. `world.db.3` to `world.db@3` after changing it to `@`, together with their sidecars and notes.
+/// Copies already in the mirror destination keep their names. Returns how many backed up files were renamed, along with
+/// any errors, since the others are renamed regardless.
+pub fn rename_backups_to_version_separator(settings: &Settings) -> (usize, Option<FileError>) {
+    let (backed_up_file_paths, scan_err) = get_backed_up_files(settings.clone());
+    let mut errs = match scan_err {
+        Some(FWarning(errs)) | Some(FError(errs)) | Some(FFatal(errs)) => errs,
+        None => Vec::new()
+    };
+    let mut renamed_count = 0;
+    for backed_up_file_path in backed_up_file_paths {
+        let (live_filename, version) = match split_version_suffix(&backed_up_file_path) {
+            Some(split) => split,
+            None => continue
+        };
+        let renamed_file_path = backed_up_file_path.with_file_name(
+            get_versioned_filename(settings, live_filename, &version));
+        if renamed_file_path == backed_up_file_path {
+            continue;
+        }
+        if renamed_file_path.exists() {
+            errs.push(format!("Not renaming {}: {} already exists", backed_up_file_path.str(), renamed_file_path.str()));
+            continue;
+        }
+        if let Err(err) = std::fs::rename(&backed_up_file_path, &renamed_file_path) {
+            errs.push(format!("Error renaming {}: {}", backed_up_file_path.str(), err));
+            continue;
+        }
+        renamed_count += 1;
+        for (from_path, to_path) in [
+            (get_sidecar_path(&backed_up_file_path), get_sidecar_path(&renamed_file_path)),
+            (get_note_path(&backed_up_file_path), get_note_path(&renamed_file_path))
+        ] {
+            if from_path.exists() {
+                if let Err(err) = std::fs::rename(&from_path, &to_path) {
+                    errs.push(format!("Error renaming {}: {}", from_path.str(), err));
+                }
+            }
+        }
+    }
+    if errs.is_empty() {
+        (renamed_count, None)
+    } else {
+        (renamed_count, Some(FError(errs)))
+    }
+}
+
+/// Parses `backed_up_file_path` and returns its version, which orders versions from oldest to newest
+pub fn get_backed_up_version(backed_up_file_path: &PathBuf) -> Option<BackupVersion> {
+    split_version_suffix(backed_up_file_path).map(|(_live_filename, version)| version)
+}
+
+/// Parses `backed_up_file_path` and returns it without its version suffix, or `None` when it has none
+pub fn get_backed_up_path(backed_up_file_path: &PathBuf) -> Option<&str> {
+    let (live_filename, _version) = split_version_suffix(backed_up_file_path)?;
+    let backed_up_file_path_str = backed_up_file_path.str();
+    // The version suffix is at the end of the file name, and so at the end of the path
+    let suffix_len = backed_up_file_path.file_name_str().len() - live_filename.len();
+    Some(&backed_up_file_path_str[..backed_up_file_path_str.len() - suffix_len])
+}
+
+/// Parses `backed_up_file_path` and returns it without its version suffix, and without the date folder it is in with
+/// `BackupLayout::Dated`, so that it is the same for all versions of a file. Returns `None` when it has no version suffix.
+pub fn strip_version_suffix_from_backed_up_file_path(backed_up_file_path: &PathBuf) -> Option<PathBuf> {
+    split_version_suffix(backed_up_file_path)
+        .map(|(live_filename, _version)| strip_date_folder(&backed_up_file_path.with_file_name(live_filename)))
+}
+
+/// Check if there exists more backed up files than is allowed by `settings` and, if there are too many, deletes the
+/// old backed up files that the retention policy of `settings` does not keep, e.g. the oldest ones beyond the maximum
+/// number of files. Returns the number of files deleted.
+pub fn delete_old_backups(settings: Settings, listener: &dyn BackupListener) -> Result<usize, FileError> {
+    listener.push_status("Removing old backups".to_string());
+    let result = delete_old_backups_from_dest(settings.clone(), listener);
+    if let Some(secondary_dest_path) = settings.secondary_dest_path.clone() {
+        // The mirror may be unavailable, which must not keep the primary destination from being pruned
+        if secondary_dest_path.is_dir() {
+            let mirror_settings = get_mirror_settings(&settings, secondary_dest_path.clone());
+            match delete_old_backups_from_dest(mirror_settings, listener) {
+                Ok(count) if count > 0 => info!("Removed {} old backups from mirror folder", count),
+                Ok(_) => {}
+                Err(err) => warn!("Error removing old backups from mirror folder {}: {}", secondary_dest_path.str(), err)
+            }
+        }
+    }
+    listener.pop_status();
+    result
+}
+
+/// `settings` with the mirror folder `secondary_dest_path` as the destination. The backup patterns with a destination
+/// of their own are left out, since their backups are not mirrored.
+fn get_mirror_settings(settings: &Settings, secondary_dest_path: PathBuf) -> Settings {
+    let backup_patterns = settings.backup_patterns.iter()
+        .filter(|backup_pattern| backup_pattern.dest_override.is_none())
+        .cloned()
+        .collect();
+    Settings { backup_dest_path: secondary_dest_path, backup_patterns, ..settings.clone() }
+}
+
+fn delete_old_backups_from_dest(settings: Settings, listener: &dyn BackupListener) -> Result<usize, FileError> {
+    let mut deleted_count = 0;
+    let doomed_paths = find_backups_outside_retention(settings.clone())?;
+    // Removing each can take a while on a network drive. Replaces the status of `delete_old_backups`.
+    for (doomed_index, path) in doomed_paths.iter().enumerate() {
+        listener.set_status(format!("Removing old backups {}/{}", doomed_index + 1, doomed_paths.len()));
+        info!("Removing {}", path.str());
+        match discard_backed_up_file(&settings, path) {
+            Ok(()) => deleted_count += 1,
+            Err(err) => error!("Error removing file {}: {}", path.str(), err)
+        }
+    }
+
+    for dest_path in settings.dest_paths() {
+        match delete_trash(dest_path, Some(settings.trash_retention_days)) {
+            Ok(0) => {}
+            Ok(count) => info!("Deleted {} backups kept in the trash of {} for more than {} days", count,
+                dest_path.str(), settings.trash_retention_days),
+            Err(err) => warn!("{}", err)
+        }
+    }
+
+    if let Some(max_total_size_mb) = settings.max_total_size_mb {
+        deleted_count += delete_oldest_backups_over_total_size(settings, max_total_size_mb)?;
+    }
+    Ok(deleted_count)
+}
+
+/// Deletes the backed up files of `live_file_path` that the retention policy of `settings` does not keep, leaving the
+/// backups of other files alone. Returns the number of files deleted.
+pub fn delete_old_backups_of_live_file(settings: Settings, live_file_path: &PathBuf) -> Result<usize, FileError> {
+    let backup_pattern = match find_backup_pattern_for_live_file(&settings, live_file_path) {
+        Some(backup_pattern) => backup_pattern,
+        None => return Ok(0)
+    };
+    let stripped_file_path = get_backup_folder(&settings, backup_pattern)
+        .join(live_file_path.file_name_str());
+    let mut deleted_count = 0;
+    for path in find_backups_outside_retention(settings.clone())? {
+        if strip_version_suffix_from_backed_up_file_path(&path).as_ref() != Some(&stripped_file_path) {
+            continue;
+        }
+        info!("Removing {}", path.str());
+        match discard_backed_up_file(&settings, &path) {
+            Ok(()) => deleted_count += 1,
+            Err(err) => error!("Error removing file {}: {}", path.str(), err)
+        }
+    }
+    Ok(deleted_count)
+}
+
+/// Finds the backed up files that the retention policy of `settings` does not keep, i.e. the files that pruning would
+/// delete
+fn find_backups_outside_retention(settings: Settings) -> Result<Vec<PathBuf>, FileError> {
+    match settings.retention_policy {
+        RetentionPolicy::KeepCount => {
+            let keep_count = settings.backup_count as usize;
+            find_backups_over_count(settings, keep_count)
+        }
+        RetentionPolicy::Tiered { keep_all_days, daily_days, weekly_weeks } =>
+            find_backups_outside_tiers(settings, keep_all_days, daily_days, weekly_weeks)
+    }
+}
+
+/// Finds the backed up files that are older than the newest `keep_count` versions of their live file, i.e. the files
+/// that pruning to `keep_count` versions would delete
+pub fn find_backups_over_count(settings: Settings, keep_count: usize) -> Result<Vec<PathBuf>, FileError> {
+    let mut doomed_paths = Vec::new();
+    for (_stripped_path, mut backed_up_paths) in group_backed_up_files_by_live_file(settings)? {
+        if backed_up_paths.len() > keep_count {
+            sort_backed_up_versions(&mut backed_up_paths);
+            doomed_paths.extend_from_slice(&backed_up_paths[..backed_up_paths.len() - keep_count]);
+        }
+    }
+    Ok(doomed_paths)
+}
+
+/// Finds the backed up files that the tiered retention policy with the given tiers does not keep. Days and weeks are
+/// counted from the Unix epoch, so that the version kept for a day or week stays the same from one pruning to the next.
+fn find_backups_outside_tiers(
+    settings: Settings, keep_all_days: u32, daily_days: u32, weekly_weeks: Option<u32>
+) -> Result<Vec<PathBuf>, FileError> {
+    const DAY_SECS: u64 = 24 * 60 * 60;
+    const WEEK_SECS: u64 = 7 * DAY_SECS;
+    let keep_all_secs = keep_all_days as u64 * DAY_SECS;
+    let daily_secs = keep_all_secs + daily_days as u64 * DAY_SECS;
+    let weekly_secs = weekly_weeks.map(|weekly_weeks| daily_secs + weekly_weeks as u64 * WEEK_SECS);
+    let now = SystemTime::now();
+
+    let mut doomed_paths = Vec::new();
+    for (_stripped_path, backed_up_paths) in group_backed_up_files_by_live_file(settings)? {
+        let mut dated_paths = Vec::new();
+        for backed_up_path in backed_up_paths {
+            let modified = match get_file_metadata(&backed_up_path) {
+                Ok((_metadata, modified)) => modified,
+                Err(err) => {
+                    // E.g. removed since it was listed. Left out as if it were not there, which at worst keeps an
+                    // older version that is no longer needed.
+                    warn!("Not pruning {}: {}", backed_up_path.str(), err);
+                    continue;
+                }
+            };
+            dated_paths.push((modified, backed_up_path));
+        }
+        // Newest first, so that the first version seen in a day or week is the one kept
+        dated_paths.sort_by(|(a_modified, _a_path), (b_modified, _b_path)| b_modified.cmp(a_modified));
+        let mut kept_days = HashSet::new();
+        let mut kept_weeks = HashSet::new();
+        for (i, (modified, backed_up_path)) in dated_paths.into_iter().enumerate() {
+            // Versions from the future count as the newest
+            let age_secs = now.duration_since(modified).map_or(0, |age| age.as_secs());
+            let epoch_secs = modified.duration_since(UNIX_EPOCH).map_or(0, |since_epoch| since_epoch.as_secs());
+            let keep = if i == 0 || age_secs < keep_all_secs {
+                true
+            } else if age_secs < daily_secs {
+                kept_days.insert(epoch_secs / DAY_SECS)
+            } else if weekly_secs.map_or(true, |weekly_secs| age_secs < weekly_secs) {
+                kept_weeks.insert(epoch_secs / WEEK_SECS)
+            } else {
+                false
+            };
+            if !keep {
+                doomed_paths.push(backed_up_path);
+            }
+        }
+    }
+    Ok(doomed_paths)
+}
+
+/// Groups the backed up files of `settings` by the path they have without a version suffix, i.e. by live file
+fn group_backed_up_files_by_live_file(settings: Settings) -> Result<MultiMap<String, PathBuf>, FileError> {
+    let mut backed_up_file_paths_by_stripped_file_paths = MultiMap::new();
+
+    let backed_up_file_paths = match get_backed_up_files(settings.clone()) {
+        (backed_up_file_paths, None) => backed_up_file_paths,
+        // Pruning an incomplete list could remove versions that are not actually the oldest
+        (_backed_up_file_paths, Some(scan_err)) => return Err(scan_err)
+    };
+    for backed_up_file_path in backed_up_file_paths {
+        let stripped_backed_up_file_path = match (
+            strip_version_suffix_from_backed_up_file_path(&backed_up_file_path),
+            get_backed_up_version(&backed_up_file_path)
+        ) {
+            (Some(path), Some(_version)) => path,
+            _ => {
+                // A stray file should not stop the other files from being pruned
+                warn!("Unable to find version suffix in {}, not pruning it", backed_up_file_path.str());
+                continue;
+            }
+        };
+        backed_up_file_paths_by_stripped_file_paths.insert(stripped_backed_up_file_path.str().to_string(), backed_up_file_path);
+    }
+    Ok(backed_up_file_paths_by_stripped_file_paths)
+}
+
+/// Deletes the oldest backed up files, by timestamp across all files, until all backed up files together take up no
+/// more than `max_total_size_mb`. The trash of the destination folders counts towards the total as well and is deleted
+/// first, oldest day first. The newest version of each file is always kept. Returns the number of backed up files
+/// deleted, not counting those in the trash.
+fn delete_oldest_backups_over_total_size(settings: Settings, max_total_size_mb: u64) -> Result<usize, FileError> {
+    let (backed_up_file_paths, scan_err) = get_backed_up_files(settings.clone());
+    if let Some(scan_err) = scan_err {
+        return Err(scan_err);
+    }
+    let (newest_backed_up_file_paths, _scan_err) = get_newest_backed_up_files(settings.clone());
+
+    let mut total_size = 0u64;
+    let mut size_errs = Vec::new();
+    let mut trash_day_folders = Vec::new();
+    for dest_path in settings.dest_paths() {
+        let trash_folder = dest_path.join(TRASH_FOLDER_NAME);
+        if !trash_folder.is_dir() {
+            continue;
+        }
+        for trash_day_folder in read_dir_paths(&trash_folder, &mut size_errs) {
+            let size = get_trashed_size(&trash_day_folder, &mut size_errs);
+            total_size += size;
+            // Not written by Valbak, so only emptying the trash removes it
+            if let Ok(trash_day) = NaiveDate::parse_from_str(trash_day_folder.file_name_str(), DATE_FOLDER_FORMAT) {
+                trash_day_folders.push((trash_day, size, trash_day_folder));
+            }
+        }
+    }
+    size_errs.iter().for_each(|err_msg| warn!("Not counting towards the maximum total backup size: {}", err_msg));
+    let mut prunable_files = Vec::new();
+    for backed_up_file_path in backed_up_file_paths {
+        let (metadata, modified) = match get_file_metadata(&backed_up_file_path) {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                // E.g. removed since it was listed, so it no longer takes up space either
+                warn!("Not counting {} towards the maximum total backup size: {}", backed_up_file_path.str(), err);
+                continue;
+            }
+        };
+        total_size += metadata.len();
+        // Stray files without a version suffix take up space, but were not written by Valbak
+        if get_backed_up_version(&backed_up_file_path).is_some()
+            && !newest_backed_up_file_paths.contains(&backed_up_file_path) {
+            prunable_files.push((modified, metadata.len(), backed_up_file_path));
+        }
+    }
+    prunable_files.sort();
+    trash_day_folders.sort();
+
+    let max_total_size = max_total_size_mb.saturating_mul(1000 * 1000);
+    for (_trash_day, size, trash_day_folder) in trash_day_folders {
+        if total_size <= max_total_size {
+            break;
+        }
+        info!("Deleting {} from the trash to stay within the maximum total backup size of {}mb",
+            trash_day_folder.str(), max_total_size_mb);
+        let removed = if trash_day_folder.is_dir() {
+            std::fs::remove_dir_all(&trash_day_folder)
+        } else {
+            std::fs::remove_file(&trash_day_folder)
+        };
+        match removed {
+            Ok(()) => total_size -= size,
+            Err(err) => error!("Error deleting {} from the trash: {}", trash_day_folder.str(), err)
+        }
+    }
+    let mut deleted_count = 0;
+    for (_modified, len, path) in prunable_files {
+        if total_size <= max_total_size {
+            break;
+        }
+        info!("Removing {} to stay within the maximum total backup size of {}mb", path.str(), max_total_size_mb);
+        // Moving it to the trash would not free any space
+        match remove_backed_up_file(&path) {
+            Ok(()) => {
+                total_size -= len;
+                deleted_count += 1;
+            }
+            Err(err) => error!("Error removing file {}: {}", path.str(), err)
+        }
+    }
+    if total_size > max_total_size {
+        warn!("Backups use {}mb, more than the maximum total backup size of {}mb, but only the newest versions remain",
+            total_size / (1000 * 1000), max_total_size_mb);
+    }
+    Ok(deleted_count)
+}
+
+/// Returns the newest backed up version of each backed up file, along with any errors scanning the backup destination
+pub fn get_newest_backed_up_files(settings: Settings) -> (Vec<PathBuf>, Option<FileError>) {
+    let (backed_up_file_paths, scan_err) = get_backed_up_files(settings);
+    let mut newest_backed_up_file_paths_by_stripped_file_paths: HashMap<PathBuf, PathBuf> = HashMap::new();
+    for backed_up_file_path in backed_up_file_paths {
+        let stripped_backed_up_file_path = match strip_version_suffix_from_backed_up_file_path(&backed_up_file_path) {
+            Some(path) => path,
+            None => continue
+        };
+        let is_newer = match newest_backed_up_file_paths_by_stripped_file_paths.get(&stripped_backed_up_file_path) {
+            None => true,
+            Some(newest_path) =>
+                compare_backed_up_versions(&backed_up_file_path, newest_path) == std::cmp::Ordering::Greater
+        };
+        if is_newer {
+            newest_backed_up_file_paths_by_stripped_file_paths.insert(stripped_backed_up_file_path, backed_up_file_path);
+        }
+    }
+    let mut newest_backed_up_file_paths: Vec<PathBuf> =
+        newest_backed_up_file_paths_by_stripped_file_paths.into_iter().map(|(_stripped_path, path)| path).collect();
+    newest_backed_up_file_paths.sort();
+    (newest_backed_up_file_paths, scan_err)
+}
+
+/// The folder that the known good copy of `backed_up_file_path` is kept in, in the destination folder that holds it
+fn get_known_good_folder(settings: &Settings, backed_up_file_path: &Path) -> PathBuf {
+    let backed_up_folder_name = backed_up_file_path.parent().unwrap().file_name_str();
+    get_dest_path_for_backed_up_file(settings, backed_up_file_path)
+        .join(KNOWN_GOOD_FOLDER_NAME)
+        .join(backed_up_folder_name)
+}
+
+/// Copies each backed up file to be the known good copy of its file, replacing any previous known good copy. Known good
+/// copies keep the name of the version they were copied from, so that they restore like any other backed up file.
+pub fn mark_known_good(settings: Settings, backed_up_file_paths: Vec<PathBuf>) -> Result<(), FileError> {
+    let _operation_guard = OperationGuard::start();
+    let mut errs = Vec::new();
+    for backed_up_path in backed_up_file_paths {
+        let stripped_backed_up_path = match strip_version_suffix_from_backed_up_file_path(&backed_up_path) {
+            Some(path) => path,
+            None => {
+                errs.push(format!("Invalid backed up file name: {}", backed_up_path.str()));
+                continue;
+            }
+        };
+        let known_good_folder = get_known_good_folder(&settings, &backed_up_path);
+        if let Err(err) = std::fs::create_dir_all(&known_good_folder) {
+            errs.push(format!("Error creating folder {}: {}", known_good_folder.str(), err));
+            continue;
+        }
+        let previous_known_good_path =
+            match find_newest_backed_up_version(&known_good_folder, stripped_backed_up_path.file_name_str()) {
+                Ok(previous) => previous.map(|(_version, path)| path),
+                Err(FWarning(mut file_errs)) | Err(FError(mut file_errs)) | Err(FFatal(mut file_errs)) => {
+                    errs.append(&mut file_errs);
+                    continue;
+                }
+            };
+
+        let (backed_up_file_metadata, _backed_up_file_modified) = match get_file_metadata(&backed_up_path) {
+            Ok(metadata) => metadata,
+            Err(FWarning(mut file_errs)) | Err(FError(mut file_errs)) | Err(FFatal(mut file_errs)) => {
+                errs.append(&mut file_errs);
+                continue;
+            }
+        };
+        let temp_known_good_path =
+            match get_temp_file_path(&settings, &known_good_folder, backed_up_path.file_name_str()) {
+                Ok(path) => path,
+                Err(FWarning(mut file_errs)) | Err(FError(mut file_errs)) | Err(FFatal(mut file_errs)) => {
+                    errs.append(&mut file_errs);
+                    continue;
+                }
+            };
+        if let Err(err) = std::fs::copy(&backed_up_path, &temp_known_good_path) {
+            errs.push(format!("Error copying file from {} to {}: {}",
+                backed_up_path.str(), temp_known_good_path.str(), err));
+            continue;
+        }
+        let backed_up_file_modified_filetime = FileTime::from_last_modification_time(&backed_up_file_metadata);
+        if let Err(err) = set_file_mtime(&temp_known_good_path, backed_up_file_modified_filetime) {
+            errs.push(format!("Error setting timestamp for {}: {}", temp_known_good_path.str(), err));
+            continue;
+        }
+
+        // Only replace the previous known good copy once the new one is complete
+        if let Some(previous_known_good_path) = previous_known_good_path {
+            if let Err(err) = std::fs::remove_file(&previous_known_good_path) {
+                errs.push(format!("Error removing file {}: {}", previous_known_good_path.str(), err));
+                continue;
+            }
+        }
+        let known_good_path = known_good_folder.join(backed_up_path.file_name_str());
+        if let Err(err) = move_file(&temp_known_good_path, &known_good_path) {
+            errs.push(format!("Error moving {} to {}: {}", temp_known_good_path.str(), known_good_path.str(), err));
+            continue;
+        }
+        info!("Marked {} as known good", backed_up_path.str());
+    }
+    if errs.is_empty() {
+        Ok(())
+    } else {
+        Err(FError(errs))
+    }
+}
+
+/// Finds the known good copy of the file that `backed_up_file_path` is a version of, if it has one
+pub fn find_known_good_file(settings: &Settings, backed_up_file_path: &PathBuf) -> Result<Option<PathBuf>, FileError> {
+    let stripped_backed_up_path = match strip_version_suffix_from_backed_up_file_path(backed_up_file_path) {
+        Some(path) => path,
+        None =>
+            return Err(FError(vec![format!("Invalid backed up file name: {}", backed_up_file_path.str())]))
+    };
+    let known_good_folder = get_known_good_folder(settings, backed_up_file_path);
+    let known_good = find_newest_backed_up_version(&known_good_folder, stripped_backed_up_path.file_name_str())?;
+    Ok(known_good.map(|(_version, path)| path))
+}
+
+/// Removes `backed_up_file_path` along with its sidecar and note, if it has them. Those that cannot be removed are only
+/// logged, since they are ignored once their backed up file is gone.
+fn remove_backed_up_file(backed_up_file_path: &PathBuf) -> std::io::Result<()> {
+    std::fs::remove_file(backed_up_file_path)?;
+    for sidecar_path in [get_sidecar_path(backed_up_file_path), get_note_path(backed_up_file_path)] {
+        if let Err(err) = std::fs::remove_file(&sidecar_path) {
+            if err.kind() != ErrorKind::NotFound {
+                warn!("Error removing sidecar {}: {}", sidecar_path.str(), err);
+            }
+        }
+    }
+    remove_emptied_date_folder(backed_up_file_path);
+    Ok(())
+}
+
+/// Date folders are left behind as their last versions are pruned. Removing a folder that is not empty fails.
+fn remove_emptied_date_folder(backed_up_file_path: &Path) {
+    let backed_up_folder = backed_up_file_path.parent().unwrap();
+    if let Some(date_folder) = backed_up_folder.parent().filter(|date_folder| is_date_folder(date_folder)) {
+        if std::fs::remove_dir(backed_up_folder).is_ok() {
+            let _ = std::fs::remove_dir(date_folder);
+        }
+    }
+}
+
+/// Deletes `backed_up_file_path` along with its sidecar and note, or moves them to the trash folder of the destination
+/// that holds it when `settings` say so. `settings` must have that destination, which differs for the mirror folder.
+fn discard_backed_up_file(settings: &Settings, backed_up_file_path: &PathBuf) -> std::io::Result<()> {
+    if !settings.delete_to_trash {
+        return remove_backed_up_file(backed_up_file_path);
+    }
+    let backup_dest_path = get_dest_path_for_backed_up_file(settings, backed_up_file_path);
+    // Where it lies in the destination, so that it can be put back by hand
+    let relative_path = backed_up_file_path.strip_prefix(backup_dest_path)
+        .unwrap_or_else(|_| Path::new(backed_up_file_path.file_name_str()));
+    let trash_day_folder = backup_dest_path.join(TRASH_FOLDER_NAME)
+        .join(Local::now().format(DATE_FOLDER_FORMAT).to_string());
+    let mut trash_file_path = trash_day_folder.join(relative_path);
+    // E.g. when a version put back by hand is deleted again the same day. Renaming over the one already in the trash
+    // would replace it on Unix.
+    let mut copy_number = 2;
+    while trash_file_path.exists() {
+        trash_file_path = trash_day_folder.join(relative_path)
+            .with_file_name(format!("{} ({})", relative_path.file_name_str(), copy_number));
+        copy_number += 1;
+    }
+    std::fs::create_dir_all(trash_file_path.parent().unwrap())?;
+    std::fs::rename(backed_up_file_path, &trash_file_path)?;
+    for (sidecar_path, trash_sidecar_path) in [
+        (get_sidecar_path(backed_up_file_path), get_sidecar_path(&trash_file_path)),
+        (get_note_path(backed_up_file_path), get_note_path(&trash_file_path))
+    ] {
+        if let Err(err) = std::fs::rename(&sidecar_path, &trash_sidecar_path) {
+            if err.kind() != ErrorKind::NotFound {
+                warn!("Error moving sidecar {} to the trash: {}", sidecar_path.str(), err);
+            }
+        }
+    }
+    remove_emptied_date_folder(backed_up_file_path);
+    Ok(())
+}
+
+/// Permanently deletes the backups in the trash folder of `backup_dest_path` that were moved there more than
+/// `retention_days` ago, or all of them without `retention_days`. Returns the number of backups deleted.
+fn delete_trash(backup_dest_path: &Path, retention_days: Option<u32>) -> Result<usize, FileError> {
+    let trash_folder = backup_dest_path.join(TRASH_FOLDER_NAME);
+    if !trash_folder.is_dir() {
+        return Ok(0);
+    }
+    let mut errs = Vec::new();
+    let mut deleted_count = 0;
+    let today = Local::now().naive_local().date();
+    for trash_day_folder in read_dir_paths(&trash_folder, &mut errs) {
+        let trash_day = match NaiveDate::parse_from_str(trash_day_folder.file_name_str(), DATE_FOLDER_FORMAT) {
+            Ok(trash_day) => trash_day,
+            // Not written by Valbak, so only emptying the trash removes it
+            Err(_) if retention_days.is_some() => continue,
+            Err(_) => today
+        };
+        let is_expired =
+            retention_days.map_or(true, |retention_days| (today - trash_day).num_days() > retention_days as i64);
+        if !is_expired {
+            continue;
+        }
+        let trashed_count = count_trashed_backups(&trash_day_folder, &mut errs);
+        let removed = if trash_day_folder.is_dir() {
+            std::fs::remove_dir_all(&trash_day_folder)
+        } else {
+            std::fs::remove_file(&trash_day_folder)
+        };
+        match removed {
+            Ok(()) => deleted_count += trashed_count,
+            Err(err) => errs.push(format!("Error deleting {} from the trash: {}", trash_day_folder.str(), err))
+        }
+    }
+    if errs.is_empty() {
+        Ok(deleted_count)
+    } else {
+        Err(FWarning(errs))
+    }
+}
+
+/// Counts the backed up files in `path` and the folders in it, leaving out sidecars and notes
+fn count_trashed_backups(path: &Path, errs: &mut Vec<String>) -> usize {
+    if !path.is_dir() {
+        return if is_sidecar_path(path) { 0 } else { 1 };
+    }
+    read_dir_paths(path, errs).iter().map(|entry_path| count_trashed_backups(entry_path, errs)).sum()
+}
+
+/// The number of bytes that `path` and the files in it take up, adding any errors reading them to `errs`
+fn get_trashed_size(path: &Path, errs: &mut Vec<String>) -> u64 {
+    if path.is_dir() {
+        return read_dir_paths(path, errs).iter().map(|entry_path| get_trashed_size(entry_path, errs)).sum();
+    }
+    match path.metadata() {
+        Ok(metadata) => metadata.len(),
+        Err(err) => {
+            errs.push(format!("Error reading the metadata of {}: {}", path.str(), err));
+            0
+        }
+    }
+}
+
+/// Permanently deletes all backups in the trash folders of the destination folders and the mirror folder of `settings`,
+/// returning the number of backups deleted
+pub fn empty_trash(settings: &Settings) -> Result<usize, FileError> {
+    let mut deleted_count = 0;
+    for dest_path in settings.dest_paths() {
+        deleted_count += delete_trash(dest_path, None)?;
+    }
+    if let Some(secondary_dest_path) = settings.secondary_dest_path.clone() {
+        deleted_count += delete_trash(&secondary_dest_path, None)?;
+    }
+    Ok(deleted_count)
+}
+
+/// Deletes each file found in `backed_up_file_paths`, or moves it to the trash when `settings` say so
+pub fn delete_backed_up_files(settings: &Settings, backed_up_file_paths: Vec<PathBuf>) -> Result<(), FileError> {
+    let mut errs = Vec::new();
+    for backed_up_path in backed_up_file_paths {
+        info!("Deleting backed up file {}", backed_up_path.str());
+        if let Err(err) = discard_backed_up_file(settings, &backed_up_path) {
+            errs.push(format!("Error deleting file {}: {}", backed_up_path.str(), err));
+        }
+    }
+    if errs.is_empty() {
+        Ok(())
+    } else {
+        Err(FError(errs))
+    }
+}
+
+/// Restores each file found in `backed_up_file_paths`
+pub fn restore_backed_up_files(
+    settings: Settings, backed_up_file_paths: Vec<PathBuf>, listener: &dyn BackupListener
+) -> Result<(), FileError>{
+    let _operation_guard = OperationGuard::start();
+    let mut errs = Vec::new();
+    let mut folder_remaps = HashMap::new();
+    let mut temp_paths = Vec::new();
+    for backed_up_path in backed_up_file_paths {
+        let restore_target = match get_restore_path_for_backed_up_file(&settings, &backed_up_path) {
+            Ok(inferred_file_path) =>
+                choose_restore_target(&settings, &backed_up_path, &inferred_file_path, &mut folder_remaps, listener)
+                    .map(|source_file_path| (source_file_path, Some(inferred_file_path))),
+            // E.g. after the game was reinstalled elsewhere, or while browsing backups with settings that do not work
+            Err(FWarning(mut file_errs)) | Err(FError(mut file_errs)) | Err(FFatal(mut file_errs)) => {
+                match listener.choose_unknown_restore_target(&backed_up_path) {
+                    Some(source_file_path) => Some((source_file_path, None)),
+                    None => {
+                        errs.append(&mut file_errs);
+                        continue;
+                    }
+                }
+            }
+        };
+        let (source_file_path, inferred_file_path) = match restore_target {
+            Some(restore_target) => restore_target,
+            None => {
+                info!("Skipped restoring {}", backed_up_path.str());
+                continue;
+            }
+        };
+        let source_folder_path = source_file_path.parent().unwrap();
+        if !source_folder_path.is_dir() {
+            if let Err(err) = std::fs::create_dir_all(source_folder_path) {
+                errs.push(format!("Error creating folder {}: {}", source_folder_path.str(), err));
+                continue;
+            }
+        }
+        if live_file_is_newer(&source_file_path, &backed_up_path) {
+            let overwrite = match settings.overwrite_newer {
+                OverwritePolicy::Always => true,
+                OverwritePolicy::Never => false,
+                OverwritePolicy::Prompt => listener.confirm_overwrite_newer(&source_file_path, &backed_up_path)
+            };
+            if !overwrite {
+                let skip_msg = format!("Did not restore {}, the live file is newer than the backup", source_file_path.str());
+                if settings.overwrite_newer == OverwritePolicy::Never {
+                    errs.push(skip_msg);
+                } else {
+                    // The user already knows
+                    info!("{}", skip_msg);
+                }
+                continue;
+            }
         }
 
-        if let Err(err) = set_file_mtime(temp_source_file_path.clone(), backed_up_file_modified_filetime) {
-            error!("{}: {}", temp_source_file_path.str(), err);
-            continue;
+        // Any other target is not a live file that is backed up
+        if settings.snapshot_before_restore && Some(&source_file_path) == inferred_file_path.as_ref() {
+            // Leave the live file alone if it could not be preserved
+            if let Err(FWarning(mut file_errs)) | Err(FError(mut file_errs)) | Err(FFatal(mut file_errs)) =
+                snapshot_live_file(settings.clone(), source_file_path.clone())
+            {
+                errs.append(&mut file_errs);
+                continue;
+            }
         }
 
-        if let Err(err) = std::fs::rename(temp_source_file_path.clone(), source_file_path.clone()) {
-            error!("{}: {}", temp_source_file_path.str(), err);
+        // Without a backup pattern, the archive of a backed up folder is restored as it is
+        let is_folder = inferred_file_path
+            .map_or(false, |inferred_file_path| is_folder_live_file(&settings, &inferred_file_path));
+        if let Err(FWarning(mut file_errs)) | Err(FError(mut file_errs)) | Err(FFatal(mut file_errs)) =
+            write_restored_file(&settings, &backed_up_path, &source_file_path, is_folder, &mut temp_paths)
+        {
+            errs.append(&mut file_errs);
             continue;
         }
+        record_restored_live_file(&source_file_path);
 
         info!("Restored {}", source_file_path.str());
     }
-    Ok(())
+    // Left behind only by a failure that was not cleaned up after, which the watcher would otherwise see
+    temp_paths.iter().for_each(|temp_path| remove_temp_path(temp_path));
+    if errs.is_empty() {
+        Ok(())
+    } else {
+        Err(FError(errs))
+    }
 }
 
-/// Finds all version files matching `backup_pattern` in `backup_dest_path`
-pub fn get_backed_up_version_paths(
-    backup_dest_path: PathBuf, backup_pattern: BackupFilePattern
+/// Remembers the size and timestamp of the live file `live_file_path` as it was just restored. The watcher sees the
+/// restore as a change, which must not be backed up as a new version even where the restored file cannot be matched to
+/// the backup it came from, e.g. when that backup was removed since, or the copy was seen before its timestamp was set.
+fn record_restored_live_file(live_file_path: &PathBuf) {
+    match get_live_file_fingerprint(live_file_path) {
+        Ok((live_file_len, live_file_modified)) => {
+            let mut restored_live_files = RESTORED_LIVE_FILES.lock();
+            restored_live_files.retain(|(restored_path, _len, _modified)| restored_path != live_file_path);
+            restored_live_files.push((live_file_path.clone(), live_file_len, live_file_modified));
+        }
+        Err(err) =>
+            warn!("Error reading the restored file {}, it may be backed up again: {}", live_file_path.str(), err)
+    }
+}
+
+/// Whether `live_file_path` still has the size `live_file_len` and timestamp `live_file_modified` that it was restored
+/// with. A restored file that changed since is forgotten, so that later changes back to the same content are backed up.
+fn is_unchanged_since_restore(live_file_path: &PathBuf, live_file_len: u64, live_file_modified: SystemTime) -> bool {
+    let mut restored_live_files = RESTORED_LIVE_FILES.lock();
+    match restored_live_files.iter().position(|(restored_path, _len, _modified)| restored_path == live_file_path) {
+        Some(index) => {
+            let (_restored_path, restored_len, restored_modified) = &restored_live_files[index];
+            if *restored_len == live_file_len && *restored_modified == live_file_modified {
+                return true;
+            }
+            restored_live_files.remove(index);
+            false
+        }
+        None => false
+    }
+}
+
+/// Where `backed_up_path` is restored to, given the live file `inferred_file_path` derived for it. The user confirms or
+/// redirects the target when `settings` say so, and whenever its folder does not exist. A redirect to another folder
+/// applies to the remaining files from the same folder as well, which are then only confirmed when `settings` say so.
+/// Returns `None` when the user skips the file.
+fn choose_restore_target(
+    settings: &Settings,
+    backed_up_path: &PathBuf,
+    inferred_file_path: &PathBuf,
+    folder_remaps: &mut HashMap<PathBuf, PathBuf>,
+    listener: &dyn BackupListener
+) -> Option<PathBuf> {
+    let inferred_folder_path = inferred_file_path.parent().unwrap().to_path_buf();
+    let target_file_path = match folder_remaps.get(&inferred_folder_path) {
+        Some(remapped_folder_path) => remapped_folder_path.join(inferred_file_path.file_name_str()),
+        None => inferred_file_path.clone()
+    };
+    if !settings.confirm_restore_targets && target_file_path.parent().unwrap().is_dir() {
+        return Some(target_file_path);
+    }
+    let chosen_file_path = listener.confirm_restore_target(backed_up_path, &target_file_path)?;
+    let chosen_folder_path = chosen_file_path.parent().unwrap().to_path_buf();
+    if chosen_folder_path != inferred_folder_path {
+        info!("Restoring files from {} to {}", inferred_folder_path.str(), chosen_folder_path.str());
+        folder_remaps.insert(inferred_folder_path, chosen_folder_path);
+    }
+    Some(chosen_file_path)
+}
+
+/// Restores each file found in `backed_up_file_paths` to a new copy next to its live file rather than over it, named
+/// for the time of the restore, e.g. `world.restored-2024-01-15T13-22-05.db`. Returns the paths of the copies.
+pub fn restore_backed_up_files_as_copies(
+    settings: Settings, backed_up_file_paths: Vec<PathBuf>
 ) -> Result<Vec<PathBuf>, FileError> {
+    let _operation_guard = OperationGuard::start();
+    let mut errs = Vec::new();
+    let mut restored_copy_paths = Vec::new();
+    let mut temp_paths = Vec::new();
+    for backed_up_path in backed_up_file_paths {
+        let source_file_path = match get_restore_path_for_backed_up_file(&settings, &backed_up_path) {
+            Ok(path) => path,
+            Err(FWarning(mut file_errs)) | Err(FError(mut file_errs)) | Err(FFatal(mut file_errs)) => {
+                errs.append(&mut file_errs);
+                continue;
+            }
+        };
+        let restored_copy_path = get_restored_copy_path(&source_file_path);
 
-    // 1. Create an absolute backed up file pattern
+        let is_folder = is_folder_live_file(&settings, &source_file_path);
+        if let Err(FWarning(mut file_errs)) | Err(FError(mut file_errs)) | Err(FFatal(mut file_errs)) =
+            write_restored_file(&settings, &backed_up_path, &restored_copy_path, is_folder, &mut temp_paths)
+        {
+            errs.append(&mut file_errs);
+            continue;
+        }
 
-    let backed_up_versions_filename_pattern = backup_pattern.filename_pattern + ".*";
-    let backed_up_folder_name = backup_pattern.source_dir.file_name().unwrap();
-    let backed_up_versions_pattern = backup_dest_path
-        .join(backed_up_folder_name)
-        .join(backed_up_versions_filename_pattern);
+        info!("Restored {} to {}", backed_up_path.str(), restored_copy_path.str());
+        restored_copy_paths.push(restored_copy_path);
+    }
+    temp_paths.iter().for_each(|temp_path| remove_temp_path(temp_path));
+    if errs.is_empty() {
+        Ok(restored_copy_paths)
+    } else {
+        Err(FError(errs))
+    }
+}
 
-    // 2. Get a list of all files matching the pattern
+/// Writes the content and timestamp of `backed_up_path` to `to_path` by way of a temp file, or a temp folder when
+/// `is_folder` and `backed_up_path` is a folder archive, so that `to_path` is never left partially written. The temp
+/// path is removed again when the restore fails, and each one used is added to `temp_paths`, for the caller to make
+/// sure none is left behind.
+fn write_restored_file(
+    settings: &Settings, backed_up_path: &PathBuf, to_path: &PathBuf, is_folder: bool, temp_paths: &mut Vec<PathBuf>
+) -> Result<(), FileError> {
+    if is_folder {
+        return write_restored_folder(settings, backed_up_path, to_path, temp_paths);
+    }
+    let backed_up_folder_path = backed_up_path.parent().unwrap();
+    let temp_file_path = get_temp_file_path(settings, backed_up_folder_path, to_path.file_name_str())?;
+    temp_paths.push(temp_file_path.clone());
 
-    let glob_paths = match glob(backed_up_versions_pattern.str()) {
-        Err(err) => {
-            return Err(FError(
-                vec![format!("Error scanning backed up files for {}: {}", backed_up_versions_pattern.str(), err)]
-            ));
+    if let Err(err) = write_restored_temp_file(settings, backed_up_path, &temp_file_path) {
+        remove_temp_path(&temp_file_path);
+        return Err(err);
+    }
+    if let Err(err) = move_file(&temp_file_path, to_path) {
+        remove_temp_path(&temp_file_path);
+        return Err(FError(vec![format!("Error moving {} to {}: {}", temp_file_path.str(), to_path.str(), err)]));
+    }
+    Ok(())
+}
+
+/// Writes the content, timestamp and possibly the permissions of `backed_up_path` to `temp_file_path`
+fn write_restored_temp_file(
+    settings: &Settings, backed_up_path: &PathBuf, temp_file_path: &PathBuf
+) -> Result<(), FileError> {
+    let (backed_up_file_metadata, _backup_file_modified) = get_file_metadata(backed_up_path)?;
+    let backed_up_file_modified_filetime = FileTime::from_last_modification_time(&backed_up_file_metadata);
+
+    if crypt::is_encrypted_file(backed_up_path) {
+        write_decrypted_copy(backed_up_path, temp_file_path)?;
+    } else if let Err(err) = std::fs::copy(backed_up_path.clone(), temp_file_path.clone()) {
+        return Err(FError(vec![format!("Error copying file from {} to {}: {}",
+            backed_up_path.str(), temp_file_path.str(), err)]));
+    }
+
+    if let Err(err) = set_file_mtime(temp_file_path.clone(), backed_up_file_modified_filetime) {
+        return Err(FError(vec![format!("Error setting timestamp for {}: {}", temp_file_path.str(), err)]));
+    }
+
+    if settings.preserve_permissions {
+        if let Err(err) = copy_file_permissions(&backed_up_file_metadata, temp_file_path) {
+            return Err(FError(vec![format!("Error setting permissions for {}: {}", temp_file_path.str(), err)]));
         }
-        Ok(glob_paths) =>
-            glob_paths
+    }
+    Ok(())
+}
+
+/// Removes the temp file or folder at `temp_path`, if it still exists
+fn remove_temp_path(temp_path: &Path) {
+    let removed = if temp_path.is_dir() {
+        std::fs::remove_dir_all(temp_path)
+    } else if temp_path.exists() {
+        std::fs::remove_file(temp_path)
+    } else {
+        return;
+    };
+    match removed {
+        Ok(()) => info!("Removed temp file {}", temp_path.str()),
+        Err(err) => warn!("Error removing temp file {}: {}", temp_path.str(), err)
+    }
+}
+
+/// Extracts the folder archive `backed_up_path` to `to_path`. A folder already at `to_path` is only replaced once the
+/// archive has been fully extracted next to it.
+fn write_restored_folder(
+    settings: &Settings, backed_up_path: &PathBuf, to_path: &PathBuf, temp_paths: &mut Vec<PathBuf>
+) -> Result<(), FileError> {
+    // Extracted next to the live folder, so that it can be renamed into place
+    let temp_folder_path = to_path.with_file_name(get_temp_filename(to_path.file_name_str()));
+    TEMP_FILES_IN_PROGRESS.lock().push(temp_folder_path.clone());
+    let replaced_folder_path = to_path.with_file_name(format!("{}{}.old", TEMP_FILE_PREFIX, to_path.file_name_str()));
+    if replaced_folder_path.exists() {
+        // Left by an earlier restore that was interrupted, and possibly the only copy of the live folder
+        return Err(FError(vec![format!("Cannot restore {} until {} is moved out of the way",
+            to_path.str(), replaced_folder_path.str())]));
+    }
+
+    let decrypted_archive_path = if crypt::is_encrypted_file(backed_up_path) {
+        let archive_filename = format!("{}.tar", to_path.file_name_str());
+        let decrypted_archive_path = get_temp_file_path(settings, backed_up_path.parent().unwrap(), &archive_filename)?;
+        temp_paths.push(decrypted_archive_path.clone());
+        if let Err(err) = write_decrypted_copy(backed_up_path, &decrypted_archive_path) {
+            remove_temp_path(&decrypted_archive_path);
+            return Err(err);
+        }
+        Some(decrypted_archive_path)
+    } else {
+        None
+    };
+    temp_paths.push(temp_folder_path.clone());
+    let extracted = extract_folder_archive(decrypted_archive_path.as_ref().unwrap_or(backed_up_path), &temp_folder_path);
+    if let Some(decrypted_archive_path) = &decrypted_archive_path {
+        if let Err(err) = std::fs::remove_file(decrypted_archive_path) {
+            warn!("Error removing temp file {}: {}", decrypted_archive_path.str(), err);
+        }
+    }
+    if let Err(err) = extracted {
+        let _ = std::fs::remove_dir_all(&temp_folder_path);
+        return Err(err);
+    }
+
+    let replacing = to_path.exists();
+    if replacing {
+        if let Err(err) = std::fs::rename(to_path, &replaced_folder_path) {
+            remove_temp_path(&temp_folder_path);
+            return Err(FError(vec![format!("Error moving {} to {}: {}", to_path.str(), replaced_folder_path.str(), err)]));
+        }
+    }
+    if let Err(err) = std::fs::rename(&temp_folder_path, to_path) {
+        if replacing {
+            // Put the live folder back
+            let _ = std::fs::rename(&replaced_folder_path, to_path);
+        }
+        remove_temp_path(&temp_folder_path);
+        return Err(FError(vec![format!("Error moving {} to {}: {}", temp_folder_path.str(), to_path.str(), err)]));
+    }
+    if replacing {
+        if let Err(err) = std::fs::remove_dir_all(&replaced_folder_path) {
+            warn!("Error removing replaced folder {}: {}", replaced_folder_path.str(), err);
+        }
+    }
+    Ok(())
+}
+
+/// Whether `live_file_path` is a folder that is backed up as an archive
+fn is_folder_live_file(settings: &Settings, live_file_path: &PathBuf) -> bool {
+    find_backup_pattern_for_live_file(settings, live_file_path).map_or(false, |pattern| pattern.directory_mode)
+}
+
+/// A path next to `live_file_path` that does not exist yet for a restored copy of it, e.g.
+/// `world.restored-2024-01-15T13-22-05.db` for `world.db`
+fn get_restored_copy_path(live_file_path: &PathBuf) -> PathBuf {
+    let live_filename = live_file_path.file_name_str();
+    // The extension keeps its dot, and a leading dot does not start an extension
+    let (stem, extension) = match live_filename.rfind('.') {
+        Some(dot_index) if dot_index > 0 => live_filename.split_at(dot_index),
+        _ => (live_filename, "")
+    };
+    let marked_stem = format!("{}{}{}", stem, RESTORED_COPY_MARKER, Local::now().format(VERSION_TIMESTAMP_FORMAT));
+    let mut restored_copy_path = live_file_path.with_file_name(format!("{}{}", marked_stem, extension));
+    let mut counter = 2;
+    while restored_copy_path.exists() {
+        restored_copy_path = live_file_path.with_file_name(format!("{}-{}{}", marked_stem, counter, extension));
+        counter += 1;
+    }
+    restored_copy_path
+}
+
+/// Whether `path` is a copy written by [`restore_backed_up_files_as_copies`]
+pub fn is_restored_copy(path: &Path) -> bool {
+    path.file_name().map_or(false, |filename| filename.to_string_lossy().contains(RESTORED_COPY_MARKER))
+}
+
+/// Checks whether `live_file_path` exists and was modified after the live file that `backed_up_file_path` was backed up
+/// from, whose timestamp the backed up file keeps
+fn live_file_is_newer(live_file_path: &PathBuf, backed_up_file_path: &PathBuf) -> bool {
+    match (get_live_file_fingerprint(live_file_path), get_file_metadata(backed_up_file_path)) {
+        (Ok((_live_len, live_modified)), Ok((_backed_up_metadata, backed_up_modified))) =>
+            live_modified > backed_up_modified,
+        _ => false
+    }
+}
+
+/// Backs up `live_file_path`, if it exists and has no backup yet, so that it can be recovered after being overwritten
+fn snapshot_live_file(settings: Settings, live_file_path: PathBuf) -> Result<(), FileError> {
+    if !live_file_path.exists() || find_backup_for_live_file(settings.clone(), live_file_path.clone())? {
+        return Ok(());
+    }
+    info!("Backing up {} before restoring over it", live_file_path.str());
+    if let Some(backed_up_file_path) = copy_live_file_to_backup(settings.clone(), live_file_path)? {
+        mirror_backed_up_file(&settings, &backed_up_file_path);
+    }
+    Ok(())
+}
+
+/// How a new backed up file differs from the version of the same file before it
+#[derive(Clone, Copy, Debug)]
+pub struct BackupChange {
+    /// The new size minus the previous size
+    pub size_delta: i64,
+    /// The new modification time minus the previous modification time, in seconds. Backed up files keep the timestamp
+    /// of their live file, so this is the time between the two saves.
+    pub modified_delta_sec: i64,
+}
+
+impl Display for BackupChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let modified_delta_sec = self.modified_delta_sec.max(0);
+        let since_previous = if modified_delta_sec >= 60 * 60 {
+            format!("{}h {}m", modified_delta_sec / (60 * 60), modified_delta_sec / 60 % 60)
+        } else if modified_delta_sec >= 60 {
+            format!("{}m {}s", modified_delta_sec / 60, modified_delta_sec % 60)
+        } else {
+            format!("{}s", modified_delta_sec)
+        };
+        write!(f, "{:+} bytes, {} after the previous version", self.size_delta, since_previous)
+    }
+}
+
+/// Compares the backed up file `backed_up_file_path` with the newest older version of the same file in the backup
+/// destination of `settings`, whatever its version separator and date folder, or returns `None` when there is no older
+/// version
+pub fn get_backup_change(
+    settings: &Settings, backed_up_file_path: &PathBuf
+) -> Result<Option<BackupChange>, FileError> {
+    let (version, stripped_path) = match (
+        get_backed_up_version(backed_up_file_path),
+        strip_version_suffix_from_backed_up_file_path(backed_up_file_path)
+    ) {
+        (Some(version), Some(stripped_path)) => (version, stripped_path),
+        _ => return Err(FError(vec![format!("{} is not a backed up file", backed_up_file_path.str())]))
+    };
+    let backup_folder = strip_date_folder(&stripped_path).parent().unwrap().to_path_buf();
+    let mut version_paths = Vec::new();
+    for backed_up_folder_pattern in get_backed_up_folder_patterns(settings, &backup_folder) {
+        let versions_pattern = backed_up_folder_pattern
+            .join(Pattern::escape(stripped_path.file_name_str()) + &get_versions_glob_suffix());
+        match glob(versions_pattern.str()) {
+            Ok(glob_paths) => version_paths.extend(glob_paths.flatten()),
+            Err(err) => return Err(FError(
+                vec![format!("Error scanning backed up files for {}: {}", versions_pattern.str(), err)]
+            ))
+        }
+    }
+    let mut previous_version: Option<(BackupVersion, PathBuf)> = None;
+    for version_path in version_paths {
+        // The pattern also matches longer names, e.g. `world.db.old.3` for `world.db`
+        if strip_version_suffix_from_backed_up_file_path(&version_path).as_ref() != Some(&stripped_path) {
+            continue;
+        }
+        if let Some(other_version) = get_backed_up_version(&version_path) {
+            let is_previous =
+                compare_versions(&other_version, &version_path, &version, backed_up_file_path)
+                    == std::cmp::Ordering::Less
+                && previous_version.as_ref().map_or(true, |(previous, previous_path)| {
+                    compare_versions(&other_version, &version_path, previous, previous_path)
+                        == std::cmp::Ordering::Greater
+                });
+            if is_previous {
+                previous_version = Some((other_version, version_path));
+            }
+        }
+    }
+    let previous_path = match previous_version {
+        Some((_version, previous_path)) => previous_path,
+        None => return Ok(None)
+    };
+    let (metadata, modified) = get_file_metadata(backed_up_file_path)?;
+    let (previous_metadata, previous_modified) = get_file_metadata(&previous_path)?;
+    let size_delta = get_backed_up_content_len(backed_up_file_path, &metadata) as i64
+        - get_backed_up_content_len(&previous_path, &previous_metadata) as i64;
+    let modified_delta_sec = match modified.duration_since(previous_modified) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(err) => -(err.duration().as_secs() as i64)
+    };
+    Ok(Some(BackupChange { size_delta, modified_delta_sec }))
+}
+
+/// How a backed up file differs from its live file
+pub struct BackupComparison {
+    pub live_file_path: PathBuf,
+    /// The live file size minus the backed up file size, or `None` when the live file does not exist
+    pub size_delta: Option<i64>,
+    /// The live file modification time minus the backed up file modification time, in seconds
+    pub modified_delta_sec: Option<i64>,
+    pub identical: bool,
+}
+
+/// Compares `backed_up_file_path` with the live file it was backed up from
+pub fn compare_backed_up_file(settings: Settings, backed_up_file_path: PathBuf) -> Result<BackupComparison, FileError> {
+    let live_file_path = get_live_file_for_backed_up_file(settings, backed_up_file_path.clone())?;
+    if !live_file_path.exists() {
+        return Ok(BackupComparison {
+            live_file_path,
+            size_delta: None,
+            modified_delta_sec: None,
+            identical: false
+        });
+    }
+    if live_file_path.is_dir() {
+        return Err(FWarning(vec![format!("Comparing folder backups is not supported: {}", live_file_path.str())]));
+    }
+    let (backed_up_file_metadata, backed_up_file_modified) = get_file_metadata(&backed_up_file_path)?;
+    let (live_file_metadata, live_file_modified) = get_file_metadata(&live_file_path)?;
+
+    let backed_up_content_len = get_backed_up_content_len(&backed_up_file_path, &backed_up_file_metadata);
+    let size_delta = live_file_metadata.len() as i64 - backed_up_content_len as i64;
+    let modified_delta_sec = match live_file_modified.duration_since(backed_up_file_modified) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(err) => -(err.duration().as_secs() as i64)
+    };
+    let identical = size_delta == 0 && backed_up_file_has_same_content(&backed_up_file_path, &live_file_path)?;
+
+    Ok(BackupComparison {
+        live_file_path,
+        size_delta: Some(size_delta),
+        modified_delta_sec: Some(modified_delta_sec),
+        identical
+    })
+}
+
+/// Compares the content of `backed_up_file_path`, decrypting it if necessary, with `live_file_path`
+fn backed_up_file_has_same_content(backed_up_file_path: &Path, live_file_path: &Path) -> Result<bool, FileError> {
+    if crypt::is_encrypted_file(backed_up_file_path) {
+        let backed_up_content = crypt::decrypt(backed_up_file_path, &read_file(backed_up_file_path)?)?;
+        return Ok(backed_up_content == read_file(live_file_path)?);
+    }
+    match files_have_same_content(backed_up_file_path, live_file_path) {
+        Ok(same) => Ok(same),
+        Err(err) =>
+            Err(FError(vec![format!("Error comparing {} with {}: {}",
+                backed_up_file_path.str(), live_file_path.str(), err)]))
+    }
+}
+
+/// The size of the content of `backed_up_file_path`, not counting any encryption overhead
+fn get_backed_up_content_len(backed_up_file_path: &Path, backed_up_file_metadata: &Metadata) -> u64 {
+    if crypt::is_encrypted_file(backed_up_file_path) {
+        backed_up_file_metadata.len().saturating_sub(crypt::ENCRYPTION_OVERHEAD)
+    } else {
+        backed_up_file_metadata.len()
+    }
+}
+
+/// Writes an encrypted copy of `from_path` to `to_path`, using the session passphrase
+fn write_encrypted_copy(settings: &Settings, from_path: &Path, to_path: &Path) -> Result<(), FileError> {
+    let plaintext = match read_live_file(settings, from_path) {
+        Ok(plaintext) => plaintext,
+        Err(err) => return Err(FError(vec![format!("Error reading file {}: {}", from_path.str(), err)]))
     };
+    let encrypted = crypt::encrypt(&plaintext)?;
+    write_file(to_path, &encrypted)
+}
+
+/// Writes a decrypted copy of the encrypted `from_path` to `to_path`, using the session passphrase
+fn write_decrypted_copy(from_path: &Path, to_path: &Path) -> Result<(), FileError> {
+    let decrypted = crypt::decrypt(from_path, &read_file(from_path)?)?;
+    write_file(to_path, &decrypted)
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, FileError> {
+    match std::fs::read(path) {
+        Ok(content) => Ok(content),
+        Err(err) => Err(FError(vec![format!("Error reading file {}: {}", path.str(), err)]))
+    }
+}
+
+fn write_file(path: &Path, content: &[u8]) -> Result<(), FileError> {
+    match std::fs::write(path, content) {
+        Ok(()) => Ok(()),
+        Err(err) => Err(FError(vec![format!("Error writing file {}: {}", path.str(), err)]))
+    }
+}
+
+fn files_have_same_content(path_a: &Path, path_b: &Path) -> std::io::Result<bool> {
+    let mut reader_a = BufReader::new(File::open(path_a)?);
+    let mut reader_b = BufReader::new(File::open(path_b)?);
+    loop {
+        let buf_a = reader_a.fill_buf()?;
+        let buf_b = reader_b.fill_buf()?;
+        if buf_a.is_empty() || buf_b.is_empty() {
+            return Ok(buf_a.is_empty() && buf_b.is_empty());
+        }
+        let len = buf_a.len().min(buf_b.len());
+        if buf_a[..len] != buf_b[..len] {
+            return Ok(false);
+        }
+        reader_a.consume(len);
+        reader_b.consume(len);
+    }
+}
 
-    // 3. Convert matching paths into a path vector
+/// Finds all version files matching `backup_pattern` in the backup destination of `settings`
+pub fn get_backed_up_version_paths(
+    settings: &Settings, backup_pattern: BackupFilePattern
+) -> Result<Vec<PathBuf>, FileError> {
 
+    let backup_folder = get_backup_folder(settings, &backup_pattern);
     let mut backed_up_version_paths = vec![];
-    for glob_path in glob_paths {
-        match glob_path {
-            Err(err) =>
+    for backed_up_folder_pattern in get_backed_up_folder_patterns(settings, &backup_folder) {
+
+        // 1. Create an absolute backed up file pattern
+
+        let backed_up_versions_pattern = backed_up_folder_pattern
+            .join(backup_pattern.filename_pattern.clone() + &get_versions_glob_suffix());
+
+        // 2. Get a list of all files matching the pattern
+
+        let glob_paths = match glob_with(backed_up_versions_pattern.str(), get_match_options(settings)) {
+            Err(err) => {
                 return Err(FError(
                     vec![format!("Error scanning backed up files for {}: {}", backed_up_versions_pattern.str(), err)]
-                )),
-            Ok(glob_path) =>
-                backed_up_version_paths.push(glob_path)
+                ));
+            }
+            Ok(glob_paths) =>
+                glob_paths
+        };
+
+        // 3. Convert matching paths into a path vector
+
+        for glob_path in glob_paths {
+            match glob_path {
+                Err(err) =>
+                    return Err(FError(
+                        vec![format!("Error scanning backed up files for {}: {}", backed_up_versions_pattern.str(), err)]
+                    )),
+                Ok(glob_path) =>
+                    backed_up_version_paths.push(glob_path)
+            }
         }
     }
 
     Ok(backed_up_version_paths)
 }
 
+/// Finds the versions of `live_file_path` that remain in its backup destination, newest first
+pub fn get_backed_up_versions_of_live_file(
+    settings: &Settings, live_file_path: &PathBuf
+) -> Result<Vec<PathBuf>, FileError> {
+    let backup_pattern = match find_backup_pattern_for_live_file(settings, live_file_path) {
+        Some(backup_pattern) => backup_pattern,
+        None => return Ok(vec![])
+    };
+    let live_filename = live_file_path.file_name_str();
+    let stripped_file_path = get_backup_folder(settings, backup_pattern).join(live_filename);
+    let live_file_pattern = BackupFilePattern {
+        filename_pattern: Pattern::escape(live_filename),
+        ..backup_pattern.clone()
+    };
+    // The pattern also matches longer names, e.g. `world.db.old.3` for `world.db`
+    let mut version_paths: Vec<PathBuf> = get_backed_up_version_paths(settings, live_file_pattern)?
+        .into_iter()
+        .filter(|path| get_backed_up_version(path).is_some()
+            && strip_version_suffix_from_backed_up_file_path(path).as_ref() == Some(&stripped_file_path))
+        .collect();
+    sort_backed_up_versions(&mut version_paths);
+    version_paths.reverse();
+    Ok(version_paths)
+}
+
+/// Counts the versions of `live_file_path` ever backed up according to the manifest of its destination in `manifests`,
+/// and the versions that remain in the destination. Versions backed up before the manifest existed are only counted
+/// while they remain.
+pub fn count_live_file_versions(
+    settings: &Settings, manifests: &HashMap<PathBuf, Manifest>, live_file_path: &PathBuf
+) -> Result<(u64, usize), FileError> {
+    let backup_pattern = match find_backup_pattern_for_live_file(settings, live_file_path) {
+        Some(backup_pattern) => backup_pattern,
+        None => return Ok((0, 0))
+    };
+    let stripped_file_path = get_backup_folder(settings, backup_pattern).join(live_file_path.file_name_str());
+    let current_count = get_backed_up_versions_of_live_file(settings, live_file_path)?.len();
+    let backup_dest_path = backup_pattern.dest_path(&settings.backup_dest_path);
+    let recorded_count = manifests.get(backup_dest_path)
+        .and_then(|manifest| manifest.get_entry(backup_dest_path, &stripped_file_path))
+        .map_or(0, |entry| entry.backup_count);
+    Ok((recorded_count.max(current_count as u64), current_count))
+}
+
 /// Transforms `backed_up_file` into a [`PathBuf`] representing the live file for which `backed_up_file` was originally
-/// created. Note that the returned path is not confirmed to exist.
-fn get_live_file_for_backed_up_file(settings: Settings, backed_up_file: PathBuf) -> Result<PathBuf, FileError> {
-    let backed_up_folder_name = backed_up_file.parent().unwrap().file_name().unwrap();
+/// created. When the patterns of several source folders back up to the same folder and match the file, the one where
+/// the live file exists wins, and otherwise the first. Note that the returned path is not confirmed to exist.
+pub fn get_live_file_for_backed_up_file(settings: Settings, backed_up_file: PathBuf) -> Result<PathBuf, FileError> {
+    let backed_up_folder_name = backed_up_file.parent().unwrap().file_name_str();
 
     let stripped_backed_up_filename = match strip_version_suffix_from_backed_up_file_path(&backed_up_file) {
         Some(path) => {
@@ -449,11 +2549,16 @@ fn get_live_file_for_backed_up_file(settings: Settings, backed_up_file: PathBuf)
             return Err(FError(vec![format!("Invalid backed up file name: {}", backed_up_file.str())]))
     };
 
-    for backup_pattern in settings.backup_patterns {
+    let match_options = get_match_options(&settings);
+    let mut matching_live_file_paths: Vec<PathBuf> = Vec::new();
+    for backup_pattern in &settings.backup_patterns {
         let backup_pattern_path = backup_pattern.to_path();
-        let backup_pattern_folder_name = backup_pattern_path.parent().unwrap().file_name().unwrap();
 
-        if backup_pattern_folder_name == backed_up_folder_name {
+        // The folder of the same name in another destination holds the files of other patterns
+        if backup_pattern.dest_subdir_name() == backed_up_folder_name
+            && backup_pattern.dest_path(&settings.backup_dest_path)
+                == get_dest_path_for_backed_up_file(&settings, &backed_up_file)
+        {
             let backup_file_pattern = match Pattern::new(backup_pattern_path.str()) {
                 Ok(pattern) => pattern,
                 Err(err) =>
@@ -466,13 +2571,52 @@ fn get_live_file_for_backed_up_file(settings: Settings, backed_up_file: PathBuf)
             let expected_live_file_path = backup_pattern_path.parent().unwrap()
                 .join(stripped_backed_up_filename.as_str());
 
-            if backup_file_pattern.matches_path(&expected_live_file_path) {
-                return Ok(expected_live_file_path);
+            if backup_file_pattern.matches_path_with(&expected_live_file_path, match_options)
+                && !matching_live_file_paths.contains(&expected_live_file_path) {
+                matching_live_file_paths.push(expected_live_file_path);
             }
         }
     }
 
-    Err(FError(vec![format!("Failed to find source file for backed up file {}", backed_up_file.str())]))
+    if matching_live_file_paths.len() > 1 {
+        debug!("{} matches the backup patterns of {} source folders", backed_up_file.str(), matching_live_file_paths.len());
+    }
+    if let Some(live_file_path) = matching_live_file_paths.iter().find(|live_file_path| live_file_path.exists()) {
+        return Ok(live_file_path.clone());
+    }
+    if let Some(live_file_path) = matching_live_file_paths.into_iter().next() {
+        return Ok(live_file_path);
+    }
+
+    Err(FError(vec![format!("No backup pattern matches the backed up file {}. Add its pattern again to restore it to \
+        where it came from.", backed_up_file.str())]))
+}
+
+/// The live file that `backed_up_file_path` is restored to: the one recorded in its sidecar when it has one, or else the
+/// one derived from the backup patterns
+fn get_restore_path_for_backed_up_file(settings: &Settings, backed_up_file_path: &PathBuf) -> Result<PathBuf, FileError> {
+    match read_sidecar(backed_up_file_path) {
+        Ok(Some(metadata)) => return Ok(metadata.original_path),
+        Ok(None) => {}
+        Err(err) => warn!("{}, restoring to the live file of the backup patterns", err)
+    }
+    get_live_file_for_backed_up_file(settings.clone(), backed_up_file_path.clone())
+}
+
+/// Determines the `source_dir` of the backup pattern from which `backed_up_file` was created
+pub fn get_source_dir_for_backed_up_file(settings: Settings, backed_up_file: PathBuf) -> Result<PathBuf, FileError> {
+    let live_file_path = get_live_file_for_backed_up_file(settings, backed_up_file)?;
+    Ok(live_file_path.parent().unwrap().to_path_buf())
+}
+
+/// Returns the size and timestamp that `live_file_path` is compared with its backups by. For a folder these are the total
+/// size of the files in it and the timestamp of the newest one.
+pub fn get_live_file_fingerprint(live_file_path: &PathBuf) -> Result<(u64, SystemTime), FileError> {
+    if live_file_path.is_dir() {
+        return get_folder_fingerprint(live_file_path);
+    }
+    let (metadata, modified) = get_file_metadata(live_file_path)?;
+    Ok((metadata.len(), modified))
 }
 
 /// Queries the filesystem for `file_path` and returns the file's metadata and modification timestamp
@@ -500,4 +2644,409 @@ pub fn get_file_metadata(file_path: &PathBuf) -> Result<(Metadata, SystemTime),
             }
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, SystemTime};
+
+    use chrono::Local;
+    use filetime::{FileTime, set_file_mtime};
+
+    use crate::file::{BackupListener, backup_live_file, delete_old_backups, find_backups_over_count, live_file_has_backup};
+    use crate::file::{BackupVersion, get_live_file_for_backed_up_file, move_file_with, restore_backed_up_files};
+    use crate::file::{split_version_suffix, strip_version_suffix_from_backed_up_file_path};
+    use crate::file::{backup_all_changed_files, DATE_FOLDER_FORMAT, delete_backed_up_files, get_backed_up_files};
+    use crate::file::{get_backed_up_versions_of_live_file, get_backup_change, get_live_files, sort_backed_up_versions};
+    use crate::file::FileError::FError;
+    use crate::file::{find_known_good_file, mark_known_good};
+    use crate::mirror::get_mirror_path;
+    use crate::settings::{BackupFilePattern, BackupLayout, Settings};
+
+    /// A folder of its own under the system temp folder, removed again when dropped
+    pub(super) struct TestDir(PathBuf);
+
+    impl TestDir {
+        pub(super) fn new(name: &str) -> TestDir {
+            static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+            let path = std::env::temp_dir().join(format!("valbak-test-{}-{}-{}",
+                name, std::process::id(), NEXT_ID.fetch_add(1, Ordering::SeqCst)));
+            std::fs::create_dir_all(&path).unwrap();
+            TestDir(path)
+        }
+
+        pub(super) fn join(&self, path: &str) -> PathBuf {
+            self.0.join(path)
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Restores every file to the live file derived for it, without asking
+    pub(super) struct TestListener;
+
+    impl BackupListener for TestListener {
+        fn push_status(&self, _status: String) {}
+        fn set_status(&self, _status: String) {}
+        fn pop_status(&self) {}
+        fn confirm_overwrite_newer(&self, _live_file_path: &Path, _backed_up_file_path: &Path) -> bool {
+            true
+        }
+        fn confirm_restore_target(&self, _backed_up_file_path: &Path, live_file_path: &Path) -> Option<PathBuf> {
+            Some(live_file_path.to_path_buf())
+        }
+        fn choose_unknown_restore_target(&self, _backed_up_file_path: &Path) -> Option<PathBuf> {
+            None
+        }
+    }
+
+    /// Settings that back up the `*.sav` files in `source` of `test_dir` to `dest`, both of which are created
+    pub(super) fn test_settings(test_dir: &TestDir) -> Settings {
+        let source_dir = test_dir.join("source");
+        let dest_dir = test_dir.join("dest");
+        std::fs::create_dir_all(&source_dir).unwrap();
+        std::fs::create_dir_all(&dest_dir).unwrap();
+        let backup_pattern = BackupFilePattern {
+            source_dir,
+            filename_pattern: "*.sav".to_string(),
+            dest_subdir: None,
+            dest_override: None,
+            directory_mode: false,
+            enabled: true
+        };
+        Settings::new(vec![backup_pattern], dest_dir)
+    }
+
+    /// Writes `content` to `path`, last modified `age_sec` seconds ago
+    pub(super) fn write_file(path: &Path, content: &str, age_sec: u64) {
+        std::fs::write(path, content).unwrap();
+        let modified = SystemTime::now() - Duration::from_secs(age_sec);
+        set_file_mtime(path, FileTime::from_system_time(modified)).unwrap();
+    }
+
+    #[test]
+    fn move_across_filesystems_keeps_timestamp() {
+        let test_dir = TestDir::new("move");
+        let from_path = test_dir.join("from.sav");
+        let to_path = test_dir.join("to.sav");
+        write_file(&from_path, "world", 3600);
+        let from_modified = from_path.metadata().unwrap().modified().unwrap();
+
+        // As `std::fs::rename` fails when the paths are on different filesystems
+        #[cfg(unix)]
+        let cross_device_error = std::io::Error::from_raw_os_error(18);
+        #[cfg(windows)]
+        let cross_device_error = std::io::Error::from_raw_os_error(17);
+        move_file_with(&from_path, &to_path, |_from_path, _to_path| Err(cross_device_error)).unwrap();
+
+        assert!(!from_path.exists());
+        assert_eq!(std::fs::read_to_string(&to_path).unwrap(), "world");
+        assert_eq!(to_path.metadata().unwrap().modified().unwrap(), from_modified);
+    }
+
+    #[test]
+    fn failed_restore_is_reported_and_others_are_restored() {
+        let test_dir = TestDir::new("restore");
+        let settings = test_settings(&test_dir);
+        let blocked_live_path = test_dir.join("source/blocked.sav");
+        let other_live_path = test_dir.join("source/other.sav");
+        write_file(&blocked_live_path, "blocked", 3600);
+        write_file(&other_live_path, "other", 3600);
+        let blocked_backup_path = backup_live_file(settings.clone(), blocked_live_path.clone(), &TestListener)
+            .unwrap().unwrap();
+        let other_backup_path = backup_live_file(settings.clone(), other_live_path.clone(), &TestListener)
+            .unwrap().unwrap();
+
+        // Renaming the restored file over a folder that is not empty fails
+        std::fs::remove_file(&blocked_live_path).unwrap();
+        std::fs::create_dir_all(blocked_live_path.join("inside")).unwrap();
+        write_file(&other_live_path, "changed", 0);
+
+        let result = restore_backed_up_files(
+            settings, vec![blocked_backup_path.clone(), other_backup_path], &TestListener);
+
+        let err_msgs = match result {
+            Err(FError(err_msgs)) => err_msgs,
+            result => panic!("Expected the failed restore to be reported, got {:?}", result)
+        };
+        assert_eq!(err_msgs.len(), 1);
+        assert!(err_msgs[0].contains("blocked.sav"), "{}", err_msgs[0]);
+        assert_eq!(std::fs::read_to_string(&other_live_path).unwrap(), "other");
+        // The temp file of the failed restore is not left behind for the watcher to see
+        let backup_folder = blocked_backup_path.parent().unwrap();
+        assert!(std::fs::read_dir(backup_folder).unwrap()
+            .all(|entry| !entry.unwrap().file_name().to_string_lossy().starts_with('_')));
+    }
+
+    #[test]
+    fn switching_from_timestamps_to_counters_prunes_the_oldest() {
+        let test_dir = TestDir::new("scheme");
+        let settings = test_settings(&test_dir);
+        let backup_folder = test_dir.join("dest/source");
+        std::fs::create_dir_all(&backup_folder).unwrap();
+        let timestamp_path = backup_folder.join("world.sav.2024-01-15T10-00-00");
+        let counter_path = backup_folder.join("world.sav.1");
+        write_file(&timestamp_path, "before the switch", 3600);
+        write_file(&counter_path, "after the switch", 60);
+
+        let doomed_paths = find_backups_over_count(settings, 1).unwrap();
+
+        assert_eq!(doomed_paths, vec![timestamp_path]);
+    }
+
+    #[test]
+    fn versions_are_listed_newest_first_across_version_schemes() {
+        let test_dir = TestDir::new("timeline");
+        let settings = test_settings(&test_dir);
+        let backup_folder = test_dir.join("dest/source");
+        std::fs::create_dir_all(&backup_folder).unwrap();
+        let timestamp_path = backup_folder.join("world.sav.2024-01-15T10-00-00");
+        let counter_path = backup_folder.join("world.sav.1");
+        write_file(&timestamp_path, "before the switch", 3600);
+        write_file(&counter_path, "after the switch", 60);
+
+        let version_paths = get_backed_up_versions_of_live_file(&settings, &test_dir.join("source/world.sav")).unwrap();
+
+        assert_eq!(version_paths, vec![counter_path, timestamp_path]);
+    }
+
+    #[test]
+    fn versions_of_both_schemes_sort_by_their_timestamps() {
+        let test_dir = TestDir::new("sort");
+        let backup_folder = test_dir.join("dest/source");
+        std::fs::create_dir_all(&backup_folder).unwrap();
+        // Compared pairwise, counter 1 is older than counter 2, which is older than the timestamp version, which is
+        // older than counter 1
+        let counter_2_path = backup_folder.join("world.sav.2");
+        let timestamp_path = backup_folder.join("world.sav.2024-01-15T10-00-00");
+        let counter_1_path = backup_folder.join("world.sav.1");
+        write_file(&counter_2_path, "oldest", 7200);
+        write_file(&timestamp_path, "middle", 3600);
+        write_file(&counter_1_path, "newest", 60);
+        let mut paths = vec![counter_1_path.clone(), timestamp_path.clone(), counter_2_path.clone()];
+
+        sort_backed_up_versions(&mut paths);
+
+        assert_eq!(paths, vec![counter_2_path, timestamp_path, counter_1_path]);
+    }
+
+    #[test]
+    fn change_is_reported_against_the_previous_version_in_another_date_folder() {
+        let test_dir = TestDir::new("change");
+        let mut settings = test_settings(&test_dir);
+        settings.backup_layout = BackupLayout::Dated;
+        let previous_path = test_dir.join("dest/2024-01-14/source/world.sav@1");
+        let backed_up_file_path = test_dir.join("dest/2024-01-15/source/world.sav@2");
+        std::fs::create_dir_all(previous_path.parent().unwrap()).unwrap();
+        std::fs::create_dir_all(backed_up_file_path.parent().unwrap()).unwrap();
+        write_file(&previous_path, "world", 3600);
+        write_file(&backed_up_file_path, "bigger world", 60);
+
+        let change = get_backup_change(&settings, &backed_up_file_path).unwrap().unwrap();
+
+        assert_eq!(change.size_delta, 7);
+        assert_eq!(change.modified_delta_sec, 3540);
+    }
+
+    #[test]
+    fn stray_files_are_skipped_when_pruning() {
+        let test_dir = TestDir::new("stray");
+        let mut settings = test_settings(&test_dir);
+        settings.backup_count = 2;
+        let backup_folder = test_dir.join("dest/source");
+        std::fs::create_dir_all(&backup_folder).unwrap();
+        let version_paths: Vec<PathBuf> = (1..=3)
+            .map(|version| backup_folder.join(format!("world.sav.{}", version)))
+            .collect();
+        for (index, version_path) in version_paths.iter().enumerate() {
+            write_file(version_path, "world", 3600 - index as u64 * 60);
+        }
+        // Put there by hand, without a version suffix
+        let stray_paths = vec![
+            backup_folder.join("world.sav"),
+            backup_folder.join("world.sav.old"),
+            backup_folder.join("readme.txt")
+        ];
+        for stray_path in &stray_paths {
+            write_file(stray_path, "stray", 7200);
+        }
+
+        let deleted_count = delete_old_backups(settings, &TestListener).unwrap();
+
+        assert_eq!(deleted_count, 1);
+        assert!(!version_paths[0].exists());
+        assert!(version_paths[1].exists() && version_paths[2].exists());
+        for stray_path in &stray_paths {
+            assert!(stray_path.exists(), "{}", stray_path.display());
+        }
+    }
+
+    #[test]
+    fn folder_with_an_added_older_file_is_not_backed_up_yet() {
+        let test_dir = TestDir::new("folder");
+        let mut settings = test_settings(&test_dir);
+        settings.backup_patterns[0].filename_pattern = "world".to_string();
+        settings.backup_patterns[0].directory_mode = true;
+        let live_folder_path = test_dir.join("source/world");
+        std::fs::create_dir_all(live_folder_path.join("chunks")).unwrap();
+        write_file(&live_folder_path.join("level.dat"), "level", 60);
+        write_file(&live_folder_path.join("chunks/0.dat"), "chunk", 3600);
+        backup_live_file(settings.clone(), live_folder_path.clone(), &TestListener).unwrap().unwrap();
+        assert!(live_file_has_backup(settings.clone(), live_folder_path.clone(), &TestListener).unwrap());
+
+        // Leaves the size of the other files and the timestamp of the newest one as they were
+        write_file(&live_folder_path.join("chunks/1.dat"), "chunk", 7200);
+
+        assert!(!live_file_has_backup(settings, live_folder_path, &TestListener).unwrap());
+    }
+
+    #[test]
+    fn only_the_last_dot_starts_the_version_suffix() {
+        let counter_7 = Some(("my.save.file", BackupVersion::Counter(7)));
+        assert_eq!(split_version_suffix(Path::new("my.save.file.7")), counter_7);
+        assert_eq!(split_version_suffix(Path::new("my.save.file@7")), counter_7);
+        assert_eq!(split_version_suffix(Path::new("my.save.7.file")), None);
+        assert_eq!(split_version_suffix(Path::new("my.save.file.")), None);
+        assert_eq!(split_version_suffix(Path::new(".7")), None);
+        let timestamp_path = PathBuf::from("dest/source/my.save.file.2024-01-15T10-00-00");
+        assert_eq!(strip_version_suffix_from_backed_up_file_path(&timestamp_path),
+            Some(PathBuf::from("dest/source/my.save.file")));
+    }
+
+    #[test]
+    fn backed_up_file_with_dots_maps_back_to_its_live_file() {
+        let test_dir = TestDir::new("dots");
+        let mut settings = test_settings(&test_dir);
+        settings.backup_patterns[0].filename_pattern = "*.file".to_string();
+        let backed_up_file_path = test_dir.join("dest/source/my.save.file.7");
+
+        let live_file_path = get_live_file_for_backed_up_file(settings.clone(), backed_up_file_path).unwrap();
+
+        assert_eq!(live_file_path, test_dir.join("source/my.save.file"));
+        // `my.file.save` does not match the pattern
+        assert!(get_live_file_for_backed_up_file(settings, test_dir.join("dest/source/my.file.save.7")).is_err());
+    }
+
+    #[test]
+    fn restored_file_is_not_backed_up_again() {
+        let test_dir = TestDir::new("restored");
+        let settings = test_settings(&test_dir);
+        let live_file_path = test_dir.join("source/world.sav");
+        write_file(&live_file_path, "world", 3600);
+        let old_backup_path = backup_live_file(settings.clone(), live_file_path.clone(), &TestListener)
+            .unwrap().unwrap();
+        write_file(&live_file_path, "changed", 60);
+        let new_backup_path = backup_live_file(settings.clone(), live_file_path.clone(), &TestListener)
+            .unwrap().unwrap();
+
+        restore_backed_up_files(settings.clone(), vec![old_backup_path.clone()], &TestListener).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&live_file_path).unwrap(), "world");
+        assert!(live_file_has_backup(settings.clone(), live_file_path.clone(), &TestListener).unwrap());
+        assert!(backup_all_changed_files(settings.clone(), &TestListener).0.backed_up_files.is_empty());
+        // Also once the backup it was restored from is pruned, leaving only a backup with other content
+        std::fs::remove_file(&old_backup_path).unwrap();
+        assert!(live_file_has_backup(settings.clone(), live_file_path, &TestListener).unwrap());
+        assert!(backup_all_changed_files(settings.clone(), &TestListener).0.backed_up_files.is_empty());
+        assert_eq!(get_backed_up_files(settings).0, vec![new_backup_path]);
+    }
+
+    #[test]
+    fn backups_of_hidden_files_are_listed_when_hidden_files_are_left_out() {
+        let test_dir = TestDir::new("hidden");
+        let mut settings = test_settings(&test_dir);
+        settings.include_hidden = false;
+        write_file(&test_dir.join("source/.hidden.sav"), "hidden", 3600);
+        let backed_up_file_path = test_dir.join("dest/source/.hidden.sav.1");
+        std::fs::create_dir_all(backed_up_file_path.parent().unwrap()).unwrap();
+        write_file(&backed_up_file_path, "hidden", 3600);
+
+        assert!(get_live_files(settings.clone()).unwrap().is_empty());
+        let (backed_up_file_paths, scan_err) = get_backed_up_files(settings);
+        assert!(scan_err.is_none());
+        assert_eq!(backed_up_file_paths, vec![backed_up_file_path]);
+    }
+
+    #[test]
+    fn destination_folders_of_backup_patterns_are_not_mirrored() {
+        let test_dir = TestDir::new("mirror");
+        let mut settings = test_settings(&test_dir);
+        let secondary_dest_path = test_dir.join("mirror");
+        let mut override_pattern = settings.backup_patterns[0].clone();
+        override_pattern.dest_override = Some(test_dir.join("dest/elsewhere"));
+        settings.backup_patterns.push(override_pattern);
+
+        assert_eq!(get_mirror_path(&settings, &secondary_dest_path, &test_dir.join("dest/source/world.sav.1")),
+            Some(test_dir.join("mirror/source/world.sav.1")));
+        let override_file_path = test_dir.join("dest/elsewhere/world.sav.1");
+        assert_eq!(get_mirror_path(&settings, &secondary_dest_path, &override_file_path), None);
+    }
+
+    #[test]
+    fn known_good_copy_is_kept_in_the_destination_of_its_backup() {
+        let test_dir = TestDir::new("known-good");
+        let mut settings = test_settings(&test_dir);
+        let override_dest_path = test_dir.join("elsewhere");
+        settings.backup_patterns[0].dest_override = Some(override_dest_path.clone());
+        let backed_up_file_path = override_dest_path.join("source/world.sav.1");
+        std::fs::create_dir_all(backed_up_file_path.parent().unwrap()).unwrap();
+        write_file(&backed_up_file_path, "world", 3600);
+
+        mark_known_good(settings.clone(), vec![backed_up_file_path.clone()]).unwrap();
+
+        let known_good_path = find_known_good_file(&settings, &backed_up_file_path).unwrap().unwrap();
+        assert!(known_good_path.starts_with(override_dest_path.join(".known_good")), "{}", known_good_path.display());
+        assert!(!test_dir.join("dest/.known_good").exists());
+    }
+
+    #[test]
+    fn trashing_the_same_version_twice_keeps_both() {
+        let test_dir = TestDir::new("trash");
+        let mut settings = test_settings(&test_dir);
+        settings.delete_to_trash = true;
+        let backed_up_file_path = test_dir.join("dest/source/world.sav.1");
+        std::fs::create_dir_all(backed_up_file_path.parent().unwrap()).unwrap();
+        write_file(&backed_up_file_path, "first", 3600);
+        delete_backed_up_files(&settings, vec![backed_up_file_path.clone()]).unwrap();
+        write_file(&backed_up_file_path, "second", 60);
+
+        delete_backed_up_files(&settings, vec![backed_up_file_path.clone()]).unwrap();
+
+        let trash_folder = test_dir.join("dest/.trash")
+            .join(Local::now().format(DATE_FOLDER_FORMAT).to_string())
+            .join("source");
+        assert_eq!(std::fs::read_to_string(trash_folder.join("world.sav.1")).unwrap(), "first");
+        assert_eq!(std::fs::read_to_string(trash_folder.join("world.sav.1 (2)")).unwrap(), "second");
+    }
+
+    #[test]
+    fn trash_is_deleted_first_to_stay_within_the_total_size() {
+        let test_dir = TestDir::new("total");
+        let mut settings = test_settings(&test_dir);
+        settings.max_total_size_mb = Some(1);
+        let backup_folder = test_dir.join("dest/source");
+        std::fs::create_dir_all(&backup_folder).unwrap();
+        let version_paths: Vec<PathBuf> = (1..=2)
+            .map(|version| backup_folder.join(format!("world.sav.{}", version)))
+            .collect();
+        write_file(&version_paths[0], &"a".repeat(400 * 1000), 3600);
+        write_file(&version_paths[1], &"b".repeat(400 * 1000), 60);
+        // Trashed yesterday, so that it is kept for the retention days
+        let yesterday = (Local::now() - chrono::Duration::days(1)).format(DATE_FOLDER_FORMAT).to_string();
+        let trashed_path = test_dir.join("dest/.trash").join(yesterday).join("source/world.sav.0");
+        std::fs::create_dir_all(trashed_path.parent().unwrap()).unwrap();
+        write_file(&trashed_path, &"c".repeat(400 * 1000), 7200);
+
+        let deleted_count = delete_old_backups(settings, &TestListener).unwrap();
+
+        assert_eq!(deleted_count, 0);
+        assert!(!trashed_path.exists());
+        assert!(version_paths.iter().all(|version_path| version_path.exists()));
+    }
 }
\ No newline at end of file
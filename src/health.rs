@@ -0,0 +1,87 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use valbak::file::{count_live_files_for_pattern, get_file_metadata, get_live_file_fingerprint, get_live_file_for_backed_up_file, get_live_files, get_newest_backed_up_files, PathExt, probe_dest_writable};
+use valbak::file::FileError::{FError, FFatal, FWarning};
+use valbak::settings::Settings;
+
+use crate::settings_file::check_settings;
+
+/// A live file that changed this much later than its newest backup is most likely no longer backed up
+const STALE_BACKUP_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Quickly checks whether `settings` actually back up anything, returning a description of each issue found: settings
+/// that do not work, a destination that cannot be written to, patterns that match no live files, and live files whose
+/// newest backup is missing or much older than the file
+pub fn check_health(settings: &Settings) -> Vec<String> {
+    let mut issues = Vec::new();
+
+    if let Err(err_msg) = check_settings(settings) {
+        issues.push(err_msg);
+    }
+
+    for dest_path in settings.dest_paths() {
+        if !dest_path.is_dir() {
+            issues.push(format!("Destination folder does not exist: {}", dest_path.str()));
+        } else if let Err(FWarning(mut errs)) | Err(FError(mut errs)) | Err(FFatal(mut errs)) =
+            probe_dest_writable(dest_path)
+        {
+            issues.append(&mut errs);
+        }
+    }
+
+    if !settings.has_enabled_patterns() {
+        issues.push("No file patterns are enabled, so nothing is backed up. Add them in File/Settings.".to_string());
+    }
+    for backup_pattern in settings.backup_patterns.iter().filter(|backup_pattern| backup_pattern.enabled) {
+        if backup_pattern.source_dir.is_dir() && matches!(count_live_files_for_pattern(settings, backup_pattern), Ok(0)) {
+            issues.push(format!("No live files match {}", backup_pattern.to_path().str()));
+        }
+    }
+
+    let live_file_paths = match get_live_files(settings.clone()) {
+        Ok(live_file_paths) => live_file_paths,
+        Err(FWarning(mut errs)) | Err(FError(mut errs)) | Err(FFatal(mut errs)) => {
+            issues.append(&mut errs);
+            return issues;
+        }
+    };
+    let (newest_backed_up_file_paths, scan_err) = get_newest_backed_up_files(settings.clone());
+    if let Some(FWarning(mut errs)) | Some(FError(mut errs)) | Some(FFatal(mut errs)) = scan_err {
+        issues.append(&mut errs);
+    }
+    let mut newest_backed_up_file_paths_by_live_file_paths: HashMap<PathBuf, PathBuf> = HashMap::new();
+    for backed_up_file_path in newest_backed_up_file_paths {
+        if let Ok(live_file_path) = get_live_file_for_backed_up_file(settings.clone(), backed_up_file_path.clone()) {
+            newest_backed_up_file_paths_by_live_file_paths.insert(live_file_path, backed_up_file_path);
+        }
+    }
+    for live_file_path in live_file_paths {
+        let backed_up_file_path = match newest_backed_up_file_paths_by_live_file_paths.get(&live_file_path) {
+            Some(backed_up_file_path) => backed_up_file_path,
+            None => {
+                issues.push(format!("{} has no backup", live_file_path.str()));
+                continue;
+            }
+        };
+        // Backups keep the timestamp of the live file they were backed up from
+        if let (Ok((_, live_file_modified)), Ok((_, backed_up_file_modified))) =
+            (get_live_file_fingerprint(&live_file_path), get_file_metadata(backed_up_file_path))
+        {
+            let is_stale = live_file_modified.duration_since(backed_up_file_modified)
+                .map_or(false, |since_backup| since_backup > STALE_BACKUP_AGE);
+            if is_stale {
+                issues.push(format!("The newest backup of {} is more than a day older than the file", live_file_path.str()));
+            }
+        }
+    }
+
+    issues
+}
\ No newline at end of file
@@ -0,0 +1,58 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use log::{info, warn};
+
+use crate::file::PathExt;
+use crate::settings::Settings;
+
+/// The environment variable holding the path of the new backed up file
+pub const BACKED_UP_PATH_VAR: &str = "VALBAK_BACKED_UP_PATH";
+/// The environment variable holding the path of the live file that was backed up
+pub const LIVE_PATH_VAR: &str = "VALBAK_LIVE_PATH";
+
+/// Runs the post backup command of `settings`, if any, for the new backed up file `backed_up_file_path` of
+/// `live_file_path`, passing both paths in the environment. The command runs in the shell on a separate thread, so
+/// that a slow command never holds up the next backup, and its exit status is logged.
+pub fn run_post_backup_command(settings: &Settings, live_file_path: &Path, backed_up_file_path: &Path) {
+    let post_backup_command = match &settings.post_backup_command {
+        Some(post_backup_command) if !post_backup_command.trim().is_empty() => post_backup_command.clone(),
+        _ => return
+    };
+    let mut command = shell_command(&post_backup_command);
+    command
+        .env(BACKED_UP_PATH_VAR, backed_up_file_path)
+        .env(LIVE_PATH_VAR, live_file_path)
+        .stdin(Stdio::null());
+    let backed_up_file_path = backed_up_file_path.to_path_buf();
+    std::thread::spawn(move || {
+        match command.status() {
+            Ok(status) if status.success() =>
+                info!("Post backup command for {} finished: {}", backed_up_file_path.str(), status),
+            Ok(status) =>
+                warn!("Post backup command for {} failed: {}", backed_up_file_path.str(), status),
+            Err(err) =>
+                warn!("Error running post backup command for {}: {}", backed_up_file_path.str(), err)
+        }
+    });
+}
+
+#[cfg(windows)]
+fn shell_command(command_line: &str) -> Command {
+    let mut command = Command::new("cmd");
+    command.arg("/C").arg(command_line);
+    command
+}
+
+#[cfg(not(windows))]
+fn shell_command(command_line: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(command_line);
+    command
+}
\ No newline at end of file
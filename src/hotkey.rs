@@ -0,0 +1,216 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use fltk::app;
+use log::*;
+
+use crate::UiMessage;
+
+/// A key combination such as `Ctrl+Alt+B`, parsed from `Settings::backup_hotkey`
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(not(windows), allow(dead_code))]
+pub struct Hotkey {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub win: bool,
+    pub key: HotkeyKey,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(not(windows), allow(dead_code))]
+pub enum HotkeyKey {
+    /// A letter or digit, in upper case
+    Char(char),
+    /// F1 to F24
+    Function(u8),
+}
+
+/// Parses a key combination of modifiers and one letter, digit or function key, separated by `+`, e.g. `Ctrl+Alt+B`
+/// or `Shift+F9`. At least one modifier is required, since the key would otherwise be taken from every other
+/// application, including the game.
+pub fn parse_hotkey(hotkey_str: &str) -> Result<Hotkey, String> {
+    let mut ctrl = false;
+    let mut alt = false;
+    let mut shift = false;
+    let mut win = false;
+    let mut key = None;
+    for part in hotkey_str.split('+').map(|part| part.trim()) {
+        match part.to_ascii_lowercase().as_str() {
+            "ctrl" | "control" => ctrl = true,
+            "alt" => alt = true,
+            "shift" => shift = true,
+            "win" | "super" => win = true,
+            _ if key.is_some() =>
+                return Err(format!("Hotkey must have only one key besides the modifiers: {}", hotkey_str)),
+            key_str => key = Some(parse_hotkey_key(key_str)
+                .ok_or_else(|| format!("Hotkey key must be a letter, a digit or F1 to F24: {}", hotkey_str))?)
+        }
+    }
+    let key = key.ok_or_else(|| format!("Hotkey is missing a key besides the modifiers: {}", hotkey_str))?;
+    if !(ctrl || alt || shift || win) {
+        return Err(format!("Hotkey must include Ctrl, Alt, Shift or Win: {}", hotkey_str));
+    }
+    Ok(Hotkey { ctrl, alt, shift, win, key })
+}
+
+fn parse_hotkey_key(key_str: &str) -> Option<HotkeyKey> {
+    let mut chars = key_str.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphanumeric() => Some(HotkeyKey::Char(c.to_ascii_uppercase())),
+        (Some('f'), Some(_)) => match key_str[1..].parse::<u8>() {
+            Ok(n) if (1..=24).contains(&n) => Some(HotkeyKey::Function(n)),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+/// The system-wide registration of the backup hotkey, which sends `UiMessage::BackupNow` whenever the key combination
+/// is pressed, even while another application has the focus. Dropping it unregisters the hotkey.
+#[cfg_attr(not(windows), allow(dead_code))]
+pub struct HotkeyRegistration {
+    pub hotkey_str: String,
+    #[cfg(windows)]
+    thread_id: u32,
+}
+
+/// Registers `hotkey_str` system-wide, returning `None` when it is invalid, already taken by another application, or
+/// when this platform has no global hotkeys, in which case the hotkey does nothing and only a warning is logged
+pub fn register_backup_hotkey(hotkey_str: &str, ui_thread_tx: app::Sender<UiMessage>) -> Option<HotkeyRegistration> {
+    let hotkey = match parse_hotkey(hotkey_str) {
+        Ok(hotkey) => hotkey,
+        Err(err_msg) => {
+            warn!("{}", err_msg);
+            return None;
+        }
+    };
+    #[cfg(windows)]
+    return windows::register(hotkey_str, &hotkey, ui_thread_tx);
+    #[cfg(not(windows))]
+    {
+        let _ = (hotkey, ui_thread_tx);
+        warn!("Global hotkeys are not available on this platform, so {} does nothing", hotkey_str);
+        None
+    }
+}
+
+#[cfg(windows)]
+impl Drop for HotkeyRegistration {
+    fn drop(&mut self) {
+        windows::unregister(self.thread_id);
+    }
+}
+
+/// Registers the hotkey for a thread without a window, which receives `WM_HOTKEY` in its message queue while it waits
+/// for messages, until it is posted `WM_QUIT`
+#[cfg(windows)]
+mod windows {
+    use std::ffi::c_void;
+    use std::ptr::null_mut;
+    use std::sync::mpsc;
+
+    use fltk::app;
+    use log::*;
+
+    use crate::hotkey::{Hotkey, HotkeyKey, HotkeyRegistration};
+    use crate::UiMessage;
+
+    const MOD_ALT: u32 = 0x1;
+    const MOD_CONTROL: u32 = 0x2;
+    const MOD_SHIFT: u32 = 0x4;
+    const MOD_WIN: u32 = 0x8;
+    const MOD_NOREPEAT: u32 = 0x4000;
+    const VK_F1: u32 = 0x70;
+    const WM_QUIT: u32 = 0x12;
+    const WM_HOTKEY: u32 = 0x312;
+    const PM_NOREMOVE: u32 = 0x0;
+    const HOTKEY_ID: i32 = 1;
+
+    #[repr(C)]
+    struct Msg {
+        hwnd: *mut c_void,
+        message: u32,
+        w_param: usize,
+        l_param: isize,
+        time: u32,
+        pt_x: i32,
+        pt_y: i32,
+    }
+
+    #[link(name = "user32")]
+    extern "system" {
+        fn RegisterHotKey(hwnd: *mut c_void, id: i32, modifiers: u32, vk: u32) -> i32;
+        fn UnregisterHotKey(hwnd: *mut c_void, id: i32) -> i32;
+        fn GetMessageW(msg: *mut Msg, hwnd: *mut c_void, msg_filter_min: u32, msg_filter_max: u32) -> i32;
+        fn PeekMessageW(msg: *mut Msg, hwnd: *mut c_void, msg_filter_min: u32, msg_filter_max: u32, remove: u32) -> i32;
+        fn PostThreadMessageW(thread_id: u32, msg: u32, w_param: usize, l_param: isize) -> i32;
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetCurrentThreadId() -> u32;
+        fn GetLastError() -> u32;
+    }
+
+    pub fn register(hotkey_str: &str, hotkey: &Hotkey, ui_thread_tx: app::Sender<UiMessage>)
+        -> Option<HotkeyRegistration>
+    {
+        let mut modifiers = MOD_NOREPEAT;
+        if hotkey.ctrl { modifiers |= MOD_CONTROL; }
+        if hotkey.alt { modifiers |= MOD_ALT; }
+        if hotkey.shift { modifiers |= MOD_SHIFT; }
+        if hotkey.win { modifiers |= MOD_WIN; }
+        let vk = match hotkey.key {
+            // The virtual key codes of letters and digits are their upper case ASCII codes
+            HotkeyKey::Char(c) => c as u32,
+            HotkeyKey::Function(n) => VK_F1 + n as u32 - 1,
+        };
+
+        let (registered_tx, registered_rx) = mpsc::channel::<Result<u32, u32>>();
+        let thread_hotkey_str = hotkey_str.to_string();
+        std::thread::spawn(move || {
+            let mut msg = Msg { hwnd: null_mut(), message: 0, w_param: 0, l_param: 0, time: 0, pt_x: 0, pt_y: 0 };
+            unsafe {
+                if RegisterHotKey(null_mut(), HOTKEY_ID, modifiers, vk) == 0 {
+                    let _ = registered_tx.send(Err(GetLastError()));
+                    return;
+                }
+                // Creates the message queue before anyone posts WM_QUIT to it
+                PeekMessageW(&mut msg, null_mut(), 0, 0, PM_NOREMOVE);
+                let _ = registered_tx.send(Ok(GetCurrentThreadId()));
+                while GetMessageW(&mut msg, null_mut(), 0, 0) > 0 {
+                    if msg.message == WM_HOTKEY {
+                        debug!("Hotkey {} pressed", thread_hotkey_str);
+                        ui_thread_tx.send(UiMessage::BackupNow);
+                    }
+                }
+                UnregisterHotKey(null_mut(), HOTKEY_ID);
+            }
+        });
+        match registered_rx.recv() {
+            Ok(Ok(thread_id)) => {
+                info!("Registered the backup hotkey {}", hotkey_str);
+                Some(HotkeyRegistration { hotkey_str: hotkey_str.to_string(), thread_id })
+            }
+            Ok(Err(err_code)) => {
+                warn!("Could not register the backup hotkey {}, it may be taken by another application (error {})",
+                    hotkey_str, err_code);
+                None
+            }
+            Err(_) => {
+                warn!("Could not register the backup hotkey {}", hotkey_str);
+                None
+            }
+        }
+    }
+
+    pub fn unregister(thread_id: u32) {
+        unsafe {
+            PostThreadMessageW(thread_id, WM_QUIT, 0, 0);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,136 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::file::{FileError, get_backed_up_files, get_backed_up_version, get_file_metadata,
+    get_live_file_for_backed_up_file, PathExt};
+use crate::file::FileError::FError;
+use crate::settings::Settings;
+
+/// One backed up file, as exported for scripts and audits
+#[derive(Serialize, Clone, Debug)]
+pub struct InventoryEntry {
+    /// The live file that the backed up file is a version of
+    pub original_path: String,
+    pub backed_up_path: String,
+    pub version: String,
+    pub size: u64,
+    /// The timestamp of the backed up file, which is that of the live file when it was backed up, in RFC 3339 format
+    pub modified: String,
+    /// The SHA-256 of the backed up file as stored, i.e. encrypted when encryption was on, in lowercase hex
+    pub sha256: String,
+}
+
+/// How an exported inventory is written
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InventoryFormat {
+    /// One JSON object per line
+    JsonLines,
+    /// Comma separated values with a header row
+    Csv,
+}
+
+impl InventoryFormat {
+    /// The format of an export to `path`, chosen by its extension. Anything but `.csv` is written as JSON lines.
+    pub fn for_path(path: &Path) -> InventoryFormat {
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("csv") => InventoryFormat::Csv,
+            _ => InventoryFormat::JsonLines
+        }
+    }
+}
+
+/// Lists every backed up file in the destination specified by `settings`, hashing each one
+pub fn get_inventory(settings: &Settings) -> Result<Vec<InventoryEntry>, FileError> {
+    let (mut backed_up_file_paths, scan_err) = get_backed_up_files(settings.clone());
+    if let Some(scan_err) = scan_err {
+        // An export that silently misses files would be worse than none
+        return Err(scan_err);
+    }
+    backed_up_file_paths.sort();
+    let mut inventory = Vec::new();
+    for backed_up_file_path in backed_up_file_paths {
+        let version = match get_backed_up_version(&backed_up_file_path) {
+            Some(version) => version,
+            None => continue
+        };
+        let original_path = get_live_file_for_backed_up_file(settings.clone(), backed_up_file_path.clone())?;
+        let (metadata, modified) = get_file_metadata(&backed_up_file_path)?;
+        let modified: DateTime<Local> = modified.into();
+        inventory.push(InventoryEntry {
+            original_path: original_path.str().to_string(),
+            backed_up_path: backed_up_file_path.str().to_string(),
+            version: version.to_string(),
+            size: metadata.len(),
+            modified: modified.to_rfc3339(),
+            sha256: hash_file(&backed_up_file_path)?,
+        });
+    }
+    Ok(inventory)
+}
+
+/// Returns the SHA-256 of the content of `file_path` in lowercase hex
+pub fn hash_file(file_path: &PathBuf) -> Result<String, FileError> {
+    let hash_err = |err| FError(vec![format!("Error reading {}: {}", file_path.str(), err)]);
+    let mut reader = BufReader::new(File::open(file_path).map_err(hash_err)?);
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let len = reader.read(&mut buf).map_err(hash_err)?;
+        if len == 0 {
+            break;
+        }
+        hasher.update(&buf[..len]);
+    }
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Writes `inventory` to `to_path` in `format`, replacing any existing file
+pub fn write_inventory(inventory: &[InventoryEntry], to_path: &Path, format: InventoryFormat) -> Result<(), FileError> {
+    let mut inventory_str = String::new();
+    match format {
+        InventoryFormat::JsonLines => {
+            for entry in inventory {
+                match serde_json::to_string(entry) {
+                    Ok(entry_str) => inventory_str.push_str(&entry_str),
+                    Err(err) => return Err(FError(vec![format!("Error creating inventory: {}", err)]))
+                }
+                inventory_str.push('\n');
+            }
+        }
+        InventoryFormat::Csv => {
+            inventory_str.push_str("original_path,backed_up_path,version,size,modified,sha256\n");
+            for entry in inventory {
+                let fields = [
+                    &entry.original_path, &entry.backed_up_path, &entry.version, &entry.size.to_string(),
+                    &entry.modified, &entry.sha256
+                ];
+                let row: Vec<String> = fields.iter().map(|field| quote_csv_field(field)).collect();
+                inventory_str.push_str(&row.join(","));
+                inventory_str.push('\n');
+            }
+        }
+    }
+    let write_err = |err| FError(vec![format!("Error writing {}: {}", to_path.str(), err)]);
+    let mut inventory_file = File::create(to_path).map_err(write_err)?;
+    inventory_file.write_all(inventory_str.as_bytes()).map_err(write_err)
+}
+
+/// Quotes `field` when it holds a character that would otherwise end it early
+fn quote_csv_field(field: &str) -> String {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
\ No newline at end of file
@@ -0,0 +1,21 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! The backup engine of Valbak: settings, and backing up, pruning and restoring files. It has no user interface and
+//! locates nothing on its own, so every path it works with is passed in by the application.
+
+pub mod archive;
+pub mod crypt;
+pub mod dest_marker;
+pub mod duplicates;
+pub mod file;
+pub mod hook;
+pub mod inventory;
+pub mod locked_file;
+pub mod manifest;
+pub mod mirror;
+pub mod settings;
+pub mod sidecar;
\ No newline at end of file
@@ -0,0 +1,67 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::path::Path;
+
+/// Copies a live file that the game holds open in a way that makes a regular copy fail
+pub trait LockedFileReader {
+    /// Copies `from_path` to `to_path`, returning the number of bytes copied
+    fn copy_locked(&self, from_path: &Path, to_path: &Path) -> std::io::Result<u64>;
+
+    /// Reads the whole content of `from_path`, for backups that are written other than by copying, such as encrypted
+    fn read_locked(&self, from_path: &Path) -> std::io::Result<Vec<u8>>;
+}
+
+/// Reads the file while allowing other processes to keep writing to it, which a Windows file copy does not allow. Files
+/// opened without any sharing at all still cannot be read.
+#[cfg(windows)]
+struct SharedReadCopier;
+
+#[cfg(windows)]
+impl LockedFileReader for SharedReadCopier {
+    fn copy_locked(&self, from_path: &Path, to_path: &Path) -> std::io::Result<u64> {
+        let mut from_file = open_shared(from_path)?;
+        let mut to_file = std::fs::File::create(to_path)?;
+        std::io::copy(&mut from_file, &mut to_file)
+    }
+
+    fn read_locked(&self, from_path: &Path) -> std::io::Result<Vec<u8>> {
+        use std::io::Read;
+
+        let mut content = Vec::new();
+        open_shared(from_path)?.read_to_end(&mut content)?;
+        Ok(content)
+    }
+}
+
+#[cfg(windows)]
+fn open_shared(path: &Path) -> std::io::Result<std::fs::File> {
+    use std::fs::OpenOptions;
+    use std::os::windows::fs::OpenOptionsExt;
+
+    const FILE_SHARE_ALL: u32 = 0x1 | 0x2 | 0x4;  // FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE
+    OpenOptions::new()
+        .read(true)
+        .share_mode(FILE_SHARE_ALL)
+        .open(path)
+}
+
+/// Returns the reader for locked files on this platform, if it needs one
+pub fn get_locked_file_reader() -> Option<Box<dyn LockedFileReader>> {
+    #[cfg(windows)]
+    return Some(Box::new(SharedReadCopier));
+    #[cfg(not(windows))]
+    return None;
+}
+
+/// Checks whether `err` means that another process has the file open in a way that prevents copying it
+pub fn is_locked_file_error(err: &std::io::Error) -> bool {
+    #[cfg(windows)]
+    const LOCKED_FILE_ERRORS: [i32; 2] = [32, 33];  // ERROR_SHARING_VIOLATION, ERROR_LOCK_VIOLATION
+    #[cfg(not(windows))]
+    const LOCKED_FILE_ERRORS: [i32; 0] = [];
+    err.raw_os_error().map_or(false, |code| LOCKED_FILE_ERRORS.contains(&code))
+}
\ No newline at end of file
@@ -6,55 +6,112 @@
 
 use std::cell::RefCell;
 use std::ops::Deref;
-use std::path::Path;
-use std::process::exit;
+use std::path::{Path, PathBuf};
+use std::process::{Command, exit};
 use std::sync::{Arc, mpsc};
 use std::thread::JoinHandle;
+use std::time::Instant;
 
 use file_rotate::{ContentLimit, FileRotate, suffix::CountSuffix};
 use file_rotate::compression::Compression;
 use fltk::app;
-use fltk::dialog::{alert_default, choice_default, message_default};
+use fltk::dialog::{alert_default, choice_default, FileChooser, FileChooserType, input_default, message_default, password_default};
 use fltk::prelude::{WidgetExt, WindowExt};
 use log::*;
 use parking_lot::ReentrantMutex;
 use simplelog::{ColorChoice, CombinedLogger, ConfigBuilder, LevelFilter, TerminalMode, TermLogger, WriteLogger};
+use valbak::crypt;
+use valbak::duplicates::{DuplicateGroup, find_duplicate_backups};
+use valbak::file::{backup_all_changed_files, backup_all_live_files, backup_live_file, BackupComparison, BackupListener, BackupSweep, compare_backed_up_file, delete_backed_up_files, delete_old_backups, delete_old_backups_of_live_file, dry_run_backup, empty_trash, FileError, find_backups_over_count, find_known_good_file, get_backed_up_files, get_live_files, get_newest_backed_up_files, get_orphaned_backed_up_files, is_operation_in_progress, mark_known_good, PathExt, remove_temp_files_in_progress, rename_backups_to_version_separator, restore_backed_up_files, restore_backed_up_files_as_copies, strip_version_suffix_from_backed_up_file_path};
+use valbak::inventory::{get_inventory, InventoryFormat, write_inventory};
+use valbak::manifest::rebuild_manifest;
+use valbak::settings::{normalize_path, Settings};
+use valbak::sidecar::{read_note, write_note};
 
 use FileError::{FError, FFatal, FWarning};
 use main_win::MainWindow;
+use pattern_test_win::PatternTestWindow;
+use version_timeline_win::VersionTimelineWindow;
+use settings_file_win::SettingsFileWindow;
 use settings_win::SettingsWindow;
 use SettingsError::{SError, SNotFound, SWarning};
 use UiMessage::*;
 
-use crate::file::{backup_all_changed_files, delete_backed_up_files, delete_old_backups, FileError, get_backed_up_files, get_live_files, PathExt, restore_backed_up_files};
-use crate::settings::{get_settings, get_settings_file_path, Settings, SettingsError, write_settings};
+use crate::health::check_health;
+use crate::hotkey::{HotkeyRegistration, register_backup_hotkey};
+use crate::settings_file::{check_settings, get_default_settings, get_settings, get_settings_file_path, parse_settings, read_settings_file_str, SettingsError, validate_settings, write_settings};
 use crate::settings_win::SettingsWinError;
-use crate::watcher::{BackupMessage, BackupStatus, start_backup_thread, stop_backup_thread};
+use crate::watcher::{BackupMessage, BackupStatus, get_backed_up_status, HEARTBEAT_INTERVAL, HEARTBEAT_TIMEOUT, note_own_dest_change, start_backup_thread, stop_backup_thread, WatcherState};
 
-mod settings;
+mod settings_file;
 mod main_win;
 mod settings_win;
+mod settings_file_win;
+mod pattern_test_win;
+mod version_timeline_win;
 mod win_common;
 mod watcher;
-mod file;
+mod health;
+mod hotkey;
 
 pub enum UiMessage {
     Alert(String),
     AlertQuit(String),
     AppQuit,
     MenuSettings,
+    MenuExportInventory,
+    MenuFindDuplicates,
+    /// The sets of identical backups found for [`MenuFindDuplicates`] on a worker thread, with the files that could
+    /// not be compared
+    DuplicatesFound(Vec<DuplicateGroup>, Option<FileError>),
+    MenuForceFullBackup,
+    MenuRebuildIndex,
+    MenuEmptyTrash,
     MenuQuit,
     MenuDocumentation,
+    MenuDiagnostics,
+    MenuEditSettingsFile,
+    MenuOpenConfigFolder,
     MenuAbout,
     SettingsBackupDestChoose,
+    SettingsTest,
+    SettingsTestPattern,
+    SettingsToggleEnabled,
+    /// A backup folder was edited in the settings window
+    SettingsRefreshHealth,
+    SettingsResetDefaults,
+    SettingsApply,
     SettingsOk,
     SettingsQuit,
+    SettingsFileSave,
+    SettingsFileCancel,
+    PatternTestChanged,
+    PatternTestClose,
+    ShowVersionTimeline,
+    VersionTimelineRestore,
+    VersionTimelineClose,
     RestoreBackup,
+    RestoreAsCopy,
+    RestoreAllLatest,
     DeleteBackup,
+    CompareBackup,
+    MarkKnownGood,
+    RestoreKnownGood,
+    NoteBackup,
+    PurgeBackups,
+    BackupNow,
+    BackupSelectedLive,
+    BackupSummary(BackupSweep),
+    /// Sent by the worker thread of [`start_backup_worker`] once its backups are done
+    BackupFinished(BackupOutcome),
+    ShowHealthDetails,
     PushStatus(String),
-    PopStatus,
+    /// Replaces the status of the latest [`PushStatus`]
     SetStatus(String),
+    PopStatus,
+    SetWatcherState(WatcherState),
     RefreshFilesLists,
+    CheckHeartbeat,
 }
 
 impl Clone for UiMessage {
@@ -64,18 +121,54 @@ impl Clone for UiMessage {
             AlertQuit(alert_msg) => AlertQuit(alert_msg.clone()),
             AppQuit => AppQuit,
             MenuSettings => MenuSettings,
+            MenuExportInventory => MenuExportInventory,
+            MenuFindDuplicates => MenuFindDuplicates,
+            DuplicatesFound(duplicate_groups, err) => DuplicatesFound(duplicate_groups.clone(), err.clone()),
+            MenuForceFullBackup => MenuForceFullBackup,
+            MenuRebuildIndex => MenuRebuildIndex,
+            MenuEmptyTrash => MenuEmptyTrash,
             MenuQuit => MenuQuit,
             MenuDocumentation => MenuDocumentation,
+            MenuDiagnostics => MenuDiagnostics,
+            MenuEditSettingsFile => MenuEditSettingsFile,
+            MenuOpenConfigFolder => MenuOpenConfigFolder,
             MenuAbout => MenuAbout,
             SettingsBackupDestChoose => SettingsBackupDestChoose,
+            SettingsTest => SettingsTest,
+            SettingsTestPattern => SettingsTestPattern,
+            SettingsToggleEnabled => SettingsToggleEnabled,
+            SettingsRefreshHealth => SettingsRefreshHealth,
+            SettingsResetDefaults => SettingsResetDefaults,
+            SettingsApply => SettingsApply,
             SettingsOk => SettingsOk,
             SettingsQuit => SettingsQuit,
+            SettingsFileSave => SettingsFileSave,
+            SettingsFileCancel => SettingsFileCancel,
+            PatternTestChanged => PatternTestChanged,
+            PatternTestClose => PatternTestClose,
+            ShowVersionTimeline => ShowVersionTimeline,
+            VersionTimelineRestore => VersionTimelineRestore,
+            VersionTimelineClose => VersionTimelineClose,
             RestoreBackup => RestoreBackup,
+            RestoreAsCopy => RestoreAsCopy,
+            RestoreAllLatest => RestoreAllLatest,
             DeleteBackup => DeleteBackup,
-            SetStatus(status) => SetStatus(status.clone()),
+            CompareBackup => CompareBackup,
+            MarkKnownGood => MarkKnownGood,
+            RestoreKnownGood => RestoreKnownGood,
+            NoteBackup => NoteBackup,
+            PurgeBackups => PurgeBackups,
+            BackupNow => BackupNow,
+            BackupSelectedLive => BackupSelectedLive,
+            BackupSummary(sweep) => BackupSummary(sweep.clone()),
+            BackupFinished(outcome) => BackupFinished(outcome.clone()),
+            ShowHealthDetails => ShowHealthDetails,
+            SetWatcherState(watcher_state) => SetWatcherState(watcher_state.clone()),
             PushStatus(status) => PushStatus(status.clone()),
+            SetStatus(status) => SetStatus(status.clone()),
             PopStatus => PopStatus,
             RefreshFilesLists => RefreshFilesLists,
+            CheckHeartbeat => CheckHeartbeat,
         }
     }
 }
@@ -87,29 +180,159 @@ impl ToString for UiMessage {
             AlertQuit(alert_msg)     => format!("AlertQuit({})", alert_msg),
             AppQuit                  => "AppQuit".to_string(),
             MenuSettings             => "MenuSettings".to_string(),
+            MenuExportInventory      => "MenuExportInventory".to_string(),
+            MenuFindDuplicates       => "MenuFindDuplicates".to_string(),
+            DuplicatesFound(groups, _) => format!("DuplicatesFound({} sets)", groups.len()),
+            MenuForceFullBackup      => "MenuForceFullBackup".to_string(),
+            MenuRebuildIndex         => "MenuRebuildIndex".to_string(),
+            MenuEmptyTrash           => "MenuEmptyTrash".to_string(),
             MenuQuit                 => "MenuQuit".to_string(),
             MenuDocumentation        => "MenuDocumentation".to_string(),
+            MenuDiagnostics          => "MenuDiagnostics".to_string(),
+            MenuEditSettingsFile     => "MenuEditSettingsFile".to_string(),
+            MenuOpenConfigFolder     => "MenuOpenConfigFolder".to_string(),
             MenuAbout                => "MenuAbout".to_string(),
             SettingsBackupDestChoose => "SettingsBackupDestChoose".to_string(),
+            SettingsTest             => "SettingsTest".to_string(),
+            SettingsTestPattern      => "SettingsTestPattern".to_string(),
+            SettingsToggleEnabled    => "SettingsToggleEnabled".to_string(),
+            SettingsRefreshHealth    => "SettingsRefreshHealth".to_string(),
+            SettingsResetDefaults    => "SettingsResetDefaults".to_string(),
+            SettingsApply            => "SettingsApply".to_string(),
             SettingsOk               => "SettingsOk".to_string(),
             SettingsQuit             => "SettingsQuit".to_string(),
+            SettingsFileSave         => "SettingsFileSave".to_string(),
+            SettingsFileCancel       => "SettingsFileCancel".to_string(),
+            PatternTestChanged       => "PatternTestChanged".to_string(),
+            PatternTestClose         => "PatternTestClose".to_string(),
+            ShowVersionTimeline      => "ShowVersionTimeline".to_string(),
+            VersionTimelineRestore   => "VersionTimelineRestore".to_string(),
+            VersionTimelineClose     => "VersionTimelineClose".to_string(),
             RestoreBackup            => "RestoreBackup".to_string(),
+            RestoreAsCopy            => "RestoreAsCopy".to_string(),
+            RestoreAllLatest         => "RestoreAllLatest".to_string(),
             DeleteBackup             => "DeleteBackup".to_string(),
+            CompareBackup            => "CompareBackup".to_string(),
+            MarkKnownGood            => "MarkKnownGood".to_string(),
+            RestoreKnownGood         => "RestoreKnownGood".to_string(),
+            NoteBackup               => "NoteBackup".to_string(),
+            PurgeBackups             => "PurgeBackups".to_string(),
+            BackupNow                => "BackupNow".to_string(),
+            BackupSelectedLive       => "BackupSelectedLive".to_string(),
+            BackupSummary(sweep)     => format!("BackupSummary({})", sweep),
+            BackupFinished(outcome)  => format!("BackupFinished({} statuses)", outcome.statuses.len()),
+            ShowHealthDetails        => "ShowHealthDetails".to_string(),
             PushStatus(status)       => format!("PushStatus({})", status),
-            PopStatus                => "PopStatus".to_string(),
             SetStatus(status)        => format!("SetStatus({})", status),
-            RefreshFilesLists        => "RefreshFilesLists".to_string()
+            PopStatus                => "PopStatus".to_string(),
+            SetWatcherState(state)   => format!("SetWatcherState({})", state),
+            RefreshFilesLists        => "RefreshFilesLists".to_string(),
+            CheckHeartbeat           => "CheckHeartbeat".to_string()
         }
     }
 }
 
+/// Shows the progress of backups in the main window's status bar, from whichever thread runs them
+#[derive(Clone)]
+pub struct UiBackupListener(pub app::Sender<UiMessage>);
+
+impl BackupListener for UiBackupListener {
+    fn push_status(&self, status: String) {
+        self.0.send(PushStatus(status));
+    }
+
+    fn set_status(&self, status: String) {
+        self.0.send(SetStatus(status));
+    }
+
+    fn pop_status(&self) {
+        self.0.send(PopStatus);
+    }
+
+    fn confirm_overwrite_newer(&self, live_file_path: &Path, backed_up_file_path: &Path) -> bool {
+        choice_default(
+            format!("The live file {} is newer than its backup {}. Overwrite it anyway?",
+                live_file_path.str(), backed_up_file_path.file_name_str()).as_str(),
+            "Skip", "Overwrite", ""
+        ) == 1
+    }
+
+    fn confirm_restore_target(&self, backed_up_file_path: &Path, live_file_path: &Path) -> Option<PathBuf> {
+        let mut target_file_path = live_file_path.to_path_buf();
+        loop {
+            let folder_note = if target_file_path.parent().map_or(false, |folder_path| folder_path.is_dir()) {
+                ""
+            } else {
+                "\nThe folder does not exist yet and will be created."
+            };
+            match choice_default(
+                format!("Restore {} to {}?{}",
+                    backed_up_file_path.file_name_str(), target_file_path.str(), folder_note).as_str(),
+                "Skip", "Restore", "Choose Other..."
+            ) {
+                1 => return Some(target_file_path),
+                // Asks again with the chosen target, or with the previous one when the chooser was cancelled
+                2 => {
+                    if let Some(chosen_file_path) = choose_restore_target_file(&target_file_path) {
+                        target_file_path = chosen_file_path;
+                    }
+                }
+                _ => return None
+            }
+        }
+    }
+
+    fn choose_unknown_restore_target(&self, backed_up_file_path: &Path) -> Option<PathBuf> {
+        match choice_default(
+            format!("Which file {} was backed up from is unknown, since no backup pattern matches it. \
+                Choose where to restore it to?", backed_up_file_path.file_name_str()).as_str(),
+            "Skip", "Choose...", ""
+        ) {
+            1 => {  // Choose...
+                let live_file_name = strip_version_suffix_from_backed_up_file_path(&backed_up_file_path.to_path_buf())
+                    .map_or(backed_up_file_path.file_name_str().to_string(), |path| path.file_name_str().to_string());
+                choose_restore_target_file(&dirs::document_dir().unwrap_or_default().join(live_file_name))
+            }
+            _ => None
+        }
+    }
+}
+
+/// What the backups run by [`start_backup_worker`] did, for the UI thread to show once they are done
+#[derive(Clone, Default)]
+pub struct BackupOutcome {
+    /// Shown in the main window as if the backup thread had reported them
+    statuses: Vec<BackupStatus>,
+    /// The errors of backing up changed files, see [`handle_backup_error`]
+    backup_err: Option<FileError>,
+    /// Any other errors, e.g. of pruning
+    errs: Vec<FileError>,
+    /// Shown in a dialog each, e.g. for a selected live file that was not backed up
+    messages: Vec<String>,
+    /// Shown in a dialog once the outcome is handled
+    summary: Option<BackupSweep>,
+}
+
 pub struct MainState {
     main_win: MainWindow,
     settings_win: Option<SettingsWindow>,
+    settings_file_win: Option<SettingsFileWindow>,
+    pattern_test_win: Option<PatternTestWindow>,
+    version_timeline_win: Option<VersionTimelineWindow>,
     settings: Option<Settings>,
     backup_thread: Option<JoinHandle<()>>,
     backup_thread_tx: Option<mpsc::Sender<BackupMessage>>,
     backup_thread_rx: Option<mpsc::Receiver<BackupStatus>>,
+    last_backup_heartbeat: Instant,
+    /// Set while the backup thread misses its heartbeats, to the watcher state to show again once it is back
+    backup_thread_unresponsive: Option<WatcherState>,
+    backup_hotkey: Option<HotkeyRegistration>,
+    /// Set while the settings do not work, e.g. because the game was reinstalled elsewhere, so that the backups are only
+    /// browsed and restored, and listed by their names rather than by the backup patterns
+    browse_only: bool,
+    /// Set while the backups started from the UI run on their worker thread, of which only one runs at a time, so that
+    /// quitting waits for it
+    backup_worker: Option<JoinHandle<()>>,
     ui_thread_tx: app::Sender<UiMessage>,
 }
 
@@ -122,10 +345,18 @@ fn main() {
         MainState {
             main_win: MainWindow::new(ui_thread_tx.clone()),
             settings_win: None,
+            settings_file_win: None,
+            pattern_test_win: None,
+            version_timeline_win: None,
             settings: None,
             backup_thread: None,
             backup_thread_tx: None,
             backup_thread_rx: None,
+            last_backup_heartbeat: Instant::now(),
+            backup_thread_unresponsive: None,
+            backup_hotkey: None,
+            browse_only: false,
+            backup_worker: None,
             ui_thread_tx: ui_thread_tx.clone(),
         })));
 
@@ -146,8 +377,28 @@ fn main() {
     match get_settings() {
         Ok(settings) => {
             // Settings loaded without error
-            state.settings = Some(settings);
+            if settings.encrypt {
+                // Only confirmed when no encrypted backup tells a mistyped passphrase apart
+                let encrypted_path = find_encrypted_backup(&settings);
+                if !ensure_session_passphrase(encrypted_path.is_none(), encrypted_path.as_deref()) {
+                    alert_default("Backups cannot be encrypted until a passphrase is entered in Settings");
+                }
+            }
+            let health_issues = check_health(&settings);
+            state.main_win.set_health_issues(health_issues);
+            let has_enabled_patterns = settings.has_enabled_patterns();
+            set_settings(&mut state, settings);
             start_backup_thread(&mut state);
+            if !has_enabled_patterns {
+                match choice_default(
+                    "No file patterns are enabled, so Valbak is not backing up anything. Add the files to back up in \
+                        Settings?",
+                    "Open Settings", "Later", ""
+                ) {
+                    0 => ui_thread_tx.send(UiMessage::MenuSettings),
+                    _ => ()
+                }
+            }
         }
         Err(SError(err_msg)) => {
             // Settings could not be loaded
@@ -155,22 +406,38 @@ fn main() {
         }
         Err(SWarning(settings, warn_msg)) => {
             // Settings loaded with a user recoverable error
-            state.settings = Some(settings.clone());
-            let mut settings_win = SettingsWindow::new(state.ui_thread_tx.clone());
-            settings_win.set_settings_to_win(settings);
-            settings_win.wind.show();
-            state.settings_win = Some(settings_win);
-            if !warn_msg.is_empty() {
-                message_default(&warn_msg);
+            set_settings(&mut state, settings.clone());
+            let can_browse = !warn_msg.is_empty() && settings.backup_dest_path.is_dir();
+            if can_browse && choice_default(
+                &format!("{}\n\nThe existing backups can still be browsed and restored until the settings are fixed.",
+                    warn_msg),
+                "Fix Settings", "Browse Backups", ""
+            ) == 1 {  // Browse Backups
+                // Fixed later in File/Settings
+                state.browse_only = true;
+                state.main_win.set_watcher_state(WatcherState::Error("Settings do not work, not backing up".to_string()));
+            } else {
+                let mut settings_win = SettingsWindow::new(state.ui_thread_tx.clone());
+                settings_win.set_settings_to_win(settings);
+                settings_win.wind.show();
+                state.settings_win = Some(settings_win);
+                if !warn_msg.is_empty() && !can_browse {
+                    message_default(&warn_msg);
+                }
             }
         }
         Err(SNotFound(Some(settings))) => {
             // A settings file was just created with defaults and needs to be validated and adjusted by the user
-            state.settings = Some(settings.clone());
+            let has_enabled_patterns = settings.has_enabled_patterns();
+            set_settings(&mut state, settings.clone());
             let mut settings_win = SettingsWindow::new(state.ui_thread_tx.clone());
             settings_win.set_settings_to_win(settings);
             settings_win.wind.show();
             state.settings_win = Some(settings_win);
+            if !has_enabled_patterns {
+                message_default("The Valheim save folder was not found. Add the folders and file patterns to back up, \
+                    and the folder to back them up to.");
+            }
         }
         _ =>
             panic!("illegal state")
@@ -184,12 +451,17 @@ fn main() {
     drop(state);
     drop(state_guard);
 
+    schedule_heartbeat_check(ui_thread_tx.clone());
+
     // Apparently sending UI messages from the main UI loop is unreliable
     let mut internal_message_queue = Vec::new();
 
     let mut quitting = false;
     // wait() blocks until a message is ready for ui_thread_rx.recv()
     while !internal_message_queue.is_empty() || app.wait() {
+        if !quitting {
+            handle_backup_statuses(main_state.clone());
+        }
         let mut ui_msg = internal_message_queue.pop();
         if ui_msg.is_none() {
             if let Some(msg) = ui_thread_rx.recv() {
@@ -201,8 +473,9 @@ fn main() {
                 // Ignore most messages
                 match ui_msg {
                     PushStatus(_) => {}
-                    PopStatus => {}
                     SetStatus(_) => {}
+                    PopStatus => {}
+                    SetWatcherState(_) => {}
                     _ => {
                         warn!("Quitting - and ignoring message {}", ui_msg.to_string());
                         continue;
@@ -215,8 +488,6 @@ fn main() {
             match ui_msg {
                 MenuSettings => {
                     assert!(state.settings.is_some(), "illegal state");
-                    // non-blocking call
-                    stop_backup_thread(&mut state);
                     let mut settings_win = SettingsWindow::new(state.ui_thread_tx.clone());
                     settings_win.set_settings_to_win(state.settings.as_ref().unwrap().clone());
                     settings_win.wind.make_modal(true);
@@ -227,6 +498,202 @@ fn main() {
                 MenuDocumentation => {
                     todo!();
                 }
+                MenuExportInventory => {
+                    if !ensure_working_settings(&state) {
+                        continue;
+                    }
+                    assert!(state.settings.is_some(), "illegal state");
+                    let settings = state.settings.as_ref().unwrap().clone();
+                    if let Some(inventory_path) = choose_inventory_file() {
+                        let result = get_inventory(&settings).and_then(|inventory| {
+                            write_inventory(&inventory, &inventory_path, InventoryFormat::for_path(&inventory_path))
+                                .map(|()| inventory.len())
+                        });
+                        match result {
+                            Ok(count) => message_default(
+                                &format!("Exported {} backed up files to {}", count, inventory_path.str())),
+                            Err(err) => handle_file_error(main_state.clone(), &err)
+                        }
+                    }
+                }
+                MenuFindDuplicates => {
+                    if !ensure_working_settings(&state) {
+                        continue;
+                    }
+                    assert!(state.settings.is_some(), "illegal state");
+                    let settings = state.settings.as_ref().unwrap().clone();
+                    // Hashing every backup of the same size as another can take minutes
+                    state.main_win.push_status("Finding duplicate backups".to_string());
+                    let ui_thread_tx = state.ui_thread_tx.clone();
+                    std::thread::spawn(move || {
+                        let (duplicate_groups, err) = find_duplicate_backups(&settings);
+                        ui_thread_tx.send(DuplicatesFound(duplicate_groups, err));
+                    });
+                }
+                DuplicatesFound(duplicate_groups, err) => {
+                    state.main_win.pop_status();
+                    let not_compared_msgs = match err {
+                        None => Vec::new(),
+                        Some(FWarning(errs)) => errs,
+                        Some(err) => {
+                            handle_file_error(main_state.clone(), &err);
+                            continue;
+                        }
+                    };
+                    let settings = match &state.settings {
+                        Some(settings) if !state.browse_only => settings.clone(),
+                        // The settings stopped working while the duplicates were searched for
+                        _ => continue
+                    };
+                    let redundant_file_paths: Vec<PathBuf> = duplicate_groups.iter()
+                        .flat_map(|duplicate_group| duplicate_group.redundant_file_paths.iter().cloned())
+                        .collect();
+                    let report = format_duplicates_report(&duplicate_groups);
+                    let not_compared = format_not_compared(&not_compared_msgs);
+                    if duplicate_groups.is_empty() {
+                        message_default(
+                            &format!("No backed up files are identical to those of another file{}", not_compared));
+                    } else if redundant_file_paths.is_empty() {
+                        message_default(
+                            &format!("{}{}\n\nEach copy is the newest backup of its file", report, not_compared));
+                    } else {
+                        match choice_default(
+                            &format!("{}{}\n\nDelete {} redundant copies, keeping one copy of each and the newest \
+                                backup of every file?", report, not_compared, redundant_file_paths.len()),
+                            "Delete", "Cancel", ""
+                        ) {
+                            0 => {  // Delete
+                                if let Err(err) = delete_backed_up_files(&settings, redundant_file_paths) {
+                                    handle_delete_error(main_state.clone(), &err);
+                                }
+                                note_own_dest_change();
+                                internal_message_queue.push(UiMessage::RefreshFilesLists);
+                            }
+                            _ => ()
+                        }
+                    }
+                }
+                MenuForceFullBackup | BackupSelectedLive if state.backup_worker.is_some() => {
+                    message_default("Wait for the running backup to finish");
+                }
+                MenuForceFullBackup => {
+                    if !ensure_working_settings(&state) {
+                        continue;
+                    }
+                    assert!(state.settings.is_some(), "illegal state");
+                    let settings = state.settings.as_ref().unwrap().clone();
+                    let live_file_count = match get_live_files(settings.clone()) {
+                        Ok(live_file_paths) => live_file_paths.len(),
+                        Err(err) => {
+                            handle_file_error(main_state.clone(), &err);
+                            continue;
+                        }
+                    };
+                    match choice_default(
+                        &format!("Back up all {} live files as new versions, even those that already have an \
+                            identical backup? Older versions beyond the backup count are removed.", live_file_count),
+                        "Back Up", "Cancel", ""
+                    ) {
+                        0 => {  // Back Up
+                            start_backup_worker(&mut state, move |listener| {
+                                let (sweep, backup_err) = backup_all_live_files(settings.clone(), listener);
+                                if !sweep.backed_up_files.is_empty() || sweep.pruned_count > 0 {
+                                    note_own_dest_change();
+                                }
+                                let mut outcome = BackupOutcome {
+                                    statuses: get_backed_up_statuses(&settings, &sweep),
+                                    backup_err,
+                                    ..Default::default()
+                                };
+                                if sweep.pruned_count > 0 {
+                                    outcome.statuses.push(BackupStatus::Pruned { count: sweep.pruned_count });
+                                }
+                                outcome.summary = Some(sweep);
+                                outcome
+                            });
+                        }
+                        _ => ()
+                    }
+                }
+                MenuRebuildIndex => {
+                    if !ensure_working_settings(&state) {
+                        continue;
+                    }
+                    assert!(state.settings.is_some(), "illegal state");
+                    let settings = state.settings.as_ref().unwrap().clone();
+                    state.main_win.push_status("Rebuilding the backup index".to_string());
+                    let result = rebuild_manifest(&settings);
+                    state.main_win.pop_status();
+                    match result {
+                        Ok(rebuild) => {
+                            let mut report = format!("Indexed {} versions of {} backed up files",
+                                rebuild.version_count, rebuild.file_count);
+                            if !rebuild.errs.is_empty() {
+                                report.push_str(&format!("\n\n{} files could not be read and were left out:\n{}",
+                                    rebuild.errs.len(), rebuild.errs.join("\n")));
+                            }
+                            message_default(&report);
+                        }
+                        Err(err) => handle_file_error(main_state.clone(), &err)
+                    }
+                    internal_message_queue.push(UiMessage::RefreshFilesLists);
+                }
+                MenuEmptyTrash => {
+                    assert!(state.settings.is_some(), "illegal state");
+                    let settings = state.settings.as_ref().unwrap().clone();
+                    match choice_default(
+                        &format!("Permanently delete the backups in the trash? Deleted backups are otherwise kept there \
+                            for {} days.", settings.trash_retention_days),
+                        "Empty Trash", "Cancel", ""
+                    ) {
+                        0 => {  // Empty Trash
+                            match empty_trash(&settings) {
+                                Ok(0) => message_default("The trash is empty"),
+                                Ok(count) => {
+                                    info!("Emptied {} backups from the trash", count);
+                                    message_default(&format!("Deleted {} backups from the trash", count));
+                                }
+                                Err(err) => handle_file_error(main_state.clone(), &err)
+                            }
+                        }
+                        _ => ()
+                    }
+                }
+                MenuDiagnostics => {
+                    message_default(&state.main_win.get_diagnostics());
+                }
+                MenuEditSettingsFile => {
+                    assert!(state.settings.is_some(), "illegal state");
+                    match read_settings_file_str() {
+                        Ok(settings_str) => {
+                            // Written without line breaks, so laid out for editing when it still is valid JSON
+                            let settings_str = match serde_json::from_str::<serde_json::Value>(&settings_str) {
+                                Ok(settings_json) => serde_json::to_string_pretty(&settings_json).unwrap_or(settings_str),
+                                Err(_) => settings_str
+                            };
+                            let mut settings_file_win = SettingsFileWindow::new(state.ui_thread_tx.clone());
+                            settings_file_win.set_text(&settings_str);
+                            settings_file_win.wind.show();
+                            state.settings_file_win = Some(settings_file_win);
+                        }
+                        Err(err) =>
+                            alert_default(&err.to_string())
+                    }
+                }
+                MenuOpenConfigFolder => {
+                    match get_settings_file_path() {
+                        Ok(settings_file_path) => {
+                            let settings_folder_path = settings_file_path.parent().unwrap();
+                            if let Err(err) = open_in_file_manager(settings_folder_path) {
+                                warn!("Error opening {} in the file manager: {}", settings_folder_path.str(), err);
+                                message_default(&format!(
+                                    "The settings file and log are in the folder:\n\n{}", settings_folder_path.str()));
+                            }
+                        }
+                        Err(err) =>
+                            alert_default(&err.to_string())
+                    }
+                }
                 MenuAbout => {
                     todo!();
                 }
@@ -237,60 +704,86 @@ fn main() {
                     // Shows a file chooser window/dialog and blocks
                     state.settings_win.as_mut().unwrap().choose_backup_dest_dir(settings);
                 }
-                SettingsOk => {
+                SettingsTest => {
                     assert!(state.settings_win.is_some(), "illegal state");
-                    match state.settings_win.as_ref().unwrap().get_settings_from_win() {
-                        Ok(settings) => {
-                            match settings::validate_settings(settings) {
-                                Ok(settings) => {
-                                    state.settings = Some(settings.clone());
-                                    match write_settings(settings) {
-                                        Err(err) => {
-                                            fatal_error(main_state.clone(), err.to_string());
-                                        }
-                                        Ok(settings) => {
-                                            state.settings_win.as_mut().unwrap().wind.hide();
-                                            state.settings_win = None;
-                                            start_backup_thread(&mut state);
-                                            if let Err(err) = backup_all_changed_files(settings.clone()) {
-                                                handle_file_error(main_state.clone(), &err);
-                                            };
-                                            if let Err(err) = delete_old_backups(settings) {
-                                                handle_file_error(main_state.clone(), &err);
-                                            }
-                                            internal_message_queue.push(UiMessage::RefreshFilesLists);
-                                        }
-                                    }
-                                }
-                                Err(err) => {
-                                    match err {
-                                        SWarning(_settings, err_msg) => {
-                                            if !err_msg.is_empty() {
-                                                warn!("{}", err_msg);
-                                                alert_default(&err_msg);
-                                            }
-                                        }
-                                        SError(err_msg) => {
-                                            fatal_error(main_state.clone(), err_msg);
-                                        }
-                                        _ =>
-                                            panic!("illegal state")
-                                    }
-                                }
+                    test_settings(main_state.clone(), &state);
+                }
+                SettingsTestPattern => {
+                    assert!(state.settings_win.is_some() && state.settings.is_some(), "illegal state");
+                    let mut pattern_test_win = PatternTestWindow::new(state.ui_thread_tx.clone());
+                    if let Some(backup_pattern) = state.settings_win.as_ref().unwrap().get_selected_pattern() {
+                        pattern_test_win.set_pattern(&backup_pattern);
+                    }
+                    pattern_test_win.test_pattern(state.settings.as_ref().unwrap());
+                    pattern_test_win.wind.show();
+                    state.pattern_test_win = Some(pattern_test_win);
+                }
+                PatternTestChanged => {
+                    assert!(state.settings.is_some(), "illegal state");
+                    let settings = state.settings.as_ref().unwrap().clone();
+                    if let Some(pattern_test_win) = state.pattern_test_win.as_mut() {
+                        pattern_test_win.test_pattern(&settings);
+                    }
+                }
+                PatternTestClose => {
+                    if let Some(mut pattern_test_win) = state.pattern_test_win.take() {
+                        pattern_test_win.wind.hide();
+                    }
+                }
+                SettingsToggleEnabled => {
+                    assert!(state.settings_win.is_some(), "illegal state");
+                    state.settings_win.as_mut().unwrap().toggle_selected_enabled();
+                }
+                SettingsRefreshHealth => {
+                    assert!(state.settings_win.is_some(), "illegal state");
+                    state.settings_win.as_mut().unwrap().refresh_health();
+                }
+                SettingsResetDefaults => {
+                    assert!(state.settings_win.is_some(), "illegal state");
+                    match choice_default(
+                        "Replace all settings with the defaults?\n\
+                            Nothing is saved until you click Ok or Apply, so the defaults can be reviewed first.",
+                        "Cancel", "Reset", ""
+                    ) {
+                        0 => {}  // Cancel
+                        _ => {  // Reset
+                            match get_default_settings() {
+                                Ok(default_settings) =>
+                                    state.settings_win.as_mut().unwrap().set_settings_to_win(default_settings),
+                                Err(err) =>
+                                    alert_default(&err.to_string())
                             }
                         }
-                        Err(err) => {
-                            match err {
-                                SettingsWinError::SwWarning(err_msg) => {
-                                    warn!("{}", err_msg);
-                                    alert_default(&err_msg);
-                                }
-                                SettingsWinError::SwError(err_msg) => {
-                                    fatal_error(main_state.clone(), err_msg);
-                                }
+                    }
+                }
+                SettingsApply => {
+                    apply_settings(main_state.clone(), &mut state, &mut internal_message_queue);
+                }
+                SettingsOk => {
+                    if apply_settings(main_state.clone(), &mut state, &mut internal_message_queue) {
+                        state.settings_win.as_mut().unwrap().wind.hide();
+                        state.settings_win = None;
+                    }
+                }
+                SettingsFileSave => {
+                    assert!(state.settings_file_win.is_some(), "illegal state");
+                    let settings_str = state.settings_file_win.as_ref().unwrap().get_text();
+                    match parse_settings(&settings_str) {
+                        Ok(settings) => {
+                            if apply_new_settings(main_state.clone(), &mut state, settings, &mut internal_message_queue) {
+                                state.settings_file_win.as_mut().unwrap().wind.hide();
+                                state.settings_file_win = None;
                             }
                         }
-                    };
+                        // Left for the user to correct, the settings file is unchanged
+                        Err(err_msg) =>
+                            state.settings_file_win.as_mut().unwrap().set_error(&format!("Invalid settings: {}", err_msg))
+                    }
+                }
+                SettingsFileCancel => {
+                    if let Some(mut settings_file_win) = state.settings_file_win.take() {
+                        settings_file_win.wind.hide();
+                    }
                 }
                 Alert(alert_msg) => {
                     alert_default(&alert_msg);
@@ -301,62 +794,478 @@ fn main() {
                 AppQuit
                 | MenuQuit
                 | SettingsQuit => {
+                    let force_quit = if is_operation_in_progress() {
+                        match choice_default(
+                            "A backup or restore is in progress. Wait for it to finish before quitting?",
+                            "Wait", "Force Quit", "Cancel"
+                        ) {
+                            0 => false,  // Wait
+                            1 => true,  // Force Quit
+                            _ => continue
+                        }
+                    } else {
+                        false
+                    };
                     quitting = true;
-                    start_graceful_quit(main_state.clone(), 0);
+                    if force_quit {
+                        warn!("Quitting without waiting for the backup in progress");
+                        remove_temp_files_in_progress();
+                        exit(0);
+                    }
+                    let backup_thread = take_backup_thread(&mut state);
+                    start_graceful_quit(backup_thread, state.backup_worker.take(), 0);
                 }
                 RestoreBackup => {
                     let selected_backup_paths = state.main_win.get_selected_backed_up_paths();
                     if !selected_backup_paths.is_empty() {
-                        //TODO show confirmation dialog
                         assert!(state.settings.is_some(), "illegal state");
-                        if let Err(err) = restore_backed_up_files(state.settings.as_ref().unwrap().clone(), selected_backup_paths) {
+                        let settings = state.settings.as_ref().unwrap().clone();
+                        let confirmed = !settings.confirm_restore || choice_default(
+                            format!("Restore {} backup files, replacing their live files?",
+                                selected_backup_paths.len()).as_str(),
+                            "Yes", "Cancel", ""
+                        ) == 0;  // Yes
+                        if confirmed && ensure_passphrase_for(&selected_backup_paths) {
+                            if let Err(err) = restore_backed_up_files(
+                                settings, selected_backup_paths, &UiBackupListener(state.ui_thread_tx.clone())
+                            ) {
+                                handle_file_error(main_state.clone(), &err);
+                            }
+                        }
+                    }
+                    internal_message_queue.push(UiMessage::RefreshFilesLists);
+                }
+                ShowVersionTimeline => {
+                    let selected_live_paths = state.main_win.get_selected_live_paths();
+                    match selected_live_paths.first() {
+                        None => message_default("Select the live file to see the versions of"),
+                        Some(live_file_path) => {
+                            assert!(state.settings.is_some(), "illegal state");
+                            let mut version_timeline_win = VersionTimelineWindow::new(state.ui_thread_tx.clone());
+                            // Lists the versions that could be read even so
+                            if let Err(err) = version_timeline_win.set_versions(
+                                state.settings.as_ref().unwrap(), live_file_path
+                            ) {
+                                handle_file_error(main_state.clone(), &err);
+                            }
+                            version_timeline_win.wind.show();
+                            state.version_timeline_win = Some(version_timeline_win);
+                        }
+                    }
+                }
+                VersionTimelineRestore => {
+                    assert!(state.version_timeline_win.is_some() && state.settings.is_some(), "illegal state");
+                    let version_path = match state.version_timeline_win.as_ref().unwrap().get_selected_version_path() {
+                        Some(version_path) => version_path,
+                        None => {
+                            message_default("Select the version to restore");
+                            continue;
+                        }
+                    };
+                    let settings = state.settings.as_ref().unwrap().clone();
+                    let confirmed = !settings.confirm_restore || choice_default(
+                        format!("Restore {}, replacing its live file?", version_path.file_name_str()).as_str(),
+                        "Yes", "Cancel", ""
+                    ) == 0;  // Yes
+                    if confirmed && ensure_passphrase_for(&[version_path.clone()]) {
+                        if let Some(mut version_timeline_win) = state.version_timeline_win.take() {
+                            version_timeline_win.wind.hide();
+                        }
+                        if let Err(err) = restore_backed_up_files(
+                            settings, vec![version_path], &UiBackupListener(state.ui_thread_tx.clone())
+                        ) {
                             handle_file_error(main_state.clone(), &err);
                         }
+                        internal_message_queue.push(UiMessage::RefreshFilesLists);
+                    }
+                }
+                VersionTimelineClose => {
+                    if let Some(mut version_timeline_win) = state.version_timeline_win.take() {
+                        version_timeline_win.wind.hide();
+                    }
+                }
+                RestoreAsCopy => {
+                    let selected_backup_paths = state.main_win.get_selected_backed_up_paths();
+                    if !selected_backup_paths.is_empty() && ensure_passphrase_for(&selected_backup_paths) {
+                        assert!(state.settings.is_some(), "illegal state");
+                        let settings = state.settings.as_ref().unwrap().clone();
+                        match restore_backed_up_files_as_copies(settings, selected_backup_paths) {
+                            Ok(restored_copy_paths) => {
+                                let restored_copies = restored_copy_paths.iter()
+                                    .map(|path| win_common::summarize(path.str()))
+                                    .collect::<Vec<String>>()
+                                    .join("\n");
+                                message_default(format!("Restored as:\n{}", restored_copies).as_str());
+                            }
+                            Err(err) => handle_file_error(main_state.clone(), &err)
+                        }
+                    }
+                }
+                RestoreAllLatest => {
+                    if !ensure_working_settings(&state) {
+                        continue;
+                    }
+                    assert!(state.settings.is_some(), "illegal state");
+                    let settings = state.settings.as_ref().unwrap().clone();
+                    let (newest_backup_paths, scan_err) = get_newest_backed_up_files(settings.clone());
+                    if let Some(scan_err) = scan_err {
+                        handle_file_error(main_state.clone(), &scan_err);
+                    }
+                    if newest_backup_paths.is_empty() {
+                        message_default("There are no backups to restore");
+                    } else {
+                        // Count the files per backup folder, e.g. worlds and characters
+                        let mut counts_by_folder: Vec<(String, usize)> = Vec::new();
+                        for path in &newest_backup_paths {
+                            let folder_name = path.parent().unwrap().file_name_str().to_string();
+                            match counts_by_folder.iter_mut().find(|(name, _count)| *name == folder_name) {
+                                Some((_name, count)) => *count += 1,
+                                None => counts_by_folder.push((folder_name, 1))
+                            }
+                        }
+                        let counts = counts_by_folder.iter()
+                            .map(|(name, count)| format!("{}: {}", name, count))
+                            .collect::<Vec<String>>()
+                            .join("\n");
+                        match choice_default(
+                            format!("Restore the newest backup of {} files, overwriting the live files?\n\n{}",
+                                newest_backup_paths.len(), counts).as_str(),
+                            "Yes", "Cancel", ""
+                        ) {
+                            0 if ensure_passphrase_for(&newest_backup_paths) => {  // Yes
+                                if let Err(err) = restore_backed_up_files(
+                                    settings, newest_backup_paths, &UiBackupListener(state.ui_thread_tx.clone())
+                                ) {
+                                    handle_file_error(main_state.clone(), &err);
+                                }
+                            }
+                            _ => ()
+                        }
                     }
                     internal_message_queue.push(UiMessage::RefreshFilesLists);
                 }
                 DeleteBackup => {
                     let selected_backup_paths = state.main_win.get_selected_backed_up_paths();
                     if !selected_backup_paths.is_empty() {
+                        assert!(state.settings.is_some(), "illegal state");
+                        let settings = state.settings.as_ref().unwrap().clone();
                         match choice_default(
                             format!("Delete {} backup files?", selected_backup_paths.len()).as_str(),
                             "Yes", "Cancel", ""
                         ) {
                             0 => {  // Yes
-                                if let Err(err) = delete_backed_up_files(selected_backup_paths) {
-                                    handle_file_error(main_state.clone(), &err);
+                                if let Err(err) = delete_backed_up_files(&settings, selected_backup_paths) {
+                                    handle_delete_error(main_state.clone(), &err);
                                 }
+                                note_own_dest_change();
                             }
                             _ => ()
                         }
                     }
                     internal_message_queue.push(UiMessage::RefreshFilesLists);
                 }
+                CompareBackup => {
+                    let mut selected_backup_paths = state.main_win.get_selected_backed_up_paths();
+                    if selected_backup_paths.len() != 1 {
+                        message_default("Select a single backup file to compare with its live file");
+                    } else if ensure_passphrase_for(&selected_backup_paths) {
+                        assert!(state.settings.is_some(), "illegal state");
+                        let settings = state.settings.as_ref().unwrap().clone();
+                        match compare_backed_up_file(settings, selected_backup_paths.remove(0)) {
+                            Ok(comparison) =>
+                                message_default(format_backup_comparison(&comparison).as_str()),
+                            Err(err) =>
+                                handle_file_error(main_state.clone(), &err)
+                        }
+                    }
+                }
+                MarkKnownGood => {
+                    let selected_backup_paths = state.main_win.get_selected_backed_up_paths();
+                    if !selected_backup_paths.is_empty() {
+                        assert!(state.settings.is_some(), "illegal state");
+                        let settings = state.settings.as_ref().unwrap().clone();
+                        if let Err(err) = mark_known_good(settings, selected_backup_paths) {
+                            handle_file_error(main_state.clone(), &err);
+                        }
+                    }
+                }
+                RestoreKnownGood => {
+                    if !ensure_working_settings(&state) {
+                        continue;
+                    }
+                    let selected_backup_paths = state.main_win.get_selected_backed_up_paths();
+                    if !selected_backup_paths.is_empty() {
+                        assert!(state.settings.is_some(), "illegal state");
+                        let settings = state.settings.as_ref().unwrap().clone();
+                        // Several selected versions of the same file share one known good copy
+                        let mut known_good_paths = Vec::new();
+                        for selected_backup_path in &selected_backup_paths {
+                            match find_known_good_file(&settings, selected_backup_path) {
+                                Ok(Some(known_good_path)) if !known_good_paths.contains(&known_good_path) =>
+                                    known_good_paths.push(known_good_path),
+                                Ok(_) => {}
+                                Err(err) => handle_file_error(main_state.clone(), &err)
+                            }
+                        }
+                        if known_good_paths.is_empty() {
+                            message_default("None of the selected files has been marked as known good");
+                        } else {
+                            match choice_default(
+                                format!("Restore the known good copy of {} files, overwriting the live files?",
+                                    known_good_paths.len()).as_str(),
+                                "Yes", "Cancel", ""
+                            ) {
+                                0 if ensure_passphrase_for(&known_good_paths) => {  // Yes
+                                    if let Err(err) = restore_backed_up_files(
+                                        settings, known_good_paths, &UiBackupListener(state.ui_thread_tx.clone())
+                                    ) {
+                                        handle_file_error(main_state.clone(), &err);
+                                    }
+                                }
+                                _ => ()
+                            }
+                        }
+                    }
+                    internal_message_queue.push(UiMessage::RefreshFilesLists);
+                }
+                NoteBackup => {
+                    let selected_backup_paths = state.main_win.get_selected_backed_up_paths();
+                    if selected_backup_paths.is_empty() {
+                        message_default("Select the backups to add a note to");
+                    } else {
+                        let current_note = match read_note(&selected_backup_paths[0]) {
+                            Ok(note) => note.unwrap_or_default(),
+                            Err(_) => String::new()
+                        };
+                        if let Some(note) = input_default(
+                            &format!("Note for {} backups, e.g. \"before boss fight\". Leave empty to remove the note.",
+                                selected_backup_paths.len()),
+                            &current_note
+                        ) {
+                            let mut errs = Vec::new();
+                            for backup_path in &selected_backup_paths {
+                                if let Err(err) = write_note(backup_path, &note) {
+                                    errs.push(err.to_string());
+                                }
+                            }
+                            if !errs.is_empty() {
+                                handle_file_error(main_state.clone(), &FError(errs));
+                            }
+                            internal_message_queue.push(UiMessage::RefreshFilesLists);
+                        }
+                    }
+                }
+                PurgeBackups => {
+                    if !ensure_working_settings(&state) {
+                        continue;
+                    }
+                    assert!(state.settings.is_some(), "illegal state");
+                    let settings = state.settings.as_ref().unwrap().clone();
+                    let keep_count = match input_default(
+                        "Keep how many of the newest versions of each file?",
+                        settings.backup_count.to_string().as_str()
+                    ) {
+                        Some(keep_count_str) => match keep_count_str.trim().parse::<usize>() {
+                            Ok(keep_count) if keep_count > 0 => Some(keep_count),
+                            _ => {
+                                alert_default("The number of versions to keep must be a whole number above 0");
+                                None
+                            }
+                        },
+                        None => None
+                    };
+                    if let Some(keep_count) = keep_count {
+                        match find_backups_over_count(settings.clone(), keep_count) {
+                            Ok(doomed_paths) if doomed_paths.is_empty() =>
+                                message_default(format!("No file has more than {} backups", keep_count).as_str()),
+                            Ok(doomed_paths) => match choice_default(
+                                format!("Delete {} backup files, keeping the newest {} of each file?",
+                                    doomed_paths.len(), keep_count).as_str(),
+                                "Yes", "Cancel", ""
+                            ) {
+                                0 => {  // Yes
+                                    if let Err(err) = delete_backed_up_files(&settings, doomed_paths) {
+                                        handle_delete_error(main_state.clone(), &err);
+                                    }
+                                    note_own_dest_change();
+                                }
+                                _ => ()
+                            },
+                            Err(err) => handle_file_error(main_state.clone(), &err)
+                        }
+                        internal_message_queue.push(UiMessage::RefreshFilesLists);
+                    }
+                }
+                BackupNow if state.browse_only => {
+                    warn!("Not backing up, the settings do not work");
+                }
+                BackupNow if state.backup_thread.is_none() => {
+                    // The hotkey is registered as soon as the settings are read, also when they do not work
+                    info!("Not backing up on the backup hotkey, since the settings are not in effect");
+                }
+                BackupNow if state.backup_worker.is_some() => {
+                    info!("Not backing up on the backup hotkey, since a backup is still running");
+                }
+                BackupNow => {
+                    // Sent by the backup hotkey, usually from within the game, so the outcome is only shown in the
+                    // main window rather than in a dialog that would take the focus
+                    assert!(state.settings.is_some(), "illegal state");
+                    let settings = state.settings.as_ref().unwrap().clone();
+                    start_backup_worker(&mut state, move |listener| {
+                        let (sweep, backup_err) = backup_all_changed_files(settings.clone(), listener);
+                        if !sweep.backed_up_files.is_empty() {
+                            note_own_dest_change();
+                        }
+                        let mut outcome = BackupOutcome {
+                            statuses: get_backed_up_statuses(&settings, &sweep),
+                            backup_err,
+                            ..Default::default()
+                        };
+                        match delete_old_backups(settings, listener) {
+                            Ok(0) => {}
+                            Ok(count) => {
+                                note_own_dest_change();
+                                outcome.statuses.push(BackupStatus::Pruned { count });
+                            }
+                            Err(err) => outcome.errs.push(err)
+                        }
+                        info!("Backed up {} changed files with the backup hotkey", sweep.backed_up_files.len());
+                        outcome
+                    });
+                }
+                BackupSelectedLive => {
+                    if !ensure_working_settings(&state) {
+                        continue;
+                    }
+                    let selected_live_paths = state.main_win.get_selected_live_paths();
+                    if selected_live_paths.is_empty() {
+                        message_default("Select the live files to back up");
+                    } else {
+                        assert!(state.settings.is_some(), "illegal state");
+                        let settings = state.settings.as_ref().unwrap().clone();
+                        start_backup_worker(&mut state, move |listener| {
+                            let mut outcome = BackupOutcome::default();
+                            for live_file_path in selected_live_paths {
+                                match backup_live_file(settings.clone(), live_file_path.clone(), listener) {
+                                    Ok(Some(backed_up_file_path)) => {
+                                        note_own_dest_change();
+                                        let status =
+                                            get_backed_up_status(&settings, live_file_path.clone(), &backed_up_file_path);
+                                        outcome.statuses.extend(status);
+                                    }
+                                    Ok(None) =>
+                                        outcome.messages.push(format!("{} was not backed up, since it is empty or \
+                                            identical to its newest backup", live_file_path.str())),
+                                    Err(err) => {
+                                        outcome.errs.push(err);
+                                        continue;
+                                    }
+                                }
+                                match delete_old_backups_of_live_file(settings.clone(), &live_file_path) {
+                                    Ok(0) => {}
+                                    Ok(count) => {
+                                        note_own_dest_change();
+                                        outcome.statuses.push(BackupStatus::Pruned { count });
+                                    }
+                                    Err(err) => outcome.errs.push(err)
+                                }
+                            }
+                            outcome
+                        });
+                    }
+                }
+                BackupSummary(sweep) => {
+                    message_default(&sweep.to_string());
+                }
+                BackupFinished(outcome) => {
+                    // Only sends this as it returns
+                    if let Some(backup_worker) = state.backup_worker.take() {
+                        if let Err(err) = backup_worker.join() {
+                            error!("Panic from backup worker thread: {:?}", err);
+                        }
+                    }
+                    for status in &outcome.statuses {
+                        state.main_win.on_backup_status(status);
+                    }
+                    if let Some(err) = &outcome.backup_err {
+                        handle_backup_error(main_state.clone(), &mut state.main_win, err);
+                    }
+                    for err in &outcome.errs {
+                        handle_file_error(main_state.clone(), err);
+                    }
+                    for msg in &outcome.messages {
+                        message_default(msg);
+                    }
+                    if let Some(sweep) = outcome.summary {
+                        internal_message_queue.push(UiMessage::BackupSummary(sweep));
+                    }
+                    internal_message_queue.push(UiMessage::RefreshFilesLists);
+                }
+                ShowHealthDetails => {
+                    message_default(&state.main_win.get_health_details());
+                }
                 PushStatus(status) => {
                     debug!("Pushing status message to: {}", &status);
                     state.main_win.push_status(status);
                 }
+                SetStatus(status) => {
+                    debug!("Setting status message to: {}", &status);
+                    state.main_win.set_status(status);
+                }
                 PopStatus => {
                     debug!("Popping status message");
                     state.main_win.pop_status();
                 }
-                SetStatus(status) => {
-                    debug!("Setting status message to: {}", &status);
-                    state.main_win.set_status(status);
+                SetWatcherState(watcher_state) => {
+                    debug!("Setting watcher state to: {}", &watcher_state);
+                    match state.backup_thread_unresponsive {
+                        // Shown once the backup thread is responsive again, the error is shown until then
+                        Some(ref mut state_before) => *state_before = watcher_state,
+                        None => state.main_win.set_watcher_state(watcher_state)
+                    }
                 },
+                CheckHeartbeat => {
+                    let since_heartbeat = state.last_backup_heartbeat.elapsed();
+                    if state.backup_thread.is_some() && state.backup_thread_unresponsive.is_none()
+                        && since_heartbeat > HEARTBEAT_TIMEOUT {
+                        warn!("No heartbeat from the backup thread for {}s", since_heartbeat.as_secs());
+                        state.backup_thread_unresponsive = Some(state.main_win.get_watcher_state());
+                        state.main_win.set_watcher_state(
+                            WatcherState::Error("Watcher unresponsive, restart Valbak".to_string()));
+                    }
+                }
+                RefreshFilesLists if state.browse_only => {
+                    let settings = state.settings.as_ref().unwrap().clone();
+                    state.main_win.set_live_files_to_win(Vec::new(), &settings);
+                    // Every backed up file in the destination, found by its name alone
+                    let (backed_up_files, scan_err) = get_orphaned_backed_up_files(&settings, &[]);
+                    if let Err(err) = state.main_win.set_backed_up_files_to_win(settings, backed_up_files) {
+                        handle_file_error(main_state.clone(), &err);
+                    }
+                    if let Some(FWarning(mut errs)) | Some(FError(mut errs)) | Some(FFatal(mut errs)) = scan_err {
+                        errs.insert(0, "The backed up files list may be incomplete".to_string());
+                        handle_file_error(main_state.clone(), &FError(errs));
+                    }
+                }
                 RefreshFilesLists => {
                     match get_live_files(state.settings.as_ref().unwrap().clone()) {
                         Ok(live_files) => {
-                            state.main_win.set_live_files_to_win(live_files);
-                            match get_backed_up_files(state.settings.as_ref().unwrap().clone()) {
-                                Ok(backed_up_files) => {
-                                    if let Err(err) = state.main_win.set_backed_up_files_to_win(backed_up_files) {
-                                        handle_file_error(main_state.clone(), &err);
-                                    }
-                                }
-                                Err(err) => {
-                                    handle_file_error(main_state.clone(), &err);
-                                }
+                            let settings = state.settings.as_ref().unwrap().clone();
+                            state.main_win.set_live_files_to_win(live_files, &settings);
+                            let (mut backed_up_files, scan_err) = get_backed_up_files(settings.clone());
+                            // Listed as well, so that they are not forgotten, but marked
+                            let (mut orphaned_files, orphan_err) = get_orphaned_backed_up_files(&settings, &backed_up_files);
+                            if let Some(err) = orphan_err {
+                                warn!("{}", err);
+                            }
+                            backed_up_files.append(&mut orphaned_files);
+                            if let Err(err) = state.main_win.set_backed_up_files_to_win(settings, backed_up_files) {
+                                handle_file_error(main_state.clone(), &err);
+                            }
+                            if let Some(FWarning(mut errs)) | Some(FError(mut errs)) | Some(FFatal(mut errs)) = scan_err {
+                                // Worth an alert, since the user may otherwise rely on an incomplete list
+                                errs.insert(0, "The backed up files list may be incomplete".to_string());
+                                handle_file_error(main_state.clone(), &FError(errs));
                             }
                         },
                         Err(err) => {
@@ -371,13 +1280,401 @@ fn main() {
     }
 }
 
+/// Checks the settings in the settings window and reports what a backup with them would do, without asking anything or
+/// writing anything, not even a missing destination folder
+fn test_settings(main_state: Arc<ReentrantMutex<RefCell<MainState>>>, state: &MainState) {
+    let settings = match state.settings_win.as_ref().unwrap().get_settings_from_win() {
+        Ok(settings) => settings,
+        Err(SettingsWinError::SwWarning(err_msg)) | Err(SettingsWinError::SwError(err_msg)) => {
+            alert_default(&err_msg);
+            return;
+        }
+    };
+    if let Err(err_msg) = check_settings(&settings) {
+        alert_default(&err_msg);
+        return;
+    }
+    if !settings.has_enabled_patterns() {
+        alert_default("No file patterns are enabled, so nothing would be backed up");
+        return;
+    }
+    let dry_run = match dry_run_backup(settings.clone()) {
+        Ok(dry_run) => dry_run,
+        Err(err) => {
+            handle_file_error(main_state, &err);
+            return;
+        }
+    };
+
+    let mut report = format!("{} files would be backed up to {}\n{} files are already backed up\n{} empty files would be skipped",
+        dry_run.backed_up_file_paths.len(), settings.backup_dest_path.str(), dry_run.up_to_date_count,
+        dry_run.skipped_count);
+    for backed_up_file_path in dry_run.backed_up_file_paths.iter().take(10) {
+        report.push_str(&format!("\n    {}", backed_up_file_path.str()));
+    }
+    if dry_run.backed_up_file_paths.len() > 10 {
+        report.push_str("\n    ...");
+    }
+    if !dry_run.errs.is_empty() {
+        report.push_str(&format!("\n\n{} problems:", dry_run.errs.len()));
+        for err_msg in &dry_run.errs {
+            report.push_str(&format!("\n    {}", err_msg));
+        }
+    }
+    message_default(&report);
+}
+
+/// Validates and saves the settings in the settings window, then restarts the backup thread with them. The settings
+/// window is left open. Returns whether the settings were applied.
+fn apply_settings(
+    main_state: Arc<ReentrantMutex<RefCell<MainState>>>,
+    state: &mut MainState,
+    internal_message_queue: &mut Vec<UiMessage>
+) -> bool {
+    assert!(state.settings_win.is_some(), "illegal state");
+    let settings = match state.settings_win.as_ref().unwrap().get_settings_from_win() {
+        Ok(settings) => settings,
+        Err(err) => {
+            match err {
+                SettingsWinError::SwWarning(err_msg) => {
+                    warn!("{}", err_msg);
+                    alert_default(&err_msg);
+                }
+                SettingsWinError::SwError(err_msg) => {
+                    fatal_error(main_state.clone(), err_msg);
+                }
+            }
+            return false;
+        }
+    };
+    apply_new_settings(main_state, state, settings, internal_message_queue)
+}
+
+/// Validates `settings` and, when they are valid, saves them, replaces the backup thread when they changed and backs up
+/// what they make changed. Returns whether they were applied.
+fn apply_new_settings(
+    main_state: Arc<ReentrantMutex<RefCell<MainState>>>,
+    state: &mut MainState,
+    settings: Settings,
+    internal_message_queue: &mut Vec<UiMessage>
+) -> bool {
+    let settings = match validate_settings(settings) {
+        Ok(settings) => settings,
+        Err(err) => {
+            match err {
+                SWarning(_settings, err_msg) => {
+                    if !err_msg.is_empty() {
+                        warn!("{}", err_msg);
+                        alert_default(&err_msg);
+                    }
+                }
+                SError(err_msg) => {
+                    fatal_error(main_state.clone(), err_msg);
+                }
+                _ =>
+                    panic!("illegal state")
+            }
+            return false;
+        }
+    };
+    if !settings.has_enabled_patterns() {
+        match choice_default(
+            "No file patterns are enabled, so Valbak will not back up anything. Save these settings anyway?",
+            "Save Anyway", "Cancel", ""
+        ) {
+            0 => {}  // Save Anyway
+            _ => return false
+        }
+    }
+    if settings.encrypt && !ensure_session_passphrase(true, find_encrypted_backup(&settings).as_deref()) {
+        alert_default("A passphrase is required to encrypt backups");
+        return false;
+    }
+    let settings_changed = state.settings.as_ref() != Some(&settings);
+    let previous_version_separator = state.settings.as_ref().map(|settings| settings.version_separator);
+    set_settings(state, settings.clone());
+    state.browse_only = false;
+    let settings = match write_settings(settings) {
+        Ok(settings) => settings,
+        Err(err) =>
+            fatal_error(main_state.clone(), err.to_string())
+    };
+
+    // The backup thread keeps running while the window is open, so that no changes are missed meanwhile. It backs up
+    // with its own copy of the settings, so it is only replaced when they changed.
+    if state.backup_thread.is_some() && settings_changed {
+        // Wait for it, so that its final state does not overwrite the state of its replacement
+        if let Err(err) = stop_backup_thread(state, WatcherState::Stopped).join() {
+            error!("Panic from backup thread: {:?}", err);
+        }
+    }
+    if previous_version_separator.map_or(false, |separator| separator != settings.version_separator) {
+        // Renamed while the backup thread is stopped, so that it does not back up alongside
+        match choice_default(
+            &format!("Rename the existing backups to use {} before their version as well? They are recognized either \
+                way.", settings.version_separator),
+            "Rename", "Keep Names", ""
+        ) {
+            0 => {  // Rename
+                let (renamed_count, rename_err) = rename_backups_to_version_separator(&settings);
+                if renamed_count > 0 {
+                    note_own_dest_change();
+                }
+                if let Some(err) = rename_err {
+                    handle_file_error(main_state.clone(), &err);
+                }
+                info!("Renamed {} backed up files to the version separator {}", renamed_count,
+                    settings.version_separator);
+            }
+            _ => ()
+        }
+    }
+    if state.backup_thread.is_none() {
+        start_backup_thread(state);
+    }
+    let listener = UiBackupListener(state.ui_thread_tx.clone());
+    let (mut sweep, backup_err) = backup_all_changed_files(settings.clone(), &listener);
+    if !sweep.backed_up_files.is_empty() || sweep.pruned_count > 0 {
+        note_own_dest_change();
+    }
+    for (live_file_path, backed_up_file_path) in &sweep.backed_up_files {
+        if let Some(status) = get_backed_up_status(&settings, live_file_path.clone(), backed_up_file_path) {
+            state.main_win.on_backup_status(&status);
+        }
+    }
+    if let Some(err) = backup_err {
+        handle_backup_error(main_state.clone(), &mut state.main_win, &err);
+    }
+    match delete_old_backups(settings.clone(), &listener) {
+        Ok(0) => {}
+        Ok(count) => {
+            note_own_dest_change();
+            state.main_win.on_backup_status(&BackupStatus::Pruned { count });
+            sweep.pruned_count += count;
+        }
+        Err(err) => handle_file_error(main_state.clone(), &err)
+    }
+    // Shown once the files lists are refreshed, confirming that the new settings captured everything
+    internal_message_queue.push(UiMessage::BackupSummary(sweep));
+    internal_message_queue.push(UiMessage::RefreshFilesLists);
+
+    state.main_win.set_health_issues(check_health(&settings));
+    if let Some(settings_win) = state.settings_win.as_mut() {
+        // Refreshes the folder health panel
+        settings_win.set_settings_to_win(settings);
+    }
+    true
+}
+
+/// Whether the settings work for operations that rely on the backup patterns, telling the user to fix them otherwise
+fn ensure_working_settings(state: &MainState) -> bool {
+    if state.browse_only {
+        message_default("Fix the settings in File/Settings first. Until then, the backups can only be browsed and \
+            restored.");
+        return false;
+    }
+    true
+}
+
+fn set_settings(state: &mut MainState, settings: Settings) {
+    win_common::set_summary_len(settings.error_summary_len);
+    let registered_hotkey_str = state.backup_hotkey.as_ref().map(|registration| registration.hotkey_str.as_str());
+    if registered_hotkey_str != settings.backup_hotkey.as_deref() {
+        // Unregisters the previous hotkey before the new one is registered, in case they are the same key
+        state.backup_hotkey = None;
+        if let Some(backup_hotkey) = &settings.backup_hotkey {
+            state.backup_hotkey = register_backup_hotkey(backup_hotkey, state.ui_thread_tx.clone());
+        }
+    }
+    state.settings = Some(settings);
+}
+
+/// Describes the first few of `duplicate_groups`, so that the report still fits on the screen
+fn format_duplicates_report(duplicate_groups: &[DuplicateGroup]) -> String {
+    const MAX_LISTED_GROUPS: usize = 8;
+    let mut report = format!("{} sets of identical backups of different files:", duplicate_groups.len());
+    for duplicate_group in duplicate_groups.iter().take(MAX_LISTED_GROUPS) {
+        report.push_str(&format!("\n\n{} bytes:", duplicate_group.size));
+        for backed_up_file_path in &duplicate_group.backed_up_file_paths {
+            let redundant_mark = if duplicate_group.redundant_file_paths.contains(backed_up_file_path) {
+                " (redundant)"
+            } else {
+                ""
+            };
+            report.push_str(&format!("\n    {}{}", backed_up_file_path.str(), redundant_mark));
+        }
+    }
+    if duplicate_groups.len() > MAX_LISTED_GROUPS {
+        report.push_str(&format!("\n\n... and {} more", duplicate_groups.len() - MAX_LISTED_GROUPS));
+    }
+    report
+}
+
+/// Describes the first few of the problems with single backed up files while finding the duplicates, to be appended to
+/// the report of the duplicates, or nothing when there were none
+fn format_not_compared(not_compared_msgs: &[String]) -> String {
+    const MAX_LISTED_MSGS: usize = 5;
+    if not_compared_msgs.is_empty() {
+        return String::new();
+    }
+    let mut report = format!("\n\nProblems with {} backed up files:", not_compared_msgs.len());
+    for not_compared_msg in not_compared_msgs.iter().take(MAX_LISTED_MSGS) {
+        report.push_str(&format!("\n    {}", win_common::summarize(not_compared_msg)));
+    }
+    if not_compared_msgs.len() > MAX_LISTED_MSGS {
+        report.push_str(&format!("\n    ... and {} more", not_compared_msgs.len() - MAX_LISTED_MSGS));
+    }
+    report
+}
+
+/// Asks where to export the backup inventory. A `.csv` file is written as CSV, and anything else as JSON lines.
+fn choose_inventory_file() -> Option<PathBuf> {
+    let start_dir = dirs::document_dir().unwrap_or_default();
+    let mut file_chooser =
+        FileChooser::new(start_dir.str(),
+                         "*.jsonl\t*.csv",
+                         FileChooserType::Create,
+                         "Export backup inventory");
+    file_chooser.set_preview(false);
+    file_chooser.preview_button().unwrap().hide();
+    file_chooser.show();
+    while file_chooser.shown() {
+        app::wait();
+    }
+    // FLTK File Chooser apparently always uses forward slashes
+    file_chooser.value(1).map(|path| normalize_path(Path::new(&path)))
+}
+
+/// Asks where to restore a backed up file to instead of `target_file_path`, starting in its closest existing folder
+fn choose_restore_target_file(target_file_path: &Path) -> Option<PathBuf> {
+    let start_path = if target_file_path.parent().map_or(false, |folder_path| folder_path.is_dir()) {
+        target_file_path.to_path_buf()
+    } else {
+        target_file_path.ancestors().find(|ancestor| ancestor.is_dir()).unwrap_or(Path::new("")).to_path_buf()
+    };
+    let mut file_chooser =
+        FileChooser::new(start_path.str(),
+                         "*",
+                         FileChooserType::Create,
+                         "Restore to");
+    file_chooser.set_preview(false);
+    file_chooser.preview_button().unwrap().hide();
+    file_chooser.show();
+    while file_chooser.shown() {
+        app::wait();
+    }
+    // FLTK File Chooser apparently always uses forward slashes
+    file_chooser.value(1).map(|path| normalize_path(Path::new(&path)))
+}
+
+/// Asks for the passphrase of encrypted backups, unless it was already entered this session. With `confirm` the
+/// passphrase must be entered twice, since a mistyped passphrase would make new backups unrecoverable. A passphrase
+/// that does not decrypt `encrypted_path`, an existing encrypted backup, is not kept, so that it is asked for again
+/// next time. Returns whether a passphrase is available.
+fn ensure_session_passphrase(confirm: bool, encrypted_path: Option<&Path>) -> bool {
+    if crypt::has_session_passphrase() {
+        return true;
+    }
+    let passphrase = match password_default("Passphrase for encrypted backups:", "") {
+        Some(passphrase) if !passphrase.is_empty() => passphrase,
+        _ => return false
+    };
+    if confirm && password_default("Confirm the passphrase:", "").as_ref() != Some(&passphrase) {
+        alert_default("The passphrases do not match");
+        return false;
+    }
+    crypt::set_session_passphrase(passphrase);
+    if let Some(encrypted_path) = encrypted_path {
+        if let Err(err) = crypt::verify_session_passphrase(encrypted_path) {
+            crypt::clear_session_passphrase();
+            error!("{}", err);
+            alert_default(&err.to_string());
+            return false;
+        }
+    }
+    true
+}
+
+/// Makes sure the passphrase has been entered when any of `backed_up_file_paths` are encrypted
+fn ensure_passphrase_for(backed_up_file_paths: &[PathBuf]) -> bool {
+    match backed_up_file_paths.iter().find(|path| crypt::is_encrypted_file(path)) {
+        Some(encrypted_path) => ensure_session_passphrase(false, Some(encrypted_path)),
+        None => true
+    }
+}
+
+/// The newest backed up file of `settings` that is encrypted, if any, to check an entered passphrase against
+fn find_encrypted_backup(settings: &Settings) -> Option<PathBuf> {
+    let (newest_backed_up_file_paths, _scan_err) = get_newest_backed_up_files(settings.clone());
+    newest_backed_up_file_paths.into_iter().find(|path| crypt::is_encrypted_file(path))
+}
+
+/// Shows each status the backup thread has sent since the last call
+fn handle_backup_statuses(main_state: Arc<ReentrantMutex<RefCell<MainState>>>) {
+    let state_guard = main_state.lock();
+    let mut state = state_guard.deref().borrow_mut();
+    let backup_statuses: Vec<BackupStatus> = match &state.backup_thread_rx {
+        Some(backup_thread_rx) => backup_thread_rx.try_iter().collect(),
+        None => Vec::new()
+    };
+    for backup_status in backup_statuses {
+        match backup_status {
+            BackupStatus::Heartbeat => {
+                state.last_backup_heartbeat = Instant::now();
+                if let Some(state_before) = state.backup_thread_unresponsive.take() {
+                    info!("Backup thread is responsive again");
+                    state.main_win.set_watcher_state(state_before);
+                }
+            }
+            BackupStatus::Error { ref msg } => {
+                state.main_win.on_backup_status(&backup_status);
+                alert_default(msg);
+            }
+            _ =>
+                state.main_win.on_backup_status(&backup_status)
+        }
+    }
+}
+
+/// Runs `backup` on a worker thread, so that backing up and pruning many files does not freeze the UI, and sends what
+/// it did back to the UI thread as [`BackupFinished`]. `backup` must not ask the user anything.
+fn start_backup_worker(
+    state: &mut MainState, backup: impl FnOnce(&UiBackupListener) -> BackupOutcome + Send + 'static
+) {
+    let listener = UiBackupListener(state.ui_thread_tx.clone());
+    state.backup_worker = Some(std::thread::spawn(move || {
+        let outcome = backup(&listener);
+        listener.0.send(BackupFinished(outcome));
+    }));
+}
+
+/// The status of each file that `sweep` backed up with `settings`, as the backup thread reports it
+fn get_backed_up_statuses(settings: &Settings, sweep: &BackupSweep) -> Vec<BackupStatus> {
+    sweep.backed_up_files.iter()
+        .filter_map(|(live_file_path, backed_up_file_path)|
+            get_backed_up_status(settings, live_file_path.clone(), backed_up_file_path))
+        .collect()
+}
+
+/// Sends [`CheckHeartbeat`] every [`HEARTBEAT_INTERVAL`]
+fn schedule_heartbeat_check(ui_thread_tx: app::Sender<UiMessage>) {
+    app::add_timeout(HEARTBEAT_INTERVAL.as_secs_f64(), move || {
+        ui_thread_tx.send(CheckHeartbeat);
+        schedule_heartbeat_check(ui_thread_tx.clone());
+    });
+}
+
 fn handle_file_error(main_state: Arc<ReentrantMutex<RefCell<MainState>>>, file_err: &FileError) {
+    const MAX_SUMMARIZED_ERRS: usize = 10;
     let summarize_errs = |errs: &Vec<String>| {
-        let mut alert_err = errs.join("\n");
-        if alert_err.len() > 100 {
-            alert_err = alert_err[..100].to_string() + "...";
+        let mut alert_errs: Vec<String> = errs.iter()
+            .take(MAX_SUMMARIZED_ERRS)
+            .map(|err_msg| win_common::summarize(err_msg))
+            .collect();
+        if errs.len() > MAX_SUMMARIZED_ERRS {
+            alert_errs.push(format!("...and {} more", errs.len() - MAX_SUMMARIZED_ERRS));
         }
-        alert_err
+        alert_errs.join("\n")
     };
     match file_err {
         FWarning(errs) => {
@@ -394,6 +1691,66 @@ fn handle_file_error(main_state: Arc<ReentrantMutex<RefCell<MainState>>>, file_e
     }
 }
 
+/// Handles the errors of backing up changed files, counting warnings, such as files skipped for their size, in the
+/// main window as the backup thread does, rather than only logging them
+fn handle_backup_error(
+    main_state: Arc<ReentrantMutex<RefCell<MainState>>>, main_win: &mut MainWindow, file_err: &FileError
+) {
+    match file_err {
+        FWarning(errs) => {
+            for err_msg in errs {
+                warn!("{}", err_msg);
+                main_win.on_backup_status(&BackupStatus::Warning { msg: err_msg.clone() });
+            }
+        }
+        _ => handle_file_error(main_state, file_err)
+    }
+}
+
+/// Handles the errors of deleting backed up files, listing them all in full when several files could not be deleted,
+/// e.g. because they are open elsewhere, since an alert only has room for the first few
+fn handle_delete_error(main_state: Arc<ReentrantMutex<RefCell<MainState>>>, file_err: &FileError) {
+    match file_err {
+        FError(errs) if errs.len() > 1 => {
+            errs.iter().for_each(|err_msg| error!("{}", err_msg));
+            win_common::show_error_list(&format!("{} backup files could not be deleted:", errs.len()), errs);
+        }
+        _ => handle_file_error(main_state, file_err)
+    }
+}
+
+fn format_backup_comparison(comparison: &BackupComparison) -> String {
+    let (size_delta, modified_delta_sec) = match (comparison.size_delta, comparison.modified_delta_sec) {
+        (Some(size_delta), Some(modified_delta_sec)) => (size_delta, modified_delta_sec),
+        _ => return format!("The live file {} does not exist", comparison.live_file_path.str())
+    };
+    let content = if comparison.identical {
+        "identical to"
+    } else {
+        "different from"
+    };
+    let modified = if modified_delta_sec >= 0 {
+        format!("{} seconds newer", modified_delta_sec)
+    } else {
+        format!("{} seconds older", -modified_delta_sec)
+    };
+    format!("The live file {} is {} the backup.\n\nSize difference: {:+} bytes\nLive file is {}",
+        comparison.live_file_path.str(), content, size_delta, modified)
+}
+
+fn open_in_file_manager(folder_path: &Path) -> std::io::Result<()> {
+    #[cfg(target_os = "windows")]
+    let file_manager = "explorer";
+    #[cfg(target_os = "macos")]
+    let file_manager = "open";
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let file_manager = "xdg-open";
+
+    // Only the launch is checked, explorer reports a failure exit code even when it succeeds
+    Command::new(file_manager).arg(folder_path).spawn()?;
+    Ok(())
+}
+
 fn init_logging(main_state: Arc<ReentrantMutex<RefCell<MainState>>>, settings_folder_path: &Path) {
     let log_file_path = settings_folder_path.join("valbak.log");
     let log_file_path = log_file_path.str();
@@ -425,7 +1782,23 @@ fn fatal_error(main_state: Arc<ReentrantMutex<RefCell<MainState>>>, err_msg: Str
     }
     // blocks until user dismisses the alert box
     alert_default(&err_msg);
-    let exit_thread = start_graceful_quit(main_state, 1);
+
+    // The main state lock is reentrant, so this cannot deadlock when the calling thread already holds it, however the
+    // state itself may still be borrowed by the caller
+    let (backup_thread, backup_worker) = {
+        let state_guard = main_state.lock();
+        let threads = match state_guard.deref().try_borrow_mut() {
+            Ok(mut state) =>
+                (take_backup_thread(&mut state), state.backup_worker.take()),
+            Err(_) => {
+                error!("Main state in use - quitting without waiting for the backup thread");
+                (None, None)
+            }
+        };
+        threads
+    };
+
+    let exit_thread = start_graceful_quit(backup_thread, backup_worker, 1);
     if let Err(_) = exit_thread.join() {
         // ignore
     }
@@ -433,21 +1806,33 @@ fn fatal_error(main_state: Arc<ReentrantMutex<RefCell<MainState>>>, err_msg: Str
     exit(1);
 }
 
-fn start_graceful_quit(main_state: Arc<ReentrantMutex<RefCell<MainState>>>, exit_code: i32) -> JoinHandle<()> {
+/// Signals the backup thread, if running, to stop and returns its handle so it can be joined
+fn take_backup_thread(state: &mut MainState) -> Option<JoinHandle<()>> {
+    if state.backup_thread.is_some() {
+        Some(stop_backup_thread(state, WatcherState::Stopped))
+    } else {
+        None
+    }
+}
+
+/// Waits for `backup_thread` and `backup_worker`, if any, on a separate thread and then exits. Nothing here touches the
+/// main state, so it is safe to call while the main state lock is held.
+fn start_graceful_quit(
+    backup_thread: Option<JoinHandle<()>>, backup_worker: Option<JoinHandle<()>>, exit_code: i32
+) -> JoinHandle<()> {
     std::thread::spawn(move || {
-        let state_guard = match main_state.try_lock() {
-            Some(guard) => guard,
-            None => panic!("illegal state - main state lock not released")
-        };
-        let mut state = state_guard.deref().borrow_mut();
-        if state.backup_thread.is_some() {
-            let backup_thread = stop_backup_thread(&mut state);
-            drop(state);
-            drop(state_guard);
+        if let Some(backup_thread) = backup_thread {
             if let Err(err) = backup_thread.join() {
                 error!("Panic from backup thread: {:?}", err);
             }
         }
+        if let Some(backup_worker) = backup_worker {
+            if let Err(err) = backup_worker.join() {
+                error!("Panic from backup worker thread: {:?}", err);
+            }
+        }
+        // Left behind by a thread that panicked mid-copy
+        remove_temp_files_in_progress();
         exit(exit_code);
     })
 }
\ No newline at end of file
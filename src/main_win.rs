@@ -4,8 +4,12 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
-use std::cmp::Ordering;
+use std::cell::RefCell;
+use std::cmp::{max, Ordering};
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
 
 use anyhow::Result;
 use chrono::{DateTime, Local};
@@ -13,19 +17,68 @@ use fltk::{app::*, app, browser::*, button::*, enums::*, group::*, prelude::*, w
 use fltk::frame::Frame;
 use fltk::menu::{MenuBar, MenuFlag};
 use log::error;
+use valbak::file::{BackupChange, BackupVersion, count_live_file_versions, get_backed_up_path, get_backed_up_version, get_file_metadata, get_live_file_fingerprint, get_source_dir_for_backed_up_file, PathExt};
+use valbak::manifest::{Manifest, read_manifest};
+use valbak::settings::{DEFAULT_DATE_FORMAT, is_valid_date_format, Settings};
+use valbak::sidecar::{read_note, read_sidecar};
 
 use FileError::{FError, FFatal};
 
 use crate::{FileError, FWarning, UiMessage, win_common};
-use crate::file::{get_backed_up_path, get_backed_up_version_number, get_file_metadata, PathExt};
-use crate::UiMessage::{AppQuit, MenuAbout, MenuDocumentation, MenuQuit, MenuSettings};
+use crate::settings_file::{read_window_layout, write_window_layout, WindowLayout};
+use crate::watcher::{BackupStatus, WatcherState};
+use crate::UiMessage::{AppQuit, MenuAbout, MenuDiagnostics, MenuDocumentation, MenuEditSettingsFile, MenuEmptyTrash, MenuExportInventory, MenuFindDuplicates, MenuForceFullBackup, MenuOpenConfigFolder, MenuQuit, MenuRebuildIndex, MenuSettings};
+
+/// Shown as the source of backed up files that no backup pattern matches any more
+const ORPHANED_SOURCE: &str = "No matching pattern";
+
+/// The border between the live files and the backed up files cannot be dragged to make either list shorter than this
+const MIN_LIST_HEIGHT: i32 = 80;
+
+/// What the backup thread has reported doing this session
+#[derive(Default)]
+struct BackupActivity {
+    watched_dirs: Vec<PathBuf>,
+    /// Source folders that disappeared, or never existed, whose backups can still be restored
+    missing_dirs: Vec<PathBuf>,
+    /// How the watcher watches the folders, and how long after a change it reports it
+    watcher_backend: Option<(String, Duration)>,
+    event_count: usize,
+    last_event: Option<(DateTime<Local>, String)>,
+    backed_up_count: usize,
+    pruned_count: usize,
+    warning_count: usize,
+    /// The most recent change that was not backed up, and why
+    last_warning: Option<(DateTime<Local>, String)>,
+    error_count: usize,
+    /// Backed up files waiting for the mirror destination to be available again
+    pending_mirror_count: usize,
+    mirror_error_count: usize,
+    last_mirror_error: Option<(DateTime<Local>, String)>,
+    last_backup: Option<(DateTime<Local>, PathBuf, BackupVersion, Option<BackupChange>)>,
+    /// Live files backed up this session, which are shown in bold
+    backed_up_live_files: HashSet<PathBuf>,
+}
 
 pub struct MainWindow {
     pub wind: DoubleWindow,
+    watcher_state_dot: Frame,
+    watcher_state_frame: Frame,
+    watcher_state: WatcherState,
+    /// Shows the most recent transient progress message, if any
     status_frame: Frame,
     status_stack: Vec<String>,
+    activity_frame: Frame,
+    activity: BackupActivity,
+    /// Shows the result of the last health check, and its details when clicked
+    health_button: Button,
+    health_issues: Vec<String>,
     live_files: MultiBrowser,
+    /// The full path shown on each row of `live_files`, in row order
+    live_file_paths: Rc<RefCell<Vec<PathBuf>>>,
     backed_up_files: MultiBrowser,
+    /// The full path shown on each row of `backed_up_files`, in row order
+    backed_up_file_paths: Rc<RefCell<Vec<PathBuf>>>,
 }
 
 impl MainWindow {
@@ -45,17 +98,43 @@ impl MainWindow {
         menu.add("File/Settings", Shortcut::None, MenuFlag::Normal,
             move |_menu_bar| sender_copy.send(MenuSettings));
         let sender_copy = ui_thread_tx.clone();
+        menu.add("File/Export Inventory...", Shortcut::None, MenuFlag::Normal,
+            move |_menu_bar| sender_copy.send(MenuExportInventory));
+        let sender_copy = ui_thread_tx.clone();
+        menu.add("File/Find Duplicate Backups...", Shortcut::None, MenuFlag::Normal,
+            move |_menu_bar| sender_copy.send(MenuFindDuplicates));
+        let sender_copy = ui_thread_tx.clone();
+        menu.add("File/Force Full Backup...", Shortcut::None, MenuFlag::Normal,
+            move |_menu_bar| sender_copy.send(MenuForceFullBackup));
+        let sender_copy = ui_thread_tx.clone();
         menu.add("File/Quit", Shortcut::None, MenuFlag::Normal,
             move |_menu_bar| sender_copy.send(MenuQuit));
         let sender_copy = ui_thread_tx.clone();
+        menu.add("Maintenance/Rebuild Backup Index", Shortcut::None, MenuFlag::Normal,
+            move |_menu_bar| sender_copy.send(MenuRebuildIndex));
+        let sender_copy = ui_thread_tx.clone();
+        menu.add("Maintenance/Empty Trash...", Shortcut::None, MenuFlag::Normal,
+            move |_menu_bar| sender_copy.send(MenuEmptyTrash));
+        let sender_copy = ui_thread_tx.clone();
         menu.add("Help/Documentation", Shortcut::None, MenuFlag::Normal,
             move |_menu_bar| sender_copy.send(MenuDocumentation));
         let sender_copy = ui_thread_tx.clone();
+        menu.add("Help/Diagnostics", Shortcut::None, MenuFlag::Normal,
+            move |_menu_bar| sender_copy.send(MenuDiagnostics));
+        let sender_copy = ui_thread_tx.clone();
+        menu.add("Help/Edit Settings File", Shortcut::None, MenuFlag::Normal,
+            move |_menu_bar| sender_copy.send(MenuEditSettingsFile));
+        let sender_copy = ui_thread_tx.clone();
+        menu.add("Help/Open Config Folder", Shortcut::None, MenuFlag::Normal,
+            move |_menu_bar| sender_copy.send(MenuOpenConfigFolder));
+        let sender_copy = ui_thread_tx.clone();
         menu.add("Help/About", Shortcut::None, MenuFlag::Normal,
             move |_menu_bar| sender_copy.send(MenuAbout));
 
         let mut live_files;
-        let backed_up_files;
+        let mut backed_up_files;
+        let live_file_paths = Rc::new(RefCell::new(Vec::new()));
+        let backed_up_file_paths = Rc::new(RefCell::new(Vec::new()));
 
         let mut content = Pack::default()
             .with_size(CONTENT_SIZE.0, CONTENT_SIZE.1)
@@ -63,26 +142,93 @@ impl MainWindow {
         content.set_spacing(5);
 
         win_common::make_section_header("Status", true);
+        let mut status_row = Pack::default()
+            .with_type(PackType::Horizontal);
+        status_row.set_spacing(5);
+
+        let mut watcher_state_dot = Frame::default();
+        watcher_state_dot.set_frame(FrameType::OvalBox);
+        let mut watcher_state_frame = Frame::default();
+        watcher_state_frame.set_align(Align::Inside | Align::Left);
+        watcher_state_frame.set_label(&WatcherState::Stopped.to_string());
         let mut status_frame = Frame::default();
         status_frame.set_align(Align::Inside | Align::Left);
-        status_frame.set_label("Unknown");
-        let text_size = status_frame.measure_label();
-        status_frame.set_size(text_size.0, text_size.1);
 
-        static FILE_LIST_COLUMN_WIDTHS: [i32; 3] = [CONTENT_SIZE.0 - 300, 200, 100];
-        let file_header_texts: Vec<&str> = vec!["File", "File Date", "File Size"];
+        let text_size = watcher_state_frame.measure_label();
+        watcher_state_dot.set_size(text_size.1, text_size.1);
+        watcher_state_frame.set_size(250, text_size.1);
+        status_frame.set_size(CONTENT_SIZE.0 - 250 - text_size.1 - 10, text_size.1);
+        status_row.set_size(0, text_size.1);
+        status_row.end();
+
+        let mut activity_frame = Frame::default();
+        activity_frame.set_align(Align::Inside | Align::Left);
+        activity_frame.set_size(CONTENT_SIZE.0, text_size.1);
+
+        let mut health_button = Button::default();
+        health_button.set_frame(FrameType::FlatBox);
+        health_button.set_align(Align::Inside | Align::Left);
+        health_button.set_size(CONTENT_SIZE.0, text_size.1);
+        health_button.emit(ui_thread_tx.clone(), UiMessage::ShowHealthDetails);
+
+        static FILE_LIST_COLUMN_WIDTHS: [i32; 4] = [CONTENT_SIZE.0 - 400, 200, 100, 100];
+        static BACKED_UP_FILE_LIST_COLUMN_WIDTHS: [i32; 5] = [CONTENT_SIZE.0 - 700, 200, 150, 100, 250];
+        let file_header_texts: Vec<&str> = vec!["File", "File Date", "File Size", "Versions"];
+        let backed_up_file_header_texts: Vec<&str> = vec!["File", "Source", "File Date", "File Size", "Note"];
+
+        // The live files and the backed up files share the rest of the window, split where the user last dragged the
+        // border between them
+        let list_panes_y = content.y() + pack_content_height(&content) + content.spacing();
+        let list_panes_height = WINDOW_SIZE.1 - 10 - list_panes_y;
+        let mut list_panes = Tile::default()
+            .with_size(CONTENT_SIZE.0, list_panes_height)
+            .with_pos(content.x(), list_panes_y);
+        // Only its bounds matter: the border between the panes cannot be dragged out of it
+        let mut list_panes_limits = Frame::default();
 
         // Live Files
+        let mut live_files_pane = Pack::default()
+            .with_size(CONTENT_SIZE.0, 0)
+            .with_pos(list_panes.x(), list_panes.y());
+        live_files_pane.set_spacing(5);
         win_common::make_section_header("Live Files", true);
         win_common::column_headers(&file_header_texts, &FILE_LIST_COLUMN_WIDTHS);
-        live_files = win_common::make_list_browser(&FILE_LIST_COLUMN_WIDTHS, 242);
+        live_files = win_common::make_list_browser(&FILE_LIST_COLUMN_WIDTHS, 212);
+        win_common::handle_list_events(&mut live_files, live_file_paths.clone(), None);
 
-        live_files.set_selection_color(Color::White);
+        let mut live_files_buttons = Pack::default()
+            .with_type(PackType::Horizontal);
+        live_files_buttons.set_spacing(5);
+
+        let mut backup_selected_live_button = Button::default()
+            .with_label("Back Up Now");
+        let text_size = backup_selected_live_button.measure_label();
+        backup_selected_live_button.set_size(text_size.0 + 15, text_size.1 + 10);
+        backup_selected_live_button
+            .emit(ui_thread_tx.clone(), UiMessage::BackupSelectedLive);
+        let mut version_timeline_button = Button::default()
+            .with_label("Versions...");
+        let text_size = version_timeline_button.measure_label();
+        version_timeline_button.set_size(text_size.0 + 15, text_size.1 + 10);
+        version_timeline_button
+            .emit(ui_thread_tx.clone(), UiMessage::ShowVersionTimeline);
+
+        live_files_buttons.set_size(0, text_size.1 + 10);
+
+        live_files_buttons.end();
+        live_files_pane.end();
+        let live_files_pane_fixed_height = pack_content_height(&live_files_pane) - live_files.height();
 
         // Backed-Up Files
+        let mut backed_up_files_pane = Pack::default()
+            .with_size(CONTENT_SIZE.0, 0)
+            .with_pos(list_panes.x(), list_panes.y());
+        backed_up_files_pane.set_spacing(5);
         win_common::make_section_header("Backed-Up Files", true);
-        win_common::column_headers(&file_header_texts, &FILE_LIST_COLUMN_WIDTHS);
-        backed_up_files = win_common::make_list_browser(&FILE_LIST_COLUMN_WIDTHS, 322);
+        win_common::column_headers(&backed_up_file_header_texts, &BACKED_UP_FILE_LIST_COLUMN_WIDTHS);
+        backed_up_files = win_common::make_list_browser(&BACKED_UP_FILE_LIST_COLUMN_WIDTHS, 302);
+        win_common::handle_list_events(&mut backed_up_files, backed_up_file_paths.clone(),
+            Some((ui_thread_tx.clone(), UiMessage::RestoreBackup)));
 
         let mut backed_up_files_buttons = Pack::default()
             .with_type(PackType::Horizontal);
@@ -92,19 +238,94 @@ impl MainWindow {
             .with_label("Restore");
         let text_size = restore_backups_button.measure_label();
         restore_backups_button.set_size(text_size.0 + 15, text_size.1 + 10);
+        let mut restore_as_copy_button = Button::default()
+            .with_label("Restore as Copy");
+        let text_size = restore_as_copy_button.measure_label();
+        restore_as_copy_button.set_size(text_size.0 + 15, text_size.1 + 10);
+        let mut restore_all_latest_button = Button::default()
+            .with_label("Restore All Latest");
+        let text_size = restore_all_latest_button.measure_label();
+        restore_all_latest_button.set_size(text_size.0 + 15, text_size.1 + 10);
         let mut delete_backups_button = Button::default()
             .with_label("Delete");
         let text_size = delete_backups_button.measure_label();
         delete_backups_button.set_size(text_size.0 + 15, text_size.1 + 10);
+        let mut compare_backup_button = Button::default()
+            .with_label("Compare");
+        let text_size = compare_backup_button.measure_label();
+        compare_backup_button.set_size(text_size.0 + 15, text_size.1 + 10);
+        let mut mark_known_good_button = Button::default()
+            .with_label("Mark as Known Good");
+        let text_size = mark_known_good_button.measure_label();
+        mark_known_good_button.set_size(text_size.0 + 15, text_size.1 + 10);
+        let mut restore_known_good_button = Button::default()
+            .with_label("Restore Known Good");
+        let text_size = restore_known_good_button.measure_label();
+        restore_known_good_button.set_size(text_size.0 + 15, text_size.1 + 10);
+        let mut note_backups_button = Button::default()
+            .with_label("Note...");
+        let text_size = note_backups_button.measure_label();
+        note_backups_button.set_size(text_size.0 + 15, text_size.1 + 10);
+        let mut purge_backups_button = Button::default()
+            .with_label("Purge...");
+        let text_size = purge_backups_button.measure_label();
+        purge_backups_button.set_size(text_size.0 + 15, text_size.1 + 10);
 
         restore_backups_button
             .emit(ui_thread_tx.clone(), UiMessage::RestoreBackup);
+        restore_as_copy_button
+            .emit(ui_thread_tx.clone(), UiMessage::RestoreAsCopy);
+        restore_all_latest_button
+            .emit(ui_thread_tx.clone(), UiMessage::RestoreAllLatest);
         delete_backups_button
             .emit(ui_thread_tx.clone(), UiMessage::DeleteBackup);
+        compare_backup_button
+            .emit(ui_thread_tx.clone(), UiMessage::CompareBackup);
+        mark_known_good_button
+            .emit(ui_thread_tx.clone(), UiMessage::MarkKnownGood);
+        restore_known_good_button
+            .emit(ui_thread_tx.clone(), UiMessage::RestoreKnownGood);
+        note_backups_button
+            .emit(ui_thread_tx.clone(), UiMessage::NoteBackup);
+        purge_backups_button
+            .emit(ui_thread_tx.clone(), UiMessage::PurgeBackups);
 
         backed_up_files_buttons.set_size(0, text_size.1 + 10);
 
         backed_up_files_buttons.end();
+        backed_up_files_pane.end();
+        let backed_up_files_pane_fixed_height = pack_content_height(&backed_up_files_pane) - backed_up_files.height();
+
+        let min_live_files_pane_height = live_files_pane_fixed_height + MIN_LIST_HEIGHT;
+        let max_live_files_pane_height = list_panes_height - backed_up_files_pane_fixed_height - MIN_LIST_HEIGHT;
+        let live_files_pane_height = read_window_layout().live_files_pane_height
+            .unwrap_or(live_files_pane_fixed_height + live_files.height())
+            .clamp(min_live_files_pane_height, max(min_live_files_pane_height, max_live_files_pane_height));
+        list_panes_limits.resize(list_panes.x(), list_panes.y() + min_live_files_pane_height,
+            CONTENT_SIZE.0, max_live_files_pane_height - min_live_files_pane_height);
+        list_panes.resizable(&list_panes_limits);
+        fit_pane(&mut live_files_pane, &mut live_files, live_files_pane_fixed_height,
+            list_panes.y(), live_files_pane_height);
+        fit_pane(&mut backed_up_files_pane, &mut backed_up_files, backed_up_files_pane_fixed_height,
+            list_panes.y() + live_files_pane_height, list_panes_height - live_files_pane_height);
+        let mut live_files_copy = live_files.clone();
+        live_files_pane.resize_callback(move |_pane, _x, _y, _w, h|
+            live_files_copy.set_size(live_files_copy.width(), h - live_files_pane_fixed_height));
+        let mut backed_up_files_copy = backed_up_files.clone();
+        backed_up_files_pane.resize_callback(move |_pane, _x, _y, _w, h|
+            backed_up_files_copy.set_size(backed_up_files_copy.width(), h - backed_up_files_pane_fixed_height));
+        list_panes.end();
+
+        let mut saved_live_files_pane_height = live_files_pane_height;
+        list_panes.handle(move |_list_panes, event| {
+            if event == Event::Released && live_files_pane.height() != saved_live_files_pane_height {
+                saved_live_files_pane_height = live_files_pane.height();
+                write_window_layout(&WindowLayout {
+                    live_files_pane_height: Some(saved_live_files_pane_height)
+                });
+            }
+            false
+        });
 
         content.end();
 
@@ -116,73 +337,263 @@ impl MainWindow {
             }
         });
 
-        MainWindow {
+        let mut main_win = MainWindow {
             wind,
+            watcher_state_dot,
+            watcher_state_frame,
+            watcher_state: WatcherState::Stopped,
             status_frame,
             status_stack: Vec::new(),
+            activity_frame,
+            activity: BackupActivity::default(),
+            health_button,
+            health_issues: Vec::new(),
             live_files,
+            live_file_paths,
             backed_up_files,
-        }
+            backed_up_file_paths,
+        };
+        main_win.set_watcher_state(WatcherState::Stopped);
+        main_win.update_activity_frame();
+        main_win
     }
 
     pub fn push_status(&mut self, status: String) {
+        let status = win_common::summarize(&status);
         self.status_frame.set_label(&status);
         self.status_stack.push(status);
     }
 
+    pub fn set_status(&mut self, status: String) {
+        self.status_stack.pop();
+        self.push_status(status);
+    }
+
     pub fn pop_status(&mut self) {
         self.status_stack.pop();
+        self.update_status_frame();
+    }
+
+    /// Shows the issues found by a health check, with only their number until the details are asked for
+    pub fn set_health_issues(&mut self, health_issues: Vec<String>) {
+        let health_label = match health_issues.len() {
+            0 => "All good".to_string(),
+            1 => "1 issue \u{2014} click for details".to_string(),
+            count => format!("{} issues \u{2014} click for details", count)
+        };
+        self.health_button.set_label(&health_label);
+        self.health_issues = health_issues;
+    }
+
+    pub fn get_health_details(&self) -> String {
+        if self.health_issues.is_empty() {
+            "No issues were found".to_string()
+        } else {
+            self.health_issues.join("\n")
+        }
+    }
+
+    pub fn set_watcher_state(&mut self, watcher_state: WatcherState) {
+        let color = match watcher_state {
+            WatcherState::Running => Color::DarkGreen,
+            WatcherState::Paused => Color::DarkYellow,
+            WatcherState::Stopped => Color::Inactive,
+            WatcherState::Idle => Color::DarkYellow,
+            WatcherState::Error(_) => Color::DarkRed
+        };
+        self.watcher_state_dot.set_color(color);
+        self.watcher_state_dot.redraw();
+        self.watcher_state_frame.set_label(&watcher_state.to_string());
+        self.watcher_state = watcher_state;
+    }
+
+    pub fn get_watcher_state(&self) -> WatcherState {
+        self.watcher_state.clone()
+    }
+
+    pub fn on_backup_status(&mut self, backup_status: &BackupStatus) {
+        match backup_status {
+            BackupStatus::Watching { dirs, missing_dirs } => {
+                self.activity.watched_dirs = dirs.clone();
+                self.activity.missing_dirs = missing_dirs.clone();
+            }
+            BackupStatus::WatcherStarted { backend, delay } =>
+                self.activity.watcher_backend = Some((backend.clone(), *delay)),
+            BackupStatus::EventReceived { event } => {
+                self.activity.event_count += 1;
+                self.activity.last_event = Some((Local::now(), event.clone()));
+                // Events alone change nothing that is shown
+                return;
+            }
+            BackupStatus::BackedUp { path, version, change } => {
+                self.activity.backed_up_count += 1;
+                self.activity.last_backup = Some((Local::now(), path.clone(), *version, *change));
+                self.activity.backed_up_live_files.insert(path.clone());
+            }
+            BackupStatus::Pruned { count } =>
+                self.activity.pruned_count += count,
+            BackupStatus::Warning { msg } => {
+                self.activity.warning_count += 1;
+                self.activity.last_warning = Some((Local::now(), msg.clone()));
+            }
+            BackupStatus::Error { .. } =>
+                self.activity.error_count += 1,
+            BackupStatus::MirrorFailed { msg } => {
+                self.activity.mirror_error_count += 1;
+                self.activity.last_mirror_error = Some((Local::now(), msg.clone()));
+            }
+            BackupStatus::MirrorPending { count } =>
+                self.activity.pending_mirror_count = *count,
+            BackupStatus::Heartbeat => {}
+        }
+        self.update_activity_frame();
+    }
+
+    /// Describes what the watcher is doing in enough detail to find out why changes are not backed up
+    pub fn get_diagnostics(&self) -> String {
+        let activity = &self.activity;
+        let mut lines = Vec::new();
+        match &activity.watcher_backend {
+            Some((backend, delay)) => {
+                lines.push(format!("Backend: {}", backend));
+                lines.push(format!("Delay: {} seconds", delay.as_secs()));
+            }
+            None => lines.push("Backend: not started".to_string())
+        }
+        lines.push(format!("Watched folders: {}", activity.watched_dirs.len()));
+        for watched_dir in &activity.watched_dirs {
+            lines.push(format!("    {}", watched_dir.str()));
+        }
+        if !activity.missing_dirs.is_empty() {
+            lines.push(format!("Missing folders, watched for until they are created: {}", activity.missing_dirs.len()));
+            for missing_dir in &activity.missing_dirs {
+                lines.push(format!("    {}", missing_dir.str()));
+            }
+        }
+        lines.push(format!("Events received: {}", activity.event_count));
+        match &activity.last_event {
+            Some((time, event)) => lines.push(format!("Last event at {}: {}", time.format("%T"), event)),
+            None => lines.push("Last event: none".to_string())
+        }
+        if let Some((time, msg)) = &activity.last_warning {
+            lines.push(format!("Warnings: {}, last at {}: {}", activity.warning_count, time.format("%T"), msg));
+        }
+        if let Some((time, msg)) = &activity.last_mirror_error {
+            lines.push(format!("Mirror errors: {}, last at {}: {}",
+                activity.mirror_error_count, time.format("%T"), msg));
+        }
+        lines.join("\n")
+    }
+
+    fn update_activity_frame(&mut self) {
+        let activity = &self.activity;
+        let mut parts = vec![
+            format!("Watching {} folders", activity.watched_dirs.len()),
+            format!("{} backups and {} removals this session", activity.backed_up_count, activity.pruned_count)
+        ];
+        if let Some((time, path, version, change)) = &activity.last_backup {
+            parts.push(format!("last backup {} version {} at {}", path.file_name_str(), version, time.format("%T")));
+            if let Some(change) = change {
+                parts.push(change.to_string());
+            }
+        }
+        if activity.warning_count > 0 {
+            parts.push(format!("{} not backed up", activity.warning_count));
+        }
+        if activity.error_count > 0 {
+            parts.push(format!("{} errors", activity.error_count));
+        }
+        if activity.pending_mirror_count > 0 {
+            parts.push(format!("{} waiting for the mirror folder", activity.pending_mirror_count));
+        }
+        if activity.mirror_error_count > 0 {
+            parts.push(format!("{} mirror errors", activity.mirror_error_count));
+        }
+        // Stays until the folders are back, since nothing is backed up from them meanwhile
+        match activity.missing_dirs.as_slice() {
+            [] => self.activity_frame.set_label_color(Color::ForeGround),
+            [missing_dir] => {
+                parts.insert(0, format!("Source folder missing: {}", missing_dir.str()));
+                self.activity_frame.set_label_color(Color::DarkRed);
+            }
+            missing_dirs => {
+                parts.insert(0, format!("{} source folders missing", missing_dirs.len()));
+                self.activity_frame.set_label_color(Color::DarkRed);
+            }
+        }
+        self.activity_frame.set_label(&parts.join(", "));
+    }
+
+    fn update_status_frame(&mut self) {
         let status = match self.status_stack.last() {
             None => "",
-            Some(status) => &status
+            Some(status) => status
         };
         self.status_frame.set_label(status);
     }
 
-    pub fn set_status(&mut self, status: String) {
-        self.status_frame.set_label(&status);
-        self.status_stack.clear();
-        self.status_stack.push(status);
-    }
-
-    pub fn set_live_files_to_win(&mut self, mut live_files: Vec<PathBuf>) {
+    pub fn set_live_files_to_win(&mut self, mut live_files: Vec<PathBuf>, settings: &Settings) {
         live_files.sort();
-        self.live_files.clear();
-        for live_file in live_files {
-            let live_file_metadata = match live_file.metadata() {
+        let mut manifests = HashMap::new();
+        for dest_path in settings.dest_paths() {
+            let manifest = match read_manifest(dest_path) {
+                Ok(manifest) => manifest,
                 Err(err) => {
-                    error!("Error reading file metadata for {}: {}", live_file.str(), err);
-                    continue;
+                    // Only the counts of pruned versions are missing without it
+                    error!("{}", err);
+                    Manifest::new()
                 }
-                Ok(metadata) =>
-                    metadata
             };
-            let live_file_modified = match live_file_metadata.modified() {
-                Err(err) => {
-                    error!("Error reading file modified time for {}: {}", live_file.str(), err);
+            manifests.insert(dest_path.to_path_buf(), manifest);
+        }
+        // Refreshes often happen while the user is selecting files, so the selection is kept
+        let selected_live_paths = self.get_selected_live_paths();
+        self.live_files.clear();
+        self.live_file_paths.borrow_mut().clear();
+        for live_file in live_files {
+            // The total size and newest timestamp of the files in a live folder
+            let (live_file_len, live_file_modified) = match get_live_file_fingerprint(&live_file) {
+                Err(FWarning(errs)) | Err(FError(errs)) | Err(FFatal(errs)) => {
+                    errs.iter().for_each(|err_msg| error!("{}", err_msg));
                     continue;
                 }
-                Ok(modified) =>
-                    modified
+                Ok(fingerprint) =>
+                    fingerprint
             };
             let live_file_modified: DateTime<Local> = live_file_modified.into();
-            let live_file_size_mb = live_file_metadata.len() / (1000 * 1000);
-            let live_file_size;
-            if live_file_size_mb > 0 {
-                live_file_size = live_file_size_mb.to_string() + "mb";
+            let live_file_size = format_list_size(live_file_len);
+            // All versions ever backed up, and those that remain
+            let live_file_versions = match count_live_file_versions(settings, &manifests, &live_file) {
+                Ok((ever_count, current_count)) => format!("{} / {}", ever_count, current_count),
+                Err(FWarning(errs)) | Err(FError(errs)) | Err(FFatal(errs)) => {
+                    errs.iter().for_each(|err_msg| error!("{}", err_msg));
+                    "?".to_string()
+                }
+            };
+            // Bold when backed up this session
+            let format = if self.activity.backed_up_live_files.contains(&live_file) {
+                "@b"
             } else {
-                live_file_size = (live_file_metadata.len() / 1000).to_string() + "kb";
-            }
-            let live_file_line = format!("{}|{}|{}",
+                ""
+            };
+            let live_file_line = format!("{}{}|{}|{}|{}",
+                format,
                 live_file.str(),
-                live_file_modified.format("%m/%d/%Y %T"),
-                live_file_size
+                format_list_time(&live_file_modified, &settings.date_format),
+                live_file_size,
+                live_file_versions
             );
             self.live_files.add(&live_file_line);
+            if selected_live_paths.contains(&live_file) {
+                self.live_files.select(self.live_files.size());
+            }
+            self.live_file_paths.borrow_mut().push(live_file);
         }
     }
 
-    pub fn set_backed_up_files_to_win(&mut self, mut backed_up_files: Vec<PathBuf>) -> Result<(), FileError> {
+    pub fn set_backed_up_files_to_win(
+        &mut self, settings: Settings, mut backed_up_files: Vec<PathBuf>
+    ) -> Result<(), FileError> {
         let mut errors = vec![];
 
         // Sort the backed up files so they are ready to be displayed to the user
@@ -245,14 +656,14 @@ impl MainWindow {
                 _ => {}
             }
 
-            let backup_number_a = match get_backed_up_version_number(a) {
+            let backup_version_a = match get_backed_up_version(a) {
                 Some(n) => n,
                 None => {
                     errors.push(format!("Invalid backup file name {}", a.str()));
                     return Ordering::Equal;
                 }
             };
-            let backup_number_b = match get_backed_up_version_number(b) {
+            let backup_version_b = match get_backed_up_version(b) {
                 Some(n) => n,
                 None => {
                     errors.push(format!("Invalid backup file name {}", b.str()));
@@ -260,15 +671,18 @@ impl MainWindow {
                 }
             };
 
-            // Reverse number ordering
-            backup_number_b.cmp(&backup_number_a)
+            // Reverse version ordering
+            backup_version_b.cmp(&backup_version_a)
         });
 
         if !errors.is_empty() {
             return Err(FWarning(errors));
         }
 
+        // Refreshes often happen while the user is selecting files, so the selection is kept
+        let selected_backed_up_paths = self.get_selected_backed_up_paths();
         self.backed_up_files.clear();
+        self.backed_up_file_paths.borrow_mut().clear();
         for backed_up_file in backed_up_files {
             let backed_up_file_metadata = match backed_up_file.metadata() {
                 Err(err) => {
@@ -287,19 +701,40 @@ impl MainWindow {
                     modified
             };
             let backed_up_file_modified: DateTime<Local> = backed_up_file_modified.into();
-            let backed_up_file_size_mb = backed_up_file_metadata.len() / (1000 * 1000);
-            let backed_up_file_size;
-            if backed_up_file_size_mb > 0 {
-                backed_up_file_size = backed_up_file_size_mb.to_string() + "mb";
-            } else {
-                backed_up_file_size = (backed_up_file_metadata.len() / 1000).to_string() + "kb";
-            }
-            let backed_up_file_line = format!("{}|{}|{}",
+            let backed_up_file_size = format_list_size(backed_up_file_metadata.len());
+            // Orphaned when no backup pattern matches it any more, so that it cannot be restored to where it came from
+            // unless its sidecar says where that is
+            let (backed_up_file_source, format) =
+                match get_source_dir_for_backed_up_file(settings.clone(), backed_up_file.clone()) {
+                    Ok(source_dir) => (source_dir.str().to_string(), ""),
+                    Err(_) => match read_sidecar(&backed_up_file) {
+                        Ok(Some(metadata)) if metadata.original_path.parent().is_some() =>
+                            (metadata.original_path.parent().unwrap().str().to_string(), "@i"),
+                        _ => (ORPHANED_SOURCE.to_string(), "@i")
+                    }
+                };
+            let backed_up_file_note = match read_note(&backed_up_file) {
+                // On one line, and without the separator of the columns
+                Ok(note) => note.unwrap_or_default().replace(|c| c == '|' || c == '\n' || c == '\r', " "),
+                Err(FWarning(errs)) | Err(FError(errs)) | Err(FFatal(errs)) => {
+                    errs.iter().for_each(|err_msg| error!("{}", err_msg));
+                    "?".to_string()
+                }
+            };
+            let backed_up_file_line = format!("{}{}|{}{}|{}|{}|{}",
+                format,
                 backed_up_file.str(),
-                backed_up_file_modified.format("%m/%d/%Y %T"),
-                backed_up_file_size
+                format,
+                backed_up_file_source,
+                format_list_time(&backed_up_file_modified, &settings.date_format),
+                backed_up_file_size,
+                backed_up_file_note
             );
             self.backed_up_files.add(&backed_up_file_line);
+            if selected_backed_up_paths.contains(&backed_up_file) {
+                self.backed_up_files.select(self.backed_up_files.size());
+            }
+            self.backed_up_file_paths.borrow_mut().push(backed_up_file);
         }
 
         if !errors.is_empty() {
@@ -308,25 +743,72 @@ impl MainWindow {
         Ok(())
     }
 
+    pub fn get_selected_live_paths(&self) -> Vec<PathBuf> {
+        let live_file_paths = self.live_file_paths.borrow();
+        let mut selected_live_paths = Vec::new();
+        for i in 1..=self.live_files.size() {
+            if self.live_files.selected(i) {
+                // The lines may start with format codes, so their paths are taken from the rows instead
+                match live_file_paths.get(i as usize - 1) {
+                    None =>
+                        panic!("illegal state"),
+                    Some(live_path) =>
+                        selected_live_paths.push(live_path.clone())
+                }
+            }
+        }
+        selected_live_paths
+    }
+
     pub fn get_selected_backed_up_paths(&self) -> Vec<PathBuf> {
         let mut selected_backed_up_paths = Vec::new();
+        let backed_up_file_paths = self.backed_up_file_paths.borrow();
         for i in 1..=self.backed_up_files.size() {
             if self.backed_up_files.selected(i) {
-                let selected_line = match self.backed_up_files.text(i) {
-                    None =>
-                        panic!("illegal state"),
-                    Some(text) =>
-                        text
-                };
-                let backed_up_path = match selected_line.split("|").next() {
+                // The lines may start with format codes, so their paths are taken from the rows instead
+                match backed_up_file_paths.get(i as usize - 1) {
                     None =>
                         panic!("illegal state"),
                     Some(backed_up_path) =>
-                        backed_up_path
-                };
-                selected_backed_up_paths.push(PathBuf::from(backed_up_path));
+                        selected_backed_up_paths.push(backed_up_path.clone())
+                }
             }
         }
         selected_backed_up_paths
     }
+}
+
+/// Formats `time` for the file lists with `date_format`, or with the default format when `date_format` is invalid, since
+/// formatting with an invalid format panics
+pub fn format_list_time(time: &DateTime<Local>, date_format: &str) -> String {
+    if is_valid_date_format(date_format) {
+        time.format(date_format).to_string()
+    } else {
+        time.format(DEFAULT_DATE_FORMAT).to_string()
+    }
+}
+
+/// Formats the file size `len` for the file lists, in mb, or in kb below a mb
+pub fn format_list_size(len: u64) -> String {
+    let size_mb = len / (1000 * 1000);
+    if size_mb > 0 {
+        size_mb.to_string() + "mb"
+    } else {
+        (len / 1000).to_string() + "kb"
+    }
+}
+
+/// The height that `pack` lays its children out in
+fn pack_content_height(pack: &Pack) -> i32 {
+    let child_heights: i32 = (0..pack.children())
+        .filter_map(|i| pack.child(i))
+        .map(|child| child.height())
+        .sum();
+    child_heights + pack.spacing() * max(pack.children() - 1, 0)
+}
+
+/// Places `pane` at `y` with `height`, giving `list` all of it that its other children do not take
+fn fit_pane(pane: &mut Pack, list: &mut MultiBrowser, fixed_height: i32, y: i32, height: i32) {
+    pane.resize(pane.x(), y, pane.width(), height);
+    list.set_size(list.width(), height - fixed_height);
 }
\ No newline at end of file
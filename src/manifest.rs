@@ -0,0 +1,208 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use parking_lot::{const_mutex, Mutex};
+use serde::{Deserialize, Serialize};
+
+use crate::file::{BackupVersion, FileError, get_backed_up_files, get_backed_up_version, get_dest_path_for_backed_up_file, get_file_metadata, PathExt, strip_version_suffix_from_backed_up_file_path};
+use crate::file::FileError::{FError, FFatal, FWarning};
+use crate::inventory::hash_file;
+use crate::settings::Settings;
+
+/// Written into `backup_dest_path` next to the `.valbak` marker, holding what Valbak knows about the backed up files
+/// beyond what their names and timestamps tell
+pub const MANIFEST_FILE_NAME: &str = ".valbak_manifest.json";
+pub const MANIFEST_SCHEMA_VERSION: &str = "1";
+
+/// Serializes the updates of the manifest, which both the backup thread and the UI thread make
+static MANIFEST_LOCK: Mutex<()> = const_mutex(());
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Manifest {
+    pub schema_version: String,
+    /// Keyed by the path of the backed up files without a version suffix, relative to the backup destination
+    #[serde(default)]
+    pub files: BTreeMap<String, ManifestEntry>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct ManifestEntry {
+    /// How many versions were ever backed up, including those since pruned or deleted
+    #[serde(default)]
+    pub backup_count: u64,
+    /// The highest counter version ever backed up, which is not given to a new version even when no version with it
+    /// remains
+    #[serde(default)]
+    pub last_counter: Option<u32>,
+}
+
+impl Manifest {
+    pub fn new() -> Manifest {
+        Manifest {
+            schema_version: MANIFEST_SCHEMA_VERSION.to_string(),
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// The entry of the backed up file `stripped_file_path`, given without a version suffix
+    pub fn get_entry(&self, backup_dest_path: &Path, stripped_file_path: &Path) -> Option<&ManifestEntry> {
+        get_manifest_key(backup_dest_path, stripped_file_path).and_then(|key| self.files.get(&key))
+    }
+}
+
+pub fn get_manifest_path(backup_dest_path: &Path) -> PathBuf {
+    backup_dest_path.join(MANIFEST_FILE_NAME)
+}
+
+/// The key of the backed up file `stripped_file_path`, given without a version suffix, or `None` when it is not in
+/// `backup_dest_path`
+fn get_manifest_key(backup_dest_path: &Path, stripped_file_path: &Path) -> Option<String> {
+    let relative_path = stripped_file_path.strip_prefix(backup_dest_path).ok()?;
+    // The same key on every platform, so that a destination can move between machines
+    let components: Vec<String> = relative_path.components()
+        .map(|component| component.as_os_str().to_string_lossy().to_string())
+        .collect();
+    Some(components.join("/"))
+}
+
+/// Reads the manifest of `backup_dest_path`, or returns an empty manifest when there is none yet
+pub fn read_manifest(backup_dest_path: &Path) -> Result<Manifest, FileError> {
+    let manifest_path = get_manifest_path(backup_dest_path);
+    if !manifest_path.exists() {
+        return Ok(Manifest::new());
+    }
+    let manifest_str = match std::fs::read_to_string(&manifest_path) {
+        Ok(manifest_str) => manifest_str,
+        Err(err) => return Err(FError(vec![format!("Error reading {}: {}", manifest_path.str(), err)]))
+    };
+    match serde_json::from_str(&manifest_str) {
+        Ok(manifest) => Ok(manifest),
+        Err(err) => Err(FError(vec![format!("Invalid backup manifest {}: {}", manifest_path.str(), err)]))
+    }
+}
+
+/// Replaces the manifest of `backup_dest_path` with `manifest`. It is written to a temp file first, so that an
+/// interrupted write leaves the previous manifest intact.
+pub fn write_manifest(backup_dest_path: &Path, manifest: &Manifest) -> Result<(), FileError> {
+    let manifest_path = get_manifest_path(backup_dest_path);
+    let temp_manifest_path = backup_dest_path.join(format!("_{}", MANIFEST_FILE_NAME));
+    let manifest_str = match serde_json::to_string_pretty(manifest) {
+        Ok(manifest_str) => manifest_str,
+        Err(err) => return Err(FError(vec![format!("Error creating backup manifest: {}", err)]))
+    };
+    if let Err(err) = std::fs::write(&temp_manifest_path, manifest_str) {
+        return Err(FError(vec![format!("Error writing {}: {}", temp_manifest_path.str(), err)]));
+    }
+    if let Err(err) = std::fs::rename(&temp_manifest_path, &manifest_path) {
+        return Err(FError(vec![format!("Error writing {}: {}", manifest_path.str(), err)]));
+    }
+    Ok(())
+}
+
+/// Reads the manifest of `backup_dest_path`, applies `update` to it and writes it back
+pub fn update_manifest(backup_dest_path: &Path, update: impl FnOnce(&mut Manifest)) -> Result<(), FileError> {
+    let _manifest_guard = MANIFEST_LOCK.lock();
+    let mut manifest = read_manifest(backup_dest_path)?;
+    update(&mut manifest);
+    write_manifest(backup_dest_path, &manifest)
+}
+
+/// What rebuilding a manifest found
+pub struct ManifestRebuild {
+    pub file_count: usize,
+    pub version_count: usize,
+    /// Backed up files that could not be read, which are left out
+    pub errs: Vec<String>,
+}
+
+/// Rebuilds the manifests of the destination folders of `settings` from the backed up files on disk, e.g. after
+/// versions were added or removed by other programs. Every backed up file is read in full, so that unreadable ones are
+/// left out and reported. Versions that were pruned or deleted leave nothing on disk, so the counts and counters of the
+/// previous manifests are kept where they are higher, including those of files with no versions left, as long as the
+/// previous manifests can still be read.
+pub fn rebuild_manifest(settings: &Settings) -> Result<ManifestRebuild, FileError> {
+    let (backed_up_file_paths, scan_err) = get_backed_up_files(settings.clone());
+    if let Some(scan_err) = scan_err {
+        // A manifest rebuilt from some of the files would undercount the others
+        return Err(scan_err);
+    }
+    let mut rebuild = ManifestRebuild { file_count: 0, version_count: 0, errs: Vec::new() };
+    // Each destination folder counts the files backed up to it
+    for backup_dest_path in settings.dest_paths() {
+        let dest_file_paths: Vec<&PathBuf> = backed_up_file_paths.iter()
+            .filter(|backed_up_file_path|
+                get_dest_path_for_backed_up_file(settings, backed_up_file_path) == backup_dest_path)
+            .collect();
+        rebuild_dest_manifest(backup_dest_path, &dest_file_paths, &mut rebuild)?;
+    }
+    Ok(rebuild)
+}
+
+fn rebuild_dest_manifest(
+    backup_dest_path: &Path, backed_up_file_paths: &[&PathBuf], rebuild: &mut ManifestRebuild
+) -> Result<(), FileError> {
+    let mut manifest = Manifest::new();
+    for &backed_up_file_path in backed_up_file_paths {
+        let key = match strip_version_suffix_from_backed_up_file_path(&backed_up_file_path)
+            .and_then(|path| get_manifest_key(backup_dest_path, &path))
+        {
+            Some(key) => key,
+            None => continue
+        };
+        let readable = get_file_metadata(backed_up_file_path)
+            .and_then(|_metadata| hash_file(backed_up_file_path));
+        if let Err(FWarning(mut errs)) | Err(FError(mut errs)) | Err(FFatal(mut errs)) = readable {
+            rebuild.errs.append(&mut errs);
+            continue;
+        }
+        let counter = match get_backed_up_version(backed_up_file_path) {
+            Some(BackupVersion::Counter(counter)) => Some(counter),
+            _ => None
+        };
+        let entry = manifest.files.entry(key).or_default();
+        entry.backup_count += 1;
+        entry.last_counter = entry.last_counter.max(counter);
+        rebuild.version_count += 1;
+    }
+    rebuild.file_count += manifest.files.len();
+
+    let _manifest_guard = MANIFEST_LOCK.lock();
+    match read_manifest(backup_dest_path) {
+        Ok(previous_manifest) => {
+            // Also of the files with no versions left, so that their counters are not given out again
+            for (key, previous_entry) in previous_manifest.files {
+                let entry = manifest.files.entry(key).or_default();
+                entry.backup_count = entry.backup_count.max(previous_entry.backup_count);
+                entry.last_counter = entry.last_counter.max(previous_entry.last_counter);
+            }
+        }
+        Err(FWarning(mut errs)) | Err(FError(mut errs)) | Err(FFatal(mut errs)) =>
+            rebuild.errs.append(&mut errs)
+    }
+    write_manifest(backup_dest_path, &manifest)
+}
+
+/// Counts the new version `backed_up_file_path` in the manifest of `backup_dest_path`, and records its counter as the
+/// last one used
+pub fn record_backup(backup_dest_path: &Path, backed_up_file_path: &PathBuf) -> Result<(), FileError> {
+    let stripped_file_path = strip_version_suffix_from_backed_up_file_path(backed_up_file_path);
+    let key = match stripped_file_path.and_then(|path| get_manifest_key(backup_dest_path, &path)) {
+        Some(key) => key,
+        None => return Err(FError(vec![format!("{} is not a backed up file", backed_up_file_path.str())]))
+    };
+    let counter = match get_backed_up_version(backed_up_file_path) {
+        Some(BackupVersion::Counter(counter)) => Some(counter),
+        _ => None
+    };
+    update_manifest(backup_dest_path, |manifest| {
+        let entry = manifest.files.entry(key).or_default();
+        entry.backup_count += 1;
+        entry.last_counter = entry.last_counter.max(counter);
+    })
+}
\ No newline at end of file
@@ -0,0 +1,127 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use filetime::{FileTime, set_file_mtime};
+use log::{info, warn};
+use parking_lot::{const_mutex, Mutex};
+
+use crate::file::{FileError, get_dest_path_for_backed_up_file, get_file_metadata, PathExt};
+use crate::file::FileError::FError;
+use crate::settings::Settings;
+
+/// How often mirroring is attempted again while the mirror destination is unavailable
+pub const MIRROR_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Backed up files that were not mirrored yet, oldest first
+static PENDING_MIRRORS: Mutex<Vec<PathBuf>> = const_mutex(Vec::new());
+static MIRROR_THREAD_RUNNING: AtomicBool = AtomicBool::new(false);
+/// Why backed up files were not mirrored, since the last call of [`take_mirror_errors`]
+static MIRROR_ERRORS: Mutex<Vec<String>> = const_mutex(Vec::new());
+
+/// Where `backed_up_file_path` is mirrored to in `secondary_dest_path`, or `None` when it is not in the backup
+/// destination. The destination folders of the backup patterns are not mirrored, not even those inside the backup
+/// destination, as the mirror folder is pruned without their backup patterns.
+pub fn get_mirror_path(settings: &Settings, secondary_dest_path: &Path, backed_up_file_path: &Path) -> Option<PathBuf> {
+    if get_dest_path_for_backed_up_file(settings, backed_up_file_path) != settings.backup_dest_path {
+        return None;
+    }
+    backed_up_file_path.strip_prefix(&settings.backup_dest_path).ok()
+        .map(|relative_path| secondary_dest_path.join(relative_path))
+}
+
+/// Queues the new backed up file `backed_up_file_path` to be copied to the mirror destination of `settings`, if any,
+/// after any backed up files still waiting to be mirrored
+pub fn mirror_backed_up_file(settings: &Settings, backed_up_file_path: &PathBuf) {
+    let secondary_dest_path = match &settings.secondary_dest_path {
+        Some(secondary_dest_path) => secondary_dest_path,
+        None => return
+    };
+    if get_mirror_path(settings, secondary_dest_path, backed_up_file_path).is_none() {
+        return;
+    }
+    PENDING_MIRRORS.lock().push(backed_up_file_path.clone());
+    start_mirroring(settings);
+}
+
+/// The number of backed up files waiting to be mirrored
+pub fn count_pending_mirrors() -> usize {
+    PENDING_MIRRORS.lock().len()
+}
+
+/// Returns the errors of mirroring since the last call, oldest first, to be shown to the user
+pub fn take_mirror_errors() -> Vec<String> {
+    std::mem::take(&mut *MIRROR_ERRORS.lock())
+}
+
+/// Mirrors the backed up files waiting to be mirrored on a separate thread, unless one is already doing so, so that
+/// an unavailable mirror destination never holds up the next backup. Mirroring stops when the mirror destination is
+/// unavailable, e.g. a NAS that is turned off, and the files that were not mirrored wait for the next call. A file that
+/// fails to mirror while the mirror destination is available would fail the same way again, so it is not retried.
+/// Files pruned from the backup destination meanwhile are no longer mirrored.
+pub fn start_mirroring(settings: &Settings) {
+    let secondary_dest_path = match &settings.secondary_dest_path {
+        Some(secondary_dest_path) => secondary_dest_path.clone(),
+        None => {
+            PENDING_MIRRORS.lock().clear();
+            return;
+        }
+    };
+    if MIRROR_THREAD_RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let settings = settings.clone();
+    std::thread::spawn(move || {
+        loop {
+            let backed_up_file_path = match PENDING_MIRRORS.lock().first() {
+                Some(backed_up_file_path) => backed_up_file_path.clone(),
+                None => break
+            };
+            if backed_up_file_path.exists() {
+                match copy_to_mirror(&settings, &secondary_dest_path, &backed_up_file_path) {
+                    Ok(()) => {}
+                    // Also when it became unavailable during the copy. The files left waiting are shown rather than an
+                    // error on every attempt.
+                    Err(err) if !secondary_dest_path.is_dir() => {
+                        warn!("{}, retrying {} files later", err, PENDING_MIRRORS.lock().len());
+                        break;
+                    }
+                    Err(err) => {
+                        warn!("{}, not mirroring it", err);
+                        MIRROR_ERRORS.lock().push(err.to_string());
+                    }
+                }
+            }
+            PENDING_MIRRORS.lock().retain(|path| *path != backed_up_file_path);
+        }
+        MIRROR_THREAD_RUNNING.store(false, Ordering::SeqCst);
+    });
+}
+
+fn copy_to_mirror(settings: &Settings, secondary_dest_path: &Path, backed_up_file_path: &PathBuf) -> Result<(), FileError> {
+    let mirror_err = |err| FError(vec![format!("Error mirroring {}: {}", backed_up_file_path.str(), err)]);
+    if !secondary_dest_path.is_dir() {
+        return Err(FError(vec![format!("Mirror folder {} is not available", secondary_dest_path.str())]));
+    }
+    let mirror_path = match get_mirror_path(settings, secondary_dest_path, backed_up_file_path) {
+        Some(mirror_path) => mirror_path,
+        None => return Err(FError(vec![format!("{} is not a backed up file", backed_up_file_path.str())]))
+    };
+    let mirror_folder = mirror_path.parent().unwrap();
+    // The date folder of the dated layout as well, if needed
+    std::fs::create_dir_all(mirror_folder).map_err(mirror_err)?;
+    // Written next to its final name first, so that an interrupted copy is never taken for a backed up file
+    let temp_mirror_path = mirror_folder.join(format!("_{}", mirror_path.file_name_str()));
+    std::fs::copy(backed_up_file_path, &temp_mirror_path).map_err(mirror_err)?;
+    let (_metadata, modified) = get_file_metadata(backed_up_file_path)?;
+    set_file_mtime(&temp_mirror_path, FileTime::from_system_time(modified)).map_err(mirror_err)?;
+    std::fs::rename(&temp_mirror_path, &mirror_path).map_err(mirror_err)?;
+    info!("Mirrored {} to {}", backed_up_file_path.str(), mirror_path.str());
+    Ok(())
+}
\ No newline at end of file
@@ -0,0 +1,141 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::path::Path;
+
+use fltk::app;
+use fltk::browser::Browser;
+use fltk::enums::{Align, CallbackTrigger, Color};
+use fltk::frame::Frame;
+use fltk::group::Pack;
+use fltk::input::Input;
+use fltk::prelude::{BrowserExt, GroupExt, InputExt, WidgetExt, WindowExt};
+use fltk::window::Window;
+use valbak::file::{get_live_files_for_pattern, PathExt};
+use valbak::file::FileError::{FError, FFatal, FWarning};
+use valbak::settings::{BackupFilePattern, normalize_path, Settings};
+
+use crate::UiMessage;
+use crate::UiMessage::{PatternTestChanged, PatternTestClose};
+use crate::win_common::{make_bottom_button_group, make_section_header};
+
+/// Lists the files that a folder and file pattern match as they are typed, to try out a backup pattern before adding it
+pub struct PatternTestWindow {
+    pub wind: Window,
+    source_dir_input: Input,
+    filename_pattern_input: Input,
+    matches_browser: Browser,
+    /// How many files match, or why the pattern does not work
+    result_frame: Frame,
+    /// Not shown in the window, so kept from the pattern being tested
+    directory_mode: bool,
+}
+
+impl PatternTestWindow {
+
+    pub fn new(sender: app::Sender<UiMessage>) -> PatternTestWindow {
+        static WINDOW_SIZE: (i32, i32) = (640, 480);
+        static CONTENT_SIZE: (i32, i32) = (WINDOW_SIZE.0 - 20, WINDOW_SIZE.1 - 20);
+
+        let mut wind = Window::default().with_label("Test File Pattern");
+        wind.make_modal(true);
+        wind.set_size(WINDOW_SIZE.0, WINDOW_SIZE.1);
+
+        let mut content = Pack::default()
+            .with_pos(10, 10);
+        content.set_spacing(5);
+
+        make_section_header("Folder", false);
+        let mut source_dir_input = Input::default();
+        source_dir_input.set_size(0, source_dir_input.text_size() + 12);
+        source_dir_input.set_trigger(CallbackTrigger::Changed);
+        source_dir_input.emit(sender.clone(), PatternTestChanged);
+
+        make_section_header("File Pattern", true);
+        let mut filename_pattern_input = Input::default();
+        filename_pattern_input.set_size(0, filename_pattern_input.text_size() + 12);
+        filename_pattern_input.set_trigger(CallbackTrigger::Changed);
+        filename_pattern_input.emit(sender.clone(), PatternTestChanged);
+
+        make_section_header("Matching Files", true);
+        let mut matches_browser = Browser::default();
+        matches_browser.set_size(0, CONTENT_SIZE.1 - 210);
+        // Takes up the height gained when the window is resized
+        content.resizable(&matches_browser);
+
+        let mut result_frame = Frame::default();
+        result_frame.set_size(0, 20);
+        result_frame.set_align(Align::Left | Align::Inside);
+
+        content.set_size(CONTENT_SIZE.0, result_frame.y() + result_frame.height());
+
+        let mut buttons = make_bottom_button_group(&mut content, CONTENT_SIZE.0, &[], &["Close"]);
+        buttons[0].emit(sender.clone(), PatternTestClose);
+
+        wind.end();
+        wind.resizable(&content);
+        wind.size_range(WINDOW_SIZE.0, WINDOW_SIZE.1, 0, 0);
+
+        wind.set_callback(move |_wind| sender.send(PatternTestClose));
+
+        PatternTestWindow {
+            wind,
+            source_dir_input,
+            filename_pattern_input,
+            matches_browser,
+            result_frame,
+            directory_mode: false
+        }
+    }
+
+    /// Starts with `backup_pattern`, e.g. the one selected in the settings
+    pub fn set_pattern(&mut self, backup_pattern: &BackupFilePattern) {
+        self.source_dir_input.set_value(backup_pattern.source_dir.str());
+        self.filename_pattern_input.set_value(&backup_pattern.filename_pattern);
+        self.directory_mode = backup_pattern.directory_mode;
+    }
+
+    /// Lists the live files that the folder and file pattern in the window match, matching them as `settings` say
+    pub fn test_pattern(&mut self, settings: &Settings) {
+        self.matches_browser.clear();
+        let backup_pattern = BackupFilePattern {
+            source_dir: normalize_path(Path::new(self.source_dir_input.value().trim())),
+            filename_pattern: self.filename_pattern_input.value().trim().to_string(),
+            dest_subdir: None,
+            dest_override: None,
+            directory_mode: self.directory_mode,
+            enabled: true
+        };
+        let result = if backup_pattern.filename_pattern.is_empty() {
+            Err("Enter a file pattern, e.g. *.db".to_string())
+        } else if backup_pattern.filename_pattern.contains(|c| c == '/' || c == '\\') {
+            // As the settings require
+            Err("The file pattern must be a file name without a folder".to_string())
+        } else if !backup_pattern.source_dir.is_dir() {
+            Err("The folder does not exist".to_string())
+        } else {
+            match get_live_files_for_pattern(settings, &backup_pattern) {
+                Ok(live_file_paths) => Ok(live_file_paths),
+                Err(FWarning(errs)) | Err(FError(errs)) | Err(FFatal(errs)) => Err(errs.join(" "))
+            }
+        };
+        match result {
+            Ok(live_file_paths) => {
+                for live_file_path in &live_file_paths {
+                    self.matches_browser.add(live_file_path.file_name_str());
+                }
+                let kind = if self.directory_mode { "folders" } else { "files" };
+                self.result_frame.set_label(&format!("{} {} match", live_file_paths.len(), kind));
+                self.result_frame.set_label_color(Color::ForeGround);
+            }
+            Err(err_msg) => {
+                self.result_frame.set_label(&err_msg);
+                self.result_frame.set_label_color(Color::DarkRed);
+            }
+        }
+        self.result_frame.redraw();
+    }
+}
\ No newline at end of file
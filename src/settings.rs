@@ -4,238 +4,381 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
-use std::fmt::{Display, Formatter};
-use std::fs;
-use std::io::ErrorKind;
-use std::io::ErrorKind::NotFound;
+
 use std::path::{Path, PathBuf};
 
-use directories::ProjectDirs;
-use fltk::dialog::{alert_default, choice_default};
-use glob::Pattern;
-use log::{debug, error, info};
+use chrono::format::{Item, StrftimeItems};
 use serde::{Deserialize, Serialize};
-use thiserror::Error;
 
 use crate::file::PathExt;
-use crate::settings::SettingsError::{SError, SNotFound, SWarning};
 
 pub const SETTINGS_VERSION: &str = "1";
+pub const DEFAULT_ERROR_SUMMARY_LEN: usize = 100;
+pub const DEFAULT_DATE_FORMAT: &str = "%m/%d/%Y %T";
+/// The characters that can separate the version suffix from the name of the live file. Backed up files with any of them
+/// are recognized, so that changing `version_separator` never hides existing backups.
+pub const VERSION_SEPARATORS: [char; 3] = ['.', '@', '~'];
+pub const DEFAULT_VERSION_SEPARATOR: char = '.';
+pub const DEFAULT_TRASH_RETENTION_DAYS: u32 = 30;
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
 pub struct Settings {
     pub settings_version: String,
     pub backup_patterns: Vec<BackupFilePattern>,
     pub backup_dest_path: PathBuf,
     pub backup_count: u8,
     pub backup_delay_sec: u8,
+    #[serde(default)]
+    pub temp_file_location: TempFileLocation,
+    /// How long a changed file must go without further changes before it is backed up, or 0 to back up on every change
+    #[serde(default)]
+    pub quiet_period_ms: u32,
+    /// Whether zero-byte live files are backed up, rather than skipped as incomplete writes
+    #[serde(default)]
+    pub backup_empty_files: bool,
+    /// Live files larger than this are skipped with a warning
+    #[serde(default)]
+    pub max_backup_file_size_mb: Option<u64>,
+    /// Copy the full permission bits of files when backing up and restoring, and on Unix attempt to copy their
+    /// ownership too. Changing ownership requires appropriate privileges; without them only a warning is logged.
+    #[serde(default)]
+    pub preserve_permissions: bool,
+    /// After a backup of a file, further changes to it are backed up no sooner than this long after it, at which point
+    /// the file as it is then is backed up. 0 means no limit.
+    #[serde(default)]
+    pub min_backup_interval_sec: u32,
+    /// How the version suffix of new backed up files is chosen
+    #[serde(default)]
+    pub version_scheme: VersionScheme,
+    /// Back up the current live file, if it has no backup yet, before a restore overwrites it
+    #[serde(default)]
+    pub snapshot_before_restore: bool,
+    /// Relative source folders are resolved against this folder when the settings are read, e.g. for a portable setup
+    /// where the drive letter changes between machines. Absolute source folders are used unchanged.
+    #[serde(default)]
+    pub base_dir: Option<PathBuf>,
+    /// Changed files are also backed up whenever a process with this name, e.g. `valheim.exe`, exits
+    #[serde(default)]
+    pub watch_process_name: Option<String>,
+    /// Instead of backing up a changed file whose content is identical to its newest backup, give that backup the
+    /// changed file's timestamp
+    #[serde(default)]
+    pub dedup_identical: bool,
+    /// Encrypt new backups with a passphrase that is entered once per session and never saved
+    #[serde(default)]
+    pub encrypt: bool,
+    /// When all backed up files together are larger than this, the oldest versions across all files are removed until
+    /// they are not. The newest version of each file is always kept.
+    #[serde(default)]
+    pub max_total_size_mb: Option<u64>,
+    /// On Windows, copy live files that the game holds open by reading them while it keeps writing, rather than
+    /// failing until the game closes them
+    #[serde(default)]
+    pub copy_locked_files: bool,
+    /// Error messages and status messages longer than this are shortened by removing the middle
+    #[serde(default = "default_error_summary_len")]
+    pub error_summary_len: usize,
+    /// What a restore does when the live file is newer than the backed up file replacing it
+    #[serde(default)]
+    pub overwrite_newer: OverwritePolicy,
+    /// Also watch `backup_dest_path`, so that the list of backed up files is refreshed when other programs add or remove
+    /// versions, e.g. a cloud sync resolving a conflict
+    #[serde(default)]
+    pub watch_backup_dest: bool,
+    /// The `chrono` format of the modified times in the file lists, e.g. `%Y-%m-%d %H:%M`
+    #[serde(default = "default_date_format")]
+    pub date_format: String,
+    /// A second backup destination, e.g. on a NAS, that every new backed up file is copied to as well and that is
+    /// pruned like `backup_dest_path`. While it is unavailable the copies wait and are retried.
+    #[serde(default)]
+    pub secondary_dest_path: Option<PathBuf>,
+    /// Write a `.meta.json` sidecar next to each new backed up file, recording the absolute path, size and timestamp of
+    /// the live file. Restores use the recorded path over the one derived from the backup patterns.
+    #[serde(default)]
+    pub write_sidecars: bool,
+    /// Match the file patterns regardless of case, e.g. `*.db` also matches `WORLD.DB`. Defaults to what the platform
+    /// does, i.e. only on Windows.
+    #[serde(default = "default_case_insensitive")]
+    pub case_insensitive: bool,
+    /// Ask before each restore where to restore the file to, showing the live file derived from the backup patterns.
+    /// Restores to a folder that does not exist, e.g. when recovering on another machine, always ask.
+    #[serde(default)]
+    pub confirm_restore_targets: bool,
+    /// Which old versions of each file are kept when pruning
+    #[serde(default)]
+    pub retention_policy: RetentionPolicy,
+    /// A shell command that is run after each backup of a changed file, e.g. to sync the backup destination to the
+    /// cloud, with the paths of the backed up file and the live file in `VALBAK_BACKED_UP_PATH` and `VALBAK_LIVE_PATH`.
+    /// It runs with the same rights as Valbak, so anyone who can edit the settings file can run any command through
+    /// it.
+    #[serde(default)]
+    pub post_backup_command: Option<String>,
+    /// Look for changes by checking the source folders every this many seconds instead of relying on notifications
+    /// from the system, which are unreliable or missing on network drives and some file systems. Changes are then
+    /// backed up within about twice this long.
+    #[serde(default)]
+    pub poll_interval_sec: Option<u32>,
+    /// Ask before restoring the selected backups over their live files. Deleting backups always asks.
+    #[serde(default = "default_confirm_restore")]
+    pub confirm_restore: bool,
+    /// Separates the version suffix of new backed up files from the name of the live file, e.g. `@` for `world.db@3`,
+    /// for file managers that take `.3` for an extension. One of `VERSION_SEPARATORS`.
+    #[serde(default = "default_version_separator")]
+    pub version_separator: char,
+    /// How the backed up files are arranged in `backup_dest_path`
+    #[serde(default)]
+    pub backup_layout: BackupLayout,
+    /// A key combination such as `Ctrl+Alt+B` that backs up all changed live files from anywhere, e.g. from within the
+    /// game, where the platform supports global hotkeys
+    #[serde(default)]
+    pub backup_hotkey: Option<String>,
+    /// Move deleted and pruned backups into the trash folder of their destination instead of deleting them, so that
+    /// they can still be recovered until `trash_retention_days` have passed or the trash is emptied
+    #[serde(default)]
+    pub delete_to_trash: bool,
+    #[serde(default = "default_trash_retention_days")]
+    pub trash_retention_days: u32,
+    /// Let the wildcards of the file patterns match hidden files: names starting with a dot, and on Windows files with
+    /// the hidden or system attribute, which some games give their saves. New settings default to what the platform
+    /// does, i.e. only on Windows, where hidden files are hidden by their attribute rather than their name. Settings
+    /// from before this option default to including them, as the file patterns have always matched hidden names.
+    #[serde(default = "default_include_hidden_of_existing_settings")]
+    pub include_hidden: bool,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
-pub struct BackupFilePattern {
-    pub source_dir: PathBuf,
-    pub filename_pattern: String
+fn default_error_summary_len() -> usize {
+    DEFAULT_ERROR_SUMMARY_LEN
 }
 
-impl BackupFilePattern {
-    pub fn to_path(&self) -> PathBuf {
-        self.source_dir.join(self.filename_pattern.clone())
-    }
+fn default_date_format() -> String {
+    DEFAULT_DATE_FORMAT.to_string()
 }
 
-#[derive(Error, Debug)]
-pub enum SettingsError {
-    SNotFound(Option<Settings>),
-    SWarning(Settings, String),
-    SError(String)
+fn default_case_insensitive() -> bool {
+    cfg!(windows)
 }
 
-impl SettingsError {
-    pub fn to_string(&self) -> String {
-        match self {
-            SNotFound(_settings) =>
-                "Settings Not Found".to_string(),
-            SWarning(_settings, err) =>
-                err.clone(),
-            SError(err) =>
-                err.clone()
-        }
-    }
+fn default_include_hidden() -> bool {
+    cfg!(windows)
 }
 
-impl Display for SettingsError {
-    fn fmt(&self, _f: &mut Formatter<'_>) -> std::fmt::Result {
-        todo!()
-    }
+fn default_include_hidden_of_existing_settings() -> bool {
+    true
 }
 
-pub fn get_settings() -> Result<Settings, SettingsError> {
-    let settings = match read_settings() {
-        Err(SettingsError::SNotFound(None)) => {
-            let settings = write_settings(get_default_settings()?)?;
-            Err(SNotFound(Some(settings)))
-        },
-        Err(err) =>
-            Err(err),
-        Ok(settings) =>
-            Ok(settings)
-    }?;
-
-    validate_settings(settings)
-}
-
-pub fn validate_settings(settings: Settings) -> Result<Settings, SettingsError> {
-    let mut err = Ok(());
-    for backup_pattern in settings.backup_patterns.iter() {
-        if !backup_pattern.source_dir.is_dir() {
-            err = Err(
-                format!("Backup folder does not exist: {}", backup_pattern.source_dir.str()));
-            break;
-        }
-        if let Err(_) = Pattern::new(&backup_pattern.filename_pattern) {
-            err = Err(format!("Invalid file pattern: {}", backup_pattern.filename_pattern));
-        }
+fn default_trash_retention_days() -> u32 {
+    DEFAULT_TRASH_RETENTION_DAYS
+}
+
+fn default_confirm_restore() -> bool {
+    true
+}
+
+fn default_version_separator() -> char {
+    DEFAULT_VERSION_SEPARATOR
+}
+
+/// Where intermediate copies are written before being moved into place
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum TempFileLocation {
+    /// The backup folder of the file being backed up or restored
+    BackupFolder,
+    /// The system temp folder
+    SystemTemp,
+    /// A `.valbak_tmp` folder inside `backup_dest_path`
+    BackupDestTemp,
+}
+
+impl Default for TempFileLocation {
+    fn default() -> Self {
+        TempFileLocation::BackupFolder
     }
-    if let Err(err_msg) = err {
-        return Err(SWarning(settings, err_msg));
+}
+
+/// The version suffix appended to backed up file names
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum VersionScheme {
+    /// An incrementing integer, e.g. `world.db.3`
+    Counter,
+    /// The local time of the backup, e.g. `world.db.2024-01-15T13-22-05`
+    Timestamp,
+}
+
+impl Default for VersionScheme {
+    fn default() -> Self {
+        VersionScheme::Counter
     }
+}
+
+/// Where in `backup_dest_path` new backed up files are put. Backed up files are found in either layout, so changing it
+/// only affects new backups.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum BackupLayout {
+    /// In the backup folder of their pattern, e.g. `worlds/world.db.3`
+    Nested,
+    /// In the backup folder of their pattern inside a folder for the day they were backed up, e.g.
+    /// `2024-01-15/worlds/world.db.3`. The versions of a file in all date folders are pruned together.
+    Dated,
+}
 
-    if !settings.backup_patterns.is_empty() && settings.backup_dest_path == PathBuf::new() {
-        let err_msg = "Missing destination folder".to_string();
-        return Err(SWarning(settings, err_msg));
+impl Default for BackupLayout {
+    fn default() -> Self {
+        BackupLayout::Nested
     }
-    if settings.backup_dest_path != PathBuf::new() && !settings.backup_dest_path.is_dir() {
-        match choice_default(
-            format!("Destination folder does not exist: {}\nCreate it?",
-                settings.backup_dest_path.str()).as_str(),
-            "Cancel", "Yes", ""
-        ) {
-            0 => {  // Cancel
-                return Err(SWarning(settings, "".to_string()));
-            }
-            _ => {  // Yes
-                if let Err(err) = std::fs::create_dir_all(settings.backup_dest_path.clone()) {
-                    error!("{}", err);
-                    alert_default(format!("Error: {}", err).as_str());
-                }
-            }
-        }
+}
+
+/// Whether a restore may overwrite a live file that is newer than the backup
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum OverwritePolicy {
+    Always,
+    /// Skip restoring the file
+    Never,
+    /// Ask for each such file
+    Prompt,
+}
+
+impl Default for OverwritePolicy {
+    fn default() -> Self {
+        OverwritePolicy::Always
     }
+}
+
+/// Which old versions of each file pruning keeps. The newest version is always kept.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum RetentionPolicy {
+    /// The newest `backup_count` versions
+    KeepCount,
+    /// Every version from the last `keep_all_days` days, then the newest version of each day for another `daily_days`
+    /// days, then the newest version of each week for another `weekly_weeks` weeks, or forever when `None`. Versions
+    /// are dated by their timestamp, i.e. when the live file was changed.
+    Tiered {
+        keep_all_days: u32,
+        daily_days: u32,
+        weekly_weeks: Option<u32>
+    },
+}
 
-    if let Err(err_msg) = err {
-        return Err(SWarning(settings, err_msg));
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        RetentionPolicy::KeepCount
     }
+}
 
-    Ok(settings)
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct BackupFilePattern {
+    pub source_dir: PathBuf,
+    pub filename_pattern: String,
+    /// The name of the folder in `backup_dest_path` that matching files are backed up to. Defaults to the name of
+    /// `source_dir`.
+    #[serde(default)]
+    pub dest_subdir: Option<String>,
+    /// The destination folder that matching files are backed up to instead of `backup_dest_path`, e.g. to keep worlds
+    /// and characters on different drives. Only `backup_dest_path` is mirrored to `secondary_dest_path`.
+    #[serde(default)]
+    pub dest_override: Option<PathBuf>,
+    /// Matches are folders rather than files, and each is backed up as a single tar archive named like the folder
+    #[serde(default)]
+    pub directory_mode: bool,
+    /// Whether matching files are watched and backed up. The backups of a disabled pattern are still listed and can be
+    /// restored.
+    #[serde(default = "default_enabled")]
+    pub enabled: bool
 }
 
-fn read_settings() -> Result<Settings, SettingsError> {
-    let settings_path = get_settings_file_path()?;
+fn default_enabled() -> bool {
+    true
+}
 
-    let settings_str = match fs::read_to_string(settings_path) {
-        Err(err) if err.kind() == NotFound =>
-            return Err(SNotFound(None)),
-        Err(err) =>
-            return Err(SError(format!("Failed to read settings file: {}", err))),
-        Ok(str) =>
-            str
-    };
+impl BackupFilePattern {
+    pub fn to_path(&self) -> PathBuf {
+        self.source_dir.join(self.filename_pattern.clone())
+    }
 
-    let settings: Settings = match serde_json::from_str(&settings_str) {
-        Err(err) => return Err(SError(format!("Error reading settings file: {}", err))),
-        Ok(settings) => settings
-    };
+    /// The name of the folder in the backup destination that matching files are backed up to
+    pub fn dest_subdir_name(&self) -> &str {
+        match &self.dest_subdir {
+            Some(dest_subdir) => dest_subdir,
+            None => self.source_dir.file_name_str()
+        }
+    }
 
-    debug!("Read settings: {:?}", settings);
-    Ok(settings)
+    /// The destination folder that matching files are backed up to: `dest_override`, or else `backup_dest_path`
+    pub fn dest_path<'a>(&'a self, backup_dest_path: &'a Path) -> &'a Path {
+        self.dest_override.as_deref().unwrap_or(backup_dest_path)
+    }
 }
 
-pub fn write_settings(settings: Settings) -> Result<Settings, SettingsError> {
-    let settings_path = get_settings_file_path()?;
 
-    let settings_dir_path = settings_path.parent().unwrap();
-    if let Err(err) = std::fs::create_dir_all(settings_dir_path) {
-        if err.kind() != ErrorKind::AlreadyExists {
-            let err_msg = format!("Error creating settings directory {}: {}",
-                settings_dir_path.str(), err);
-            error!("{}", err_msg);
-            return Err(SWarning(settings, err_msg));
+impl Settings {
+    /// Settings that back up `backup_patterns` to `backup_dest_path`, with the defaults for everything else
+    pub fn new(backup_patterns: Vec<BackupFilePattern>, backup_dest_path: PathBuf) -> Settings {
+        Settings {
+            settings_version: SETTINGS_VERSION.to_string(),
+            backup_patterns,
+            backup_dest_path,
+            backup_count: 5,
+            backup_delay_sec: 10,
+            temp_file_location: TempFileLocation::default(),
+            quiet_period_ms: 0,
+            backup_empty_files: false,
+            max_backup_file_size_mb: None,
+            preserve_permissions: false,
+            min_backup_interval_sec: 0,
+            version_scheme: VersionScheme::default(),
+            snapshot_before_restore: false,
+            base_dir: None,
+            watch_process_name: None,
+            dedup_identical: false,
+            encrypt: false,
+            max_total_size_mb: None,
+            copy_locked_files: false,
+            error_summary_len: DEFAULT_ERROR_SUMMARY_LEN,
+            overwrite_newer: OverwritePolicy::default(),
+            watch_backup_dest: false,
+            date_format: default_date_format(),
+            secondary_dest_path: None,
+            write_sidecars: false,
+            case_insensitive: default_case_insensitive(),
+            confirm_restore_targets: false,
+            retention_policy: RetentionPolicy::default(),
+            post_backup_command: None,
+            poll_interval_sec: None,
+            confirm_restore: default_confirm_restore(),
+            version_separator: DEFAULT_VERSION_SEPARATOR,
+            backup_layout: BackupLayout::default(),
+            backup_hotkey: None,
+            delete_to_trash: false,
+            trash_retention_days: DEFAULT_TRASH_RETENTION_DAYS,
+            include_hidden: default_include_hidden()
         }
     }
 
-    let settings_str = match serde_json::to_string(&settings) {
-        Err(err) => return Err(SError(format!("Error writing settings: {}", err))),
-        Ok(settings_str) => settings_str
-    };
-
-    match fs::write(settings_path, settings_str.as_bytes()) {
-        Err(err) =>
-            Err(SWarning( settings, format!("Failed to write settings file: {}", err))),
-        Ok(()) =>
-            Ok(settings)
+    /// Whether any backup pattern is enabled, i.e. whether anything is watched and backed up at all
+    pub fn has_enabled_patterns(&self) -> bool {
+        self.backup_patterns.iter().any(|backup_pattern| backup_pattern.enabled)
     }
-}
 
-pub fn get_settings_file_path() -> Result<PathBuf, SettingsError> {
-    let project_dirs = ProjectDirs::from("org", "valbak", "Valbak");
-    match project_dirs {
-        None =>
-            Err(SError("Failed to find settings folder".to_string())),
-        Some(project_dirs) => {
-            let settings_dir_path = project_dirs.config_dir();
-            let settings_file_path = settings_dir_path.join(Path::new("settings.json"));
-            info!("Using settings file: {}", settings_file_path.str());
-            Ok(settings_file_path)
+    /// `backup_dest_path` followed by the other destination folders that backup patterns override it with, each once
+    pub fn dest_paths(&self) -> Vec<&Path> {
+        let mut dest_paths = vec![self.backup_dest_path.as_path()];
+        for backup_pattern in &self.backup_patterns {
+            let dest_path = backup_pattern.dest_path(&self.backup_dest_path);
+            if !dest_paths.contains(&dest_path) {
+                dest_paths.push(dest_path);
+            }
         }
+        dest_paths
     }
 }
 
-pub fn get_default_settings() -> Result<Settings, SettingsError> {
-    let mut backup_dest_dir = PathBuf::new();
+/// Whether `date_format` is a `chrono` format that can be used in the file lists. `|` is not allowed, since it separates
+/// the columns of the lists.
+pub fn is_valid_date_format(date_format: &str) -> bool {
+    !date_format.contains('|') && StrftimeItems::new(date_format).all(|item| !matches!(item, Item::Error))
+}
 
-    let backup_patterns = match dirs::data_local_dir() {
-        None => {
-            vec![]
-        }
-        Some(local_dir) => {
-            let mut local_low_dir = local_dir.str().to_string();
-            local_low_dir.push_str("Low");
-
-            let valheim_src_dir = Path::new(&local_low_dir)
-                .join("IronGate")
-                .join("Valheim");
-            let worlds_src_dir = valheim_src_dir.join("worlds");
-            let characters_src_dir = valheim_src_dir.join("characters");
-
-            backup_dest_dir = match dirs::document_dir() {
-                None => PathBuf::from(""),
-                Some(doc_dir) => doc_dir
-            };
-            backup_dest_dir.push("Valbak");
-
-            vec![
-                BackupFilePattern {
-                    source_dir: worlds_src_dir.clone(),
-                    // dest_dir: worlds_dest_dir.str().to_string(),
-                    filename_pattern: "*.db".to_string()
-                },
-                BackupFilePattern {
-                    source_dir: worlds_src_dir.clone(),
-                    filename_pattern: "*.fwl".to_string()
-                },
-                BackupFilePattern {
-                    source_dir: characters_src_dir.clone(),
-                    filename_pattern: "*.fch".to_string()
-                }
-            ]
-        }
-    };
-
-    Ok(Settings {
-        settings_version: SETTINGS_VERSION.to_string(),
-        backup_patterns,
-        backup_dest_path: backup_dest_dir,
-        backup_count: 5,
-        backup_delay_sec: 10
-    })
+/// Rebuilds `path` from its components, giving typed and chosen paths the same form: platform separators throughout,
+/// and no repeated or trailing separators or `.` components
+pub fn normalize_path(path: &Path) -> PathBuf {
+    path.components().collect()
 }
\ No newline at end of file
@@ -0,0 +1,450 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io::ErrorKind;
+use std::io::ErrorKind::NotFound;
+use std::path::{Path, PathBuf};
+
+use directories::ProjectDirs;
+use fltk::dialog::{alert_default, choice_default};
+use glob::Pattern;
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use valbak::dest_marker::find_unrelated_dest_files;
+use valbak::file::FileError::{FError, FFatal, FWarning};
+use valbak::file::PathExt;
+use valbak::settings::{BackupFilePattern, DEFAULT_DATE_FORMAT, is_valid_date_format, normalize_path, Settings, VERSION_SEPARATORS};
+
+use crate::hotkey::parse_hotkey;
+use crate::settings_file::SettingsError::{SError, SNotFound, SWarning};
+
+#[cfg(all(unix, not(target_os = "macos")))]
+const VALHEIM_STEAM_APP_ID: &str = "892970";
+/// Written next to the settings file
+const WINDOW_LAYOUT_FILE_NAME: &str = "window_layout.json";
+
+/// How the user last arranged the main window. Kept out of the settings, so that rearranging the window never counts
+/// as a change of the settings.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct WindowLayout {
+    /// The height of the live files pane, above the backed up files pane
+    #[serde(default)]
+    pub live_files_pane_height: Option<i32>,
+}
+
+#[derive(Error, Debug)]
+pub enum SettingsError {
+    SNotFound(Option<Settings>),
+    SWarning(Settings, String),
+    SError(String)
+}
+
+impl SettingsError {
+    pub fn to_string(&self) -> String {
+        match self {
+            SNotFound(_settings) =>
+                "Settings Not Found".to_string(),
+            SWarning(_settings, err) =>
+                err.clone(),
+            SError(err) =>
+                err.clone()
+        }
+    }
+}
+
+impl Display for SettingsError {
+    fn fmt(&self, _f: &mut Formatter<'_>) -> std::fmt::Result {
+        todo!()
+    }
+}
+
+pub fn get_settings() -> Result<Settings, SettingsError> {
+    let settings = match read_settings() {
+        Err(SettingsError::SNotFound(None)) => {
+            let settings = write_settings(get_default_settings()?)?;
+            Err(SNotFound(Some(settings)))
+        },
+        Err(err) =>
+            Err(err),
+        Ok(settings) =>
+            Ok(settings)
+    }?;
+
+    validate_settings(settings)
+}
+
+pub fn validate_settings(mut settings: Settings) -> Result<Settings, SettingsError> {
+    settings.backup_dest_path = normalize_path(&settings.backup_dest_path);
+    settings.secondary_dest_path = settings.secondary_dest_path.as_deref().map(normalize_path);
+    for backup_pattern in settings.backup_patterns.iter_mut() {
+        backup_pattern.source_dir = normalize_path(&backup_pattern.source_dir);
+        backup_pattern.dest_override = backup_pattern.dest_override.as_deref().map(normalize_path);
+    }
+
+    if !is_valid_date_format(&settings.date_format) {
+        warn!("Invalid date format {}, using {}", settings.date_format, DEFAULT_DATE_FORMAT);
+        settings.date_format = DEFAULT_DATE_FORMAT.to_string();
+    }
+
+    if let Err(err_msg) = check_settings(&settings) {
+        return Err(SWarning(settings, err_msg));
+    }
+    if settings.backup_dest_path != PathBuf::new() && !settings.backup_dest_path.is_dir() {
+        match choice_default(
+            format!("Destination folder does not exist: {}\nCreate it?",
+                settings.backup_dest_path.str()).as_str(),
+            "Cancel", "Yes", ""
+        ) {
+            0 => {  // Cancel
+                return Err(SWarning(settings, "".to_string()));
+            }
+            _ => {  // Yes
+                if let Err(err) = std::fs::create_dir_all(settings.backup_dest_path.clone()) {
+                    error!("{}", err);
+                    alert_default(format!("Error: {}", err).as_str());
+                }
+            }
+        }
+    }
+
+    if settings.backup_dest_path != PathBuf::new() && settings.backup_dest_path.is_dir() {
+        let unrelated_files = match find_unrelated_dest_files(&settings) {
+            Ok(unrelated_files) => unrelated_files,
+            Err(FWarning(errs)) | Err(FError(errs)) | Err(FFatal(errs)) =>
+                return Err(SWarning(settings, errs.join("\n")))
+        };
+        if !unrelated_files.is_empty() {
+            match choice_default(
+                format!("Destination folder {} contains {} files that are not backups, such as {}\n\
+                    Valbak may remove files in it. Use it anyway?",
+                    settings.backup_dest_path.str(), unrelated_files.len(), unrelated_files[0].str()).as_str(),
+                "Cancel", "Use Anyway", ""
+            ) {
+                0 => {  // Cancel
+                    return Err(SWarning(settings, "".to_string()));
+                }
+                _ => {  // Use Anyway
+                    warn!("Using destination folder {} although it contains files that are not backups",
+                        settings.backup_dest_path.str());
+                }
+            }
+        }
+    }
+
+    Ok(settings)
+}
+
+/// Checks `settings` for mistakes that keep them from working, without asking anything, and returns the first one found
+pub fn check_settings(settings: &Settings) -> Result<(), String> {
+    for backup_pattern in settings.backup_patterns.iter() {
+        if !backup_pattern.source_dir.is_dir() {
+            return Err(format!("Backup folder does not exist: {}", backup_pattern.source_dir.str()));
+        }
+        if backup_pattern.filename_pattern.contains(|c| c == '/' || c == '\\') {
+            // Backups are filed by the name of the source folder, so matches must be directly inside it
+            return Err(format!("File pattern must be a file name without a folder: {}", backup_pattern.filename_pattern));
+        }
+        if let Some(dest_subdir) = &backup_pattern.dest_subdir {
+            if dest_subdir.is_empty() || dest_subdir == ".." || dest_subdir.contains(|c| c == '/' || c == '\\') {
+                return Err(format!("Backup folder must be a folder name: {}", dest_subdir));
+            }
+        }
+        if let Err(_) = Pattern::new(&backup_pattern.filename_pattern) {
+            return Err(format!("Invalid file pattern: {}", backup_pattern.filename_pattern));
+        }
+        if let Some(dest_override) = &backup_pattern.dest_override {
+            // Unlike the destination folder, it is not offered to be created, since it is not shown in the window
+            if !dest_override.is_dir() {
+                return Err(format!("Destination folder of {} does not exist: {}",
+                    backup_pattern.to_path().str(), dest_override.str()));
+            }
+            // Each destination would then list and prune the other's files as its own
+            let overlaps = |other_dest_path: &Path| dest_override != other_dest_path
+                && (dest_override.starts_with(other_dest_path) || other_dest_path.starts_with(dest_override));
+            if overlaps(&settings.backup_dest_path)
+                || settings.secondary_dest_path.as_deref().map_or(false, overlaps) {
+                return Err(format!("Destination folder of {} must be outside the other destination folders: {}",
+                    backup_pattern.to_path().str(), dest_override.str()));
+            }
+        }
+    }
+
+    if !VERSION_SEPARATORS.contains(&settings.version_separator) {
+        let version_separators: Vec<String> = VERSION_SEPARATORS.iter().map(|c| c.to_string()).collect();
+        return Err(format!("Version separator must be one of {}: {}",
+            version_separators.join(" "), settings.version_separator));
+    }
+    if let Some(backup_hotkey) = &settings.backup_hotkey {
+        parse_hotkey(backup_hotkey)?;
+    }
+    if !settings.backup_patterns.is_empty() && settings.backup_dest_path == PathBuf::new() {
+        return Err("Missing destination folder".to_string());
+    }
+    if let Some(secondary_dest_path) = &settings.secondary_dest_path {
+        // Pruning either would then prune the other's files as well
+        if secondary_dest_path.starts_with(&settings.backup_dest_path)
+            || settings.backup_dest_path.starts_with(secondary_dest_path) {
+            return Err(format!("Mirror folder must be outside the destination folder: {}", secondary_dest_path.str()));
+        }
+    }
+    Ok(())
+}
+
+fn read_settings() -> Result<Settings, SettingsError> {
+    let settings_path = get_settings_file_path()?;
+
+    let settings_str = match fs::read_to_string(settings_path) {
+        Err(err) if err.kind() == NotFound =>
+            return Err(SNotFound(None)),
+        Err(err) =>
+            return Err(SError(format!("Failed to read settings file: {}", err))),
+        Ok(str) =>
+            str
+    };
+
+    let settings = match parse_settings(&settings_str) {
+        Err(err_msg) => return Err(SError(format!("Error reading settings file: {}", err_msg))),
+        Ok(settings) => settings
+    };
+
+    debug!("Read settings: {:?}", settings);
+    Ok(settings)
+}
+
+/// Reads settings from the JSON of a settings file, returning the parse error otherwise
+pub fn parse_settings(settings_str: &str) -> Result<Settings, String> {
+    let mut settings: Settings = match serde_json::from_str(settings_str) {
+        Err(err) => return Err(err.to_string()),
+        Ok(settings) => settings
+    };
+    resolve_source_dirs(&mut settings);
+    Ok(settings)
+}
+
+/// Reads the settings file as it is, without parsing it, for editing. Returns an empty string when there is none.
+pub fn read_settings_file_str() -> Result<String, SettingsError> {
+    let settings_path = get_settings_file_path()?;
+    match fs::read_to_string(settings_path) {
+        Err(err) if err.kind() == NotFound =>
+            Ok(String::new()),
+        Err(err) =>
+            Err(SError(format!("Failed to read settings file: {}", err))),
+        Ok(settings_str) =>
+            Ok(settings_str)
+    }
+}
+
+pub fn write_settings(settings: Settings) -> Result<Settings, SettingsError> {
+    let settings_path = get_settings_file_path()?;
+
+    let settings_dir_path = settings_path.parent().unwrap();
+    if let Err(err) = std::fs::create_dir_all(settings_dir_path) {
+        if err.kind() != ErrorKind::AlreadyExists {
+            let err_msg = format!("Error creating settings directory {}: {}",
+                settings_dir_path.str(), err);
+            error!("{}", err_msg);
+            return Err(SWarning(settings, err_msg));
+        }
+    }
+
+    let settings_str = match serde_json::to_string(&relativize_source_dirs(&settings)) {
+        Err(err) => return Err(SError(format!("Error writing settings: {}", err))),
+        Ok(settings_str) => settings_str
+    };
+
+    match fs::write(settings_path, settings_str.as_bytes()) {
+        Err(err) =>
+            Err(SWarning( settings, format!("Failed to write settings file: {}", err))),
+        Ok(()) =>
+            Ok(settings)
+    }
+}
+
+/// Joins each relative source folder in `settings` onto its base folder, if it has one
+fn resolve_source_dirs(settings: &mut Settings) {
+    if let Some(base_dir) = &settings.base_dir {
+        for backup_pattern in settings.backup_patterns.iter_mut() {
+            if backup_pattern.source_dir.is_relative() {
+                backup_pattern.source_dir = base_dir.join(&backup_pattern.source_dir);
+            }
+        }
+    }
+}
+
+/// Returns a copy of `settings` with each source folder inside its base folder made relative again, so that the
+/// settings file stays portable
+fn relativize_source_dirs(settings: &Settings) -> Settings {
+    let mut settings = settings.clone();
+    if let Some(base_dir) = &settings.base_dir {
+        for backup_pattern in settings.backup_patterns.iter_mut() {
+            if let Ok(relative_source_dir) = backup_pattern.source_dir.strip_prefix(base_dir) {
+                backup_pattern.source_dir = relative_source_dir.to_path_buf();
+            }
+        }
+    }
+    settings
+}
+
+pub fn get_settings_file_path() -> Result<PathBuf, SettingsError> {
+    let project_dirs = ProjectDirs::from("org", "valbak", "Valbak");
+    match project_dirs {
+        None =>
+            Err(SError("Failed to find settings folder".to_string())),
+        Some(project_dirs) => {
+            let settings_dir_path = project_dirs.config_dir();
+            let settings_file_path = settings_dir_path.join(Path::new("settings.json"));
+            info!("Using settings file: {}", settings_file_path.str());
+            Ok(settings_file_path)
+        }
+    }
+}
+
+/// Reads the last layout of the main window, or returns the default layout when there is none or it cannot be read
+pub fn read_window_layout() -> WindowLayout {
+    let window_layout_path = match get_settings_file_path() {
+        Ok(settings_file_path) => settings_file_path.with_file_name(WINDOW_LAYOUT_FILE_NAME),
+        Err(_) => return WindowLayout::default()
+    };
+    let window_layout_str = match fs::read_to_string(&window_layout_path) {
+        Err(err) if err.kind() == NotFound =>
+            return WindowLayout::default(),
+        Err(err) => {
+            warn!("Error reading {}: {}", window_layout_path.str(), err);
+            return WindowLayout::default();
+        }
+        Ok(window_layout_str) =>
+            window_layout_str
+    };
+    serde_json::from_str(&window_layout_str).unwrap_or_else(|err| {
+        warn!("Invalid window layout {}: {}", window_layout_path.str(), err);
+        WindowLayout::default()
+    })
+}
+
+/// Saves `window_layout` for the next start. Failing to is only logged, since the default layout works as well.
+pub fn write_window_layout(window_layout: &WindowLayout) {
+    let window_layout_path = match get_settings_file_path() {
+        Ok(settings_file_path) => settings_file_path.with_file_name(WINDOW_LAYOUT_FILE_NAME),
+        Err(_) => return
+    };
+    let window_layout_str = match serde_json::to_string(window_layout) {
+        Ok(window_layout_str) => window_layout_str,
+        Err(err) => {
+            warn!("Error saving window layout: {}", err);
+            return;
+        }
+    };
+    if let Err(err) = fs::write(&window_layout_path, window_layout_str) {
+        warn!("Error writing {}: {}", window_layout_path.str(), err);
+    }
+}
+
+pub fn get_default_settings() -> Result<Settings, SettingsError> {
+    let mut backup_dest_dir = PathBuf::new();
+
+    let backup_patterns = match find_valheim_save_dir() {
+        None => {
+            vec![]
+        }
+        Some(valheim_src_dir) => {
+            info!("Found Valheim save folder: {}", valheim_src_dir.str());
+            let worlds_src_dir = valheim_src_dir.join("worlds");
+            let characters_src_dir = valheim_src_dir.join("characters");
+
+            backup_dest_dir = match dirs::document_dir() {
+                None => PathBuf::from(""),
+                Some(doc_dir) => doc_dir
+            };
+            backup_dest_dir.push("Valbak");
+
+            vec![
+                BackupFilePattern {
+                    source_dir: worlds_src_dir.clone(),
+                    // dest_dir: worlds_dest_dir.str().to_string(),
+                    filename_pattern: "*.db".to_string(),
+                    dest_subdir: None,
+                    dest_override: None,
+                    directory_mode: false,
+                    enabled: true
+                },
+                BackupFilePattern {
+                    source_dir: worlds_src_dir.clone(),
+                    filename_pattern: "*.fwl".to_string(),
+                    dest_subdir: None,
+                    dest_override: None,
+                    directory_mode: false,
+                    enabled: true
+                },
+                BackupFilePattern {
+                    source_dir: characters_src_dir.clone(),
+                    filename_pattern: "*.fch".to_string(),
+                    dest_subdir: None,
+                    dest_override: None,
+                    directory_mode: false,
+                    enabled: true
+                }
+            ].into_iter()
+                .filter(|backup_pattern| backup_pattern.source_dir.is_dir())
+                .collect()
+        }
+    };
+
+    Ok(Settings::new(backup_patterns, backup_dest_dir))
+}
+
+/// Returns the first existing folder where Valheim keeps its saved games on this platform
+fn find_valheim_save_dir() -> Option<PathBuf> {
+    get_valheim_save_dir_candidates().into_iter()
+        .find(|candidate| candidate.is_dir())
+}
+
+#[cfg(windows)]
+fn get_valheim_save_dir_candidates() -> Vec<PathBuf> {
+    // Unity uses %USERPROFILE%\AppData\LocalLow, which has no known folder of its own
+    match dirs::data_local_dir() {
+        None => vec![],
+        Some(local_dir) => {
+            let mut local_low_dir = local_dir.str().to_string();
+            local_low_dir.push_str("Low");
+            vec![Path::new(&local_low_dir).join("IronGate").join("Valheim")]
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn get_valheim_save_dir_candidates() -> Vec<PathBuf> {
+    match dirs::data_dir() {
+        None => vec![],
+        Some(data_dir) => vec![data_dir.join("IronGate").join("Valheim")]
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn get_valheim_save_dir_candidates() -> Vec<PathBuf> {
+    let mut candidates = vec![];
+    // The native Linux build
+    if let Some(config_dir) = dirs::config_dir() {
+        candidates.push(config_dir.join("unity3d").join("IronGate").join("Valheim"));
+    }
+    // The Windows build running under Proton, in either of Steam's usual locations
+    if let Some(home_dir) = dirs::home_dir() {
+        let steam_dirs = vec![
+            home_dir.join(".steam").join("steam"),
+            home_dir.join(".local").join("share").join("Steam")
+        ];
+        for steam_dir in steam_dirs {
+            candidates.push(steam_dir
+                .join("steamapps").join("compatdata").join(VALHEIM_STEAM_APP_ID)
+                .join("pfx").join("drive_c").join("users").join("steamuser")
+                .join("AppData").join("LocalLow").join("IronGate").join("Valheim"));
+        }
+    }
+    candidates
+}
\ No newline at end of file
@@ -0,0 +1,87 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use fltk::app;
+use fltk::enums::{Align, Color, Font};
+use fltk::frame::Frame;
+use fltk::group::Pack;
+use fltk::prelude::{DisplayExt, GroupExt, WidgetExt, WindowExt};
+use fltk::text::{TextBuffer, TextEditor};
+use fltk::window::Window;
+
+use crate::UiMessage;
+use crate::UiMessage::{SettingsFileCancel, SettingsFileSave};
+use crate::win_common::{make_bottom_button_group, make_section_header};
+
+/// Edits the raw JSON of the settings file, which is only saved once it reads as valid settings
+pub struct SettingsFileWindow {
+    pub wind: Window,
+    buffer: TextBuffer,
+    /// Says why the text was not saved, if it was not
+    error_frame: Frame,
+}
+
+impl SettingsFileWindow {
+
+    pub fn new(sender: app::Sender<UiMessage>) -> SettingsFileWindow {
+        static WINDOW_SIZE: (i32, i32) = (800, 640);
+        static CONTENT_SIZE: (i32, i32) = (WINDOW_SIZE.0 - 20, WINDOW_SIZE.1 - 20);
+
+        let mut wind = Window::default().with_label("Settings File");
+        wind.make_modal(true);
+        wind.set_size(WINDOW_SIZE.0, WINDOW_SIZE.1);
+
+        let mut content = Pack::default()
+            .with_pos(10, 10);
+        content.set_spacing(5);
+
+        make_section_header("settings.json", false);
+        let buffer = TextBuffer::default();
+        let mut editor = TextEditor::default();
+        editor.set_buffer(buffer.clone());
+        editor.set_text_font(Font::Courier);
+        editor.set_size(0, CONTENT_SIZE.1 - 120);
+        // Takes up the height gained when the window is resized
+        content.resizable(&editor);
+
+        let mut error_frame = Frame::default();
+        error_frame.set_size(0, 40);
+        error_frame.set_align(Align::Left | Align::Inside | Align::Wrap);
+        error_frame.set_label_color(Color::DarkRed);
+
+        content.set_size(CONTENT_SIZE.0, error_frame.y() + error_frame.height());
+
+        let mut buttons = make_bottom_button_group(&mut content, CONTENT_SIZE.0, &[], &["Save", "Cancel"]);
+        buttons[0].emit(sender.clone(), SettingsFileSave);
+        buttons[1].emit(sender.clone(), SettingsFileCancel);
+
+        wind.end();
+        wind.resizable(&content);
+        wind.size_range(WINDOW_SIZE.0, WINDOW_SIZE.1, 0, 0);
+
+        // Closing the window discards the changes, like Cancel
+        wind.set_callback(move |_wind| sender.send(SettingsFileCancel));
+
+        SettingsFileWindow {
+            wind,
+            buffer,
+            error_frame
+        }
+    }
+
+    pub fn set_text(&mut self, settings_str: &str) {
+        self.buffer.set_text(settings_str);
+    }
+
+    pub fn get_text(&self) -> String {
+        self.buffer.text()
+    }
+
+    pub fn set_error(&mut self, err_msg: &str) {
+        self.error_frame.set_label(err_msg);
+        self.error_frame.redraw();
+    }
+}
\ No newline at end of file
@@ -5,27 +5,32 @@
  */
 
 use std::fmt::{Display, Formatter};
-use std::path::PathBuf;
+use std::path::Path;
 
 use fltk::app;
-use fltk::browser::MultiBrowser;
+use fltk::browser::{Browser, MultiBrowser};
 use fltk::button::Button;
 use fltk::dialog::{FileChooser, FileChooserType};
 use fltk::enums::Event;
-use fltk::frame::Frame;
-use fltk::group::{Group, Pack, PackType};
+use fltk::group::{Pack, PackType};
 use fltk::input::Input;
+use fltk::misc::Spinner;
 use fltk::prelude::{BrowserExt, GroupExt, InputExt, WidgetExt, WindowExt};
 use fltk::window::Window;
 use thiserror::Error;
+use valbak::file::{count_live_files_for_pattern, get_free_space_mb, PathExt, probe_dest_writable};
+use valbak::mirror::count_pending_mirrors;
+use valbak::settings::{BackupFilePattern, is_valid_date_format, normalize_path, Settings, SETTINGS_VERSION};
 
 use UiMessage::SettingsBackupDestChoose;
 
-use crate::file::PathExt;
-use crate::settings::{BackupFilePattern, Settings, SETTINGS_VERSION};
 use crate::UiMessage;
-use crate::UiMessage::{SettingsOk, SettingsQuit};
-use crate::win_common::{column_headers, make_list_browser, make_section_header};
+use crate::UiMessage::{SettingsApply, SettingsOk, SettingsQuit, SettingsRefreshHealth, SettingsResetDefaults, SettingsTest, SettingsTestPattern, SettingsToggleEnabled};
+use crate::win_common::{column_headers, make_bottom_button_group, make_list_browser, make_section_header};
+
+/// Shown in the first column of the backup patterns list for enabled and disabled patterns
+const ENABLED_MARK: &str = "[x]";
+const DISABLED_MARK: &str = "[ ]";
 
 #[derive(Error, Debug)]
 pub enum SettingsWinError {
@@ -39,18 +44,26 @@ impl Display for SettingsWinError {
     }
 }
 
+/// Pruning to no backups would delete every backup
+const MIN_BACKUP_COUNT: u8 = 1;
+
 pub struct SettingsWindow {
     pub wind: Window,
     backup_files_browser: MultiBrowser,
+    health_browser: Browser,
     backup_dest_input: Input,
-    backup_count_input: Input,
-    backup_delay_input: Input
+    secondary_dest_input: Input,
+    backup_count_input: Spinner,
+    backup_delay_input: Spinner,
+    date_format_input: Input,
+    // The settings last set to the window, which supplies any values the window does not edit
+    settings: Option<Settings>
 }
 
 impl SettingsWindow {
 
     pub fn new(sender: app::Sender<UiMessage>) -> SettingsWindow {
-        static WINDOW_SIZE: (i32, i32) = (800, 440);
+        static WINDOW_SIZE: (i32, i32) = (800, 640);
         static CONTENT_SIZE: (i32, i32) = (WINDOW_SIZE.0 - 20, WINDOW_SIZE.1 - 20);
 
         let mut wind = Window::default().with_label("Settings");
@@ -60,14 +73,16 @@ impl SettingsWindow {
         let mut content = Pack::default()
             .with_pos(10, 10);
         content.set_spacing(5);
-        static BACKUP_LIST_COLUMN_WIDTHS: [i32; 2] = [CONTENT_SIZE.0 - 100, 100];
+        static BACKUP_LIST_COLUMN_WIDTHS: [i32; 4] = [40, CONTENT_SIZE.0 - 290, 100, 150];
 
         // Live Files
         make_section_header("Files to Backup", true);
         column_headers(
-            &vec!["Folder", "File Pattern"],
+            &vec!["On", "Folder", "File Pattern", "Backup Folder"],
             &BACKUP_LIST_COLUMN_WIDTHS);
         let backup_files_browser = make_list_browser(&BACKUP_LIST_COLUMN_WIDTHS, 100);
+        // Takes up the height gained when the window is resized
+        content.resizable(&backup_files_browser);
 
         let mut backup_files_buttons = Pack::default()
             .with_type(PackType::Horizontal);
@@ -85,11 +100,30 @@ impl SettingsWindow {
             .with_label("Delete");
         let text_size = delete_backup_button.measure_label();
         delete_backup_button.set_size(text_size.0 + 15, text_size.1 + 10);
+        let mut toggle_backup_button = Button::default()
+            .with_label("Enable/Disable");
+        let text_size = toggle_backup_button.measure_label();
+        toggle_backup_button.set_size(text_size.0 + 15, text_size.1 + 10);
+        toggle_backup_button.emit(sender.clone(), SettingsToggleEnabled);
+        let mut test_backup_button = Button::default()
+            .with_label("Test Backup");
+        let text_size = test_backup_button.measure_label();
+        test_backup_button.set_size(text_size.0 + 15, text_size.1 + 10);
+        test_backup_button.emit(sender.clone(), SettingsTest);
+        let mut test_pattern_button = Button::default()
+            .with_label("Test Pattern...");
+        let text_size = test_pattern_button.measure_label();
+        test_pattern_button.set_size(text_size.0 + 15, text_size.1 + 10);
+        test_pattern_button.emit(sender.clone(), SettingsTestPattern);
 
         backup_files_buttons.set_size(0, text_size.1 + 10);
 
         backup_files_buttons.end();
 
+        make_section_header("Folder Health", true);
+        let mut health_browser = Browser::default();
+        health_browser.set_size(0, 80);
+
         make_section_header("Backup to folder", true);
 
         let mut backup_dest_fields = Pack::default()
@@ -98,6 +132,7 @@ impl SettingsWindow {
 
         let mut backup_dest_input = Input::default();
         backup_dest_input.set_size(0, backup_dest_input.text_size() + 12);
+        backup_dest_input.emit(sender.clone(), SettingsRefreshHealth);
 
         let mut backup_dest_select_button = Button::default()
             .with_label("...");
@@ -110,46 +145,48 @@ impl SettingsWindow {
             CONTENT_SIZE.0 - backup_dest_select_button.width() - 5, backup_dest_input.height());
 
         backup_dest_fields.set_size(0, backup_dest_select_button.height());
+        // Takes up the width gained when the window is resized, keeping the button at the right
+        backup_dest_fields.resizable(&backup_dest_input);
         backup_dest_fields.end();
 
-        make_section_header("Maximum number of backups per file", true);
-
-        let mut backup_count_input = Input::default();
-        backup_count_input.set_size(0, backup_count_input.text_size() + 12);
+        make_section_header("Also copy backups to folder, e.g. on a NAS (optional)", true);
 
-        make_section_header("File backup delay in seconds", true);
+        let mut secondary_dest_input = Input::default();
+        secondary_dest_input.set_size(0, secondary_dest_input.text_size() + 12);
+        secondary_dest_input.emit(sender.clone(), SettingsRefreshHealth);
 
-        let mut backup_delay_input = Input::default();
-        backup_delay_input.set_size(0, backup_delay_input.text_size() + 12);
+        make_section_header(&format!("Maximum number of backups per file ({}-{})", MIN_BACKUP_COUNT, u8::MAX), true);
 
-        content.set_size(CONTENT_SIZE.0, backup_delay_input.y() + backup_delay_input.height());
+        let mut backup_count_input = Spinner::default();
+        backup_count_input.set_size(0, backup_count_input.text_size() + 12);
+        backup_count_input.set_range(MIN_BACKUP_COUNT as f64, u8::MAX as f64);
+        backup_count_input.set_step(1.0);
 
-        let mut bottom_button_group_pad = Frame::default();
-        bottom_button_group_pad.set_size(0, 10);
+        make_section_header(&format!("File backup delay in seconds (0-{})", u8::MAX), true);
 
-        let mut bottom_button_group = Group::default();
+        let mut backup_delay_input = Spinner::default();
+        backup_delay_input.set_size(0, backup_delay_input.text_size() + 12);
+        backup_delay_input.set_range(0.0, u8::MAX as f64);
+        backup_delay_input.set_step(1.0);
 
-        content.end();
+        make_section_header("Date format of file lists, e.g. %Y-%m-%d %H:%M", true);
 
-        let mut quit_button = Button::default()
-            .with_label("Quit");
-        let text_size = quit_button.measure_label();
-        quit_button.set_size(text_size.0 + 50, text_size.1 + 14);
-        quit_button.set_pos(CONTENT_SIZE.0 - quit_button.width() - 5, 0);
-        quit_button.emit(sender.clone(), SettingsQuit);
+        let mut date_format_input = Input::default();
+        date_format_input.set_size(0, date_format_input.text_size() + 12);
 
-        let mut ok_button = Button::default()
-            .with_label("Ok");
-        let text_size = ok_button.measure_label();
-        ok_button.set_size(text_size.0 + 50, text_size.1 + 14);
-        ok_button.set_pos(quit_button.x() - ok_button.width() - 5, 0);
-        ok_button.emit(sender.clone(), SettingsOk);
+        content.set_size(CONTENT_SIZE.0, date_format_input.y() + date_format_input.height());
 
-        bottom_button_group.set_size(CONTENT_SIZE.0, ok_button.height());
-        bottom_button_group.add(&ok_button);
-        bottom_button_group.add(&quit_button);
+        // Reset apart from the others, at the left
+        let mut buttons = make_bottom_button_group(
+            &mut content, CONTENT_SIZE.0, &["Reset to Defaults..."], &["Apply", "Ok", "Quit"]);
+        buttons[0].emit(sender.clone(), SettingsResetDefaults);
+        buttons[1].emit(sender.clone(), SettingsApply);
+        buttons[2].emit(sender.clone(), SettingsOk);
+        buttons[3].emit(sender.clone(), SettingsQuit);
 
         wind.end();
+        wind.resizable(&content);
+        wind.size_range(WINDOW_SIZE.0, WINDOW_SIZE.1, 0, 0);
 
         wind.set_callback(|_wind| {
             if app::event() == Event::Close {
@@ -160,72 +197,187 @@ impl SettingsWindow {
         SettingsWindow {
             wind,
             backup_files_browser,
+            health_browser,
             backup_dest_input,
+            secondary_dest_input,
             backup_count_input,
-            backup_delay_input
+            backup_delay_input,
+            date_format_input,
+            settings: None
         }
     }
 
     pub fn get_settings_from_win(&self) -> Result<Settings, SettingsWinError> {
-        let mut backup_patterns = vec![];
-        for i in 1..=self.backup_files_browser.size() {
-            let text = self.backup_files_browser.text(i);
-            let backup_files_line = text.unwrap();
-            let backup_files_parts: Vec<&str> = backup_files_line.split("|").collect();
-            let backup_source_path = backup_files_parts[0];
-            let backup_files_glob = backup_files_parts[1];
-            backup_patterns.push(BackupFilePattern {
-                source_dir: PathBuf::from(backup_source_path),
-                filename_pattern: backup_files_glob.to_string()
-            });
-        }
+        assert!(self.settings.is_some(), "illegal state");
+        let backup_patterns = (1..=self.backup_files_browser.size())
+            .map(|i| self.get_pattern_from_win(i))
+            .collect();
 
         let backup_dest_path = self.backup_dest_input.value();
 
-        let backup_count = self.backup_count_input.value();
-        let backup_count = match backup_count.parse::<u8>() {
-            Ok(count) =>
-                count,
-            Err(_err) =>
-                return Err(
-                    SettingsWinError::SwWarning(format!("Invalid backup count: {}", backup_count))
-                )
+        let secondary_dest_path = self.secondary_dest_input.value();
+        let secondary_dest_path = if secondary_dest_path.trim().is_empty() {
+            None
+        } else {
+            Some(normalize_path(Path::new(secondary_dest_path.trim())))
         };
 
-        let backup_delay_sec = self.backup_delay_input.value();
-        let backup_delay_sec = match backup_delay_sec.parse::<u8>() {
-            Ok(delay_sec) =>
-                delay_sec,
-            Err(_err) =>
-                return Err(
-                    SettingsWinError::SwWarning(format!("Invalid delay seconds: {}", backup_delay_sec))
-                )
-        };
+        // The spinners keep typed values within their range as well
+        let backup_count = self.backup_count_input.value().round()
+            .max(MIN_BACKUP_COUNT as f64)
+            .min(u8::MAX as f64) as u8;
+        let backup_delay_sec = self.backup_delay_input.value().round()
+            .max(0.0)
+            .min(u8::MAX as f64) as u8;
+
+        let date_format = self.date_format_input.value();
+        if !is_valid_date_format(&date_format) {
+            return Err(SettingsWinError::SwWarning(format!("Invalid date format: {}", date_format)));
+        }
 
         Ok(Settings {
                 settings_version: SETTINGS_VERSION.to_string(),
                 backup_patterns,
-                backup_dest_path: PathBuf::from(backup_dest_path),
+                backup_dest_path: normalize_path(Path::new(backup_dest_path.trim())),
                 backup_count,
-                backup_delay_sec
+                backup_delay_sec,
+                date_format,
+                secondary_dest_path,
+                ..self.settings.clone().unwrap()
         })
     }
 
+    /// The backup pattern in line `i` of the backup patterns list
+    fn get_pattern_from_win(&self, i: i32) -> BackupFilePattern {
+        let text = self.backup_files_browser.text(i);
+        let backup_files_line = text.unwrap();
+        let backup_files_parts: Vec<&str> = backup_files_line.split("|").collect();
+        let enabled = backup_files_parts[0] == ENABLED_MARK;
+        let backup_source_path = backup_files_parts[1];
+        let backup_files_glob = backup_files_parts[2];
+        // Empty when backing up to the default folder
+        let backup_dest_subdir = backup_files_parts[3].trim();
+        let source_dir = normalize_path(Path::new(backup_source_path.trim()));
+        // Not shown in the window, so kept from the pattern being edited
+        let edited_pattern = self.settings.as_ref().unwrap().backup_patterns.iter()
+            .find(|pattern| pattern.source_dir == source_dir && pattern.filename_pattern == backup_files_glob);
+        let directory_mode = edited_pattern.map_or(false, |pattern| pattern.directory_mode);
+        let dest_override = edited_pattern.and_then(|pattern| pattern.dest_override.clone());
+        BackupFilePattern {
+            source_dir,
+            filename_pattern: backup_files_glob.to_string(),
+            dest_subdir: if backup_dest_subdir.is_empty() {
+                None
+            } else {
+                Some(backup_dest_subdir.to_string())
+            },
+            dest_override,
+            directory_mode,
+            enabled
+        }
+    }
+
+    /// The first selected backup pattern, if any is selected
+    pub fn get_selected_pattern(&self) -> Option<BackupFilePattern> {
+        (1..=self.backup_files_browser.size())
+            .find(|i| self.backup_files_browser.selected(*i))
+            .map(|i| self.get_pattern_from_win(i))
+    }
+
     pub fn set_settings_to_win(&mut self, settings: Settings) {
         self.clear_win();
-        for backup_pattern in settings.backup_patterns {
-            let backup_file_line = format!("{}|{}",
+        self.settings = Some(settings.clone());
+        for backup_pattern in &settings.backup_patterns {
+            let backup_file_line = format!("{}|{}|{}|{}",
+                if backup_pattern.enabled { ENABLED_MARK } else { DISABLED_MARK },
                 backup_pattern.source_dir.str(),
-                backup_pattern.filename_pattern
+                backup_pattern.filename_pattern,
+                backup_pattern.dest_subdir.as_deref().unwrap_or("")
             );
             self.backup_files_browser.add(&backup_file_line);
         }
 
         self.backup_dest_input.set_value(settings.backup_dest_path.str());
 
-        self.backup_count_input.set_value(&settings.backup_count.to_string());
+        self.secondary_dest_input.set_value(settings.secondary_dest_path.as_ref().map_or("", |path| path.str()));
 
-        self.backup_delay_input.set_value(&settings.backup_delay_sec.to_string());
+        self.backup_count_input.set_value(settings.backup_count as f64);
+
+        self.backup_delay_input.set_value(settings.backup_delay_sec as f64);
+
+        self.date_format_input.set_value(&settings.date_format);
+
+        self.update_health(&settings);
+    }
+
+    /// Enables the selected backup patterns if any of them is disabled, or else disables them all. Takes effect once the
+    /// settings are applied.
+    pub fn toggle_selected_enabled(&mut self) {
+        let selected_lines: Vec<i32> = (1..=self.backup_files_browser.size())
+            .filter(|i| self.backup_files_browser.selected(*i))
+            .collect();
+        let enable = selected_lines.iter()
+            .any(|i| !self.backup_files_browser.text(*i).unwrap().starts_with(ENABLED_MARK));
+        for i in selected_lines {
+            let backup_files_line = self.backup_files_browser.text(i).unwrap();
+            let (_enabled_mark, pattern_columns) = backup_files_line.split_once('|').unwrap();
+            self.backup_files_browser.set_text(i, &format!("{}|{}",
+                if enable { ENABLED_MARK } else { DISABLED_MARK },
+                pattern_columns
+            ));
+        }
+        self.refresh_health();
+    }
+
+    /// Updates the folder health for the settings as they are in the window, e.g. once patterns were enabled or
+    /// disabled or a backup folder was edited
+    pub fn refresh_health(&mut self) {
+        // Only an invalid date format keeps the settings from being read, which the health does not depend on
+        if let Ok(settings) = self.get_settings_from_win() {
+            self.update_health(&settings);
+        }
+    }
+
+    /// Shows whether each source folder exists and how many files it matches, and the state of the backup folders
+    fn update_health(&mut self, settings: &Settings) {
+        self.health_browser.clear();
+        for backup_pattern in &settings.backup_patterns {
+            let health = if !backup_pattern.enabled {
+                "disabled".to_string()
+            } else if !backup_pattern.source_dir.is_dir() {
+                "missing".to_string()
+            } else {
+                match count_live_files_for_pattern(settings, backup_pattern) {
+                    Ok(count) => format!("exists, {} files match", count),
+                    Err(_) => "exists, invalid file pattern".to_string()
+                }
+            };
+            self.health_browser.add(&format!("{} \u{2014} {}", backup_pattern.to_path().str(), health));
+        }
+        for dest_path in settings.dest_paths() {
+            let dest_health = if !dest_path.is_dir() {
+                "missing".to_string()
+            } else {
+                let writable = match probe_dest_writable(dest_path) {
+                    Ok(()) => "writable",
+                    Err(_) => "not writable"
+                };
+                match get_free_space_mb(dest_path) {
+                    Ok(free_space_mb) => format!("exists, {}, {}mb free", writable, free_space_mb),
+                    Err(_) => format!("exists, {}, free space unknown", writable)
+                }
+            };
+            self.health_browser.add(&format!("Backup folder {} \u{2014} {}", dest_path.str(), dest_health));
+        }
+        if let Some(secondary_dest_path) = &settings.secondary_dest_path {
+            let secondary_dest_health = if secondary_dest_path.is_dir() {
+                "available"
+            } else {
+                "unavailable"
+            };
+            self.health_browser.add(&format!("Mirror folder {} \u{2014} {}, {} backups waiting to be copied",
+                secondary_dest_path.str(), secondary_dest_health, count_pending_mirrors()));
+        }
     }
 
     fn clear_win(&mut self) {
@@ -248,12 +400,9 @@ impl SettingsWindow {
         while file_chooser.shown() {
             app::wait();
         }
-        if let Some(mut dir) = file_chooser.directory() {
+        if let Some(dir) = file_chooser.directory() {
             // FLTK File Chooser apparently always uses forward slashes
-            if std::path::MAIN_SEPARATOR != '/' {
-                dir = dir.replace("/", &std::path::MAIN_SEPARATOR.to_string());
-            }
-            settings.backup_dest_path = PathBuf::from(dir);
+            settings.backup_dest_path = normalize_path(Path::new(&dir));
             self.set_settings_to_win(settings);
         }
     }
@@ -0,0 +1,108 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::file::{FileError, PathExt};
+use crate::file::FileError::FError;
+
+/// Appended to the name of a backed up file to name its sidecar, e.g. `world.db.3.meta.json`
+pub const SIDECAR_SUFFIX: &str = ".meta.json";
+/// Appended to the name of a backed up file to name the file holding its note, e.g. `world.db.3.note`
+pub const NOTE_SUFFIX: &str = ".note";
+
+/// What was backed up to a backed up file, recorded next to it when it was backed up, so that it can be restored to
+/// where it came from even after the backup patterns change
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct BackupMetadata {
+    /// The absolute path of the live file
+    pub original_path: PathBuf,
+    /// The size of the live file, or of all files in a live folder
+    pub size: u64,
+    /// The timestamp of the live file, or of the newest file in a live folder
+    pub modified: SystemTime,
+}
+
+pub fn get_sidecar_path(backed_up_file_path: &Path) -> PathBuf {
+    let mut sidecar_file_name = backed_up_file_path.file_name_str().to_string();
+    sidecar_file_name.push_str(SIDECAR_SUFFIX);
+    backed_up_file_path.with_file_name(sidecar_file_name)
+}
+
+/// Whether `path` is the sidecar or the note of a backed up file
+pub fn is_sidecar_path(path: &Path) -> bool {
+    path.file_name_str().ends_with(SIDECAR_SUFFIX) || path.file_name_str().ends_with(NOTE_SUFFIX)
+}
+
+pub fn get_note_path(backed_up_file_path: &Path) -> PathBuf {
+    let mut note_file_name = backed_up_file_path.file_name_str().to_string();
+    note_file_name.push_str(NOTE_SUFFIX);
+    backed_up_file_path.with_file_name(note_file_name)
+}
+
+/// Records `metadata` in the sidecar of `backed_up_file_path`
+pub fn write_sidecar(backed_up_file_path: &Path, metadata: &BackupMetadata) -> Result<(), FileError> {
+    let sidecar_path = get_sidecar_path(backed_up_file_path);
+    let metadata_str = match serde_json::to_string_pretty(metadata) {
+        Ok(metadata_str) => metadata_str,
+        Err(err) => return Err(FError(vec![format!("Error creating sidecar for {}: {}", backed_up_file_path.str(), err)]))
+    };
+    if let Err(err) = std::fs::write(&sidecar_path, metadata_str) {
+        return Err(FError(vec![format!("Error writing {}: {}", sidecar_path.str(), err)]));
+    }
+    Ok(())
+}
+
+/// Reads the sidecar of `backed_up_file_path`, or returns `None` when it has none, e.g. because it was backed up
+/// without sidecars
+pub fn read_sidecar(backed_up_file_path: &Path) -> Result<Option<BackupMetadata>, FileError> {
+    let sidecar_path = get_sidecar_path(backed_up_file_path);
+    if !sidecar_path.exists() {
+        return Ok(None);
+    }
+    let metadata_str = match std::fs::read_to_string(&sidecar_path) {
+        Ok(metadata_str) => metadata_str,
+        Err(err) => return Err(FError(vec![format!("Error reading {}: {}", sidecar_path.str(), err)]))
+    };
+    match serde_json::from_str(&metadata_str) {
+        Ok(metadata) => Ok(Some(metadata)),
+        Err(err) => Err(FError(vec![format!("Invalid sidecar {}: {}", sidecar_path.str(), err)]))
+    }
+}
+
+/// Attaches `note` to `backed_up_file_path`, e.g. "before boss fight", replacing any note it had. An empty note removes
+/// the note.
+pub fn write_note(backed_up_file_path: &Path, note: &str) -> Result<(), FileError> {
+    let note_path = get_note_path(backed_up_file_path);
+    let note = note.trim();
+    if note.is_empty() {
+        if note_path.exists() {
+            if let Err(err) = std::fs::remove_file(&note_path) {
+                return Err(FError(vec![format!("Error removing {}: {}", note_path.str(), err)]));
+            }
+        }
+        return Ok(());
+    }
+    if let Err(err) = std::fs::write(&note_path, note) {
+        return Err(FError(vec![format!("Error writing {}: {}", note_path.str(), err)]));
+    }
+    Ok(())
+}
+
+/// Reads the note attached to `backed_up_file_path`, or returns `None` when it has none
+pub fn read_note(backed_up_file_path: &Path) -> Result<Option<String>, FileError> {
+    let note_path = get_note_path(backed_up_file_path);
+    if !note_path.exists() {
+        return Ok(None);
+    }
+    match std::fs::read_to_string(&note_path) {
+        Ok(note) => Ok(Some(note.trim().to_string())),
+        Err(err) => Err(FError(vec![format!("Error reading {}: {}", note_path.str(), err)]))
+    }
+}
\ No newline at end of file
@@ -0,0 +1,123 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::path::PathBuf;
+
+use chrono::{DateTime, Local};
+use fltk::app;
+use fltk::browser::HoldBrowser;
+use fltk::group::Pack;
+use fltk::prelude::{BrowserExt, GroupExt, WidgetExt, WindowExt};
+use fltk::window::Window;
+use log::error;
+use valbak::file::{FileError, get_backed_up_version, get_backed_up_versions_of_live_file, get_file_metadata, PathExt};
+use valbak::file::FileError::{FError, FFatal, FWarning};
+use valbak::settings::Settings;
+use valbak::sidecar::read_note;
+
+use crate::main_win::{format_list_size, format_list_time};
+use crate::UiMessage;
+use crate::UiMessage::{VersionTimelineClose, VersionTimelineRestore};
+use crate::win_common::{column_headers, make_bottom_button_group, make_section_header};
+
+/// Lists every remaining version of one live file, newest first, to pick the one to restore without searching the
+/// list of all backed up files
+pub struct VersionTimelineWindow {
+    pub wind: Window,
+    versions_browser: HoldBrowser,
+    /// The backed up file of each line of `versions_browser`
+    version_paths: Vec<PathBuf>,
+}
+
+impl VersionTimelineWindow {
+
+    pub fn new(sender: app::Sender<UiMessage>) -> VersionTimelineWindow {
+        static WINDOW_SIZE: (i32, i32) = (640, 480);
+        static CONTENT_SIZE: (i32, i32) = (WINDOW_SIZE.0 - 20, WINDOW_SIZE.1 - 20);
+        static VERSION_LIST_COLUMN_WIDTHS: [i32; 4] = [150, 200, 100, CONTENT_SIZE.0 - 450];
+
+        let mut wind = Window::default().with_label("Versions");
+        wind.make_modal(true);
+        wind.set_size(WINDOW_SIZE.0, WINDOW_SIZE.1);
+
+        let mut content = Pack::default()
+            .with_pos(10, 10);
+        content.set_spacing(5);
+
+        make_section_header("Versions, Newest First", false);
+        column_headers(&vec!["Version", "Date", "Size", "Note"], &VERSION_LIST_COLUMN_WIDTHS);
+        let mut versions_browser = HoldBrowser::default();
+        versions_browser.set_size(0, CONTENT_SIZE.1 - 90);
+        versions_browser.set_column_char('|');
+        versions_browser.set_column_widths(&VERSION_LIST_COLUMN_WIDTHS);
+        // Takes up the height gained when the window is resized
+        content.resizable(&versions_browser);
+
+        content.set_size(CONTENT_SIZE.0, versions_browser.y() + versions_browser.height());
+
+        let mut buttons = make_bottom_button_group(&mut content, CONTENT_SIZE.0, &[], &["Restore", "Close"]);
+        buttons[0].emit(sender.clone(), VersionTimelineRestore);
+        buttons[1].emit(sender.clone(), VersionTimelineClose);
+
+        wind.end();
+        wind.resizable(&content);
+        wind.size_range(WINDOW_SIZE.0, WINDOW_SIZE.1, 0, 0);
+
+        wind.set_callback(move |_wind| sender.send(VersionTimelineClose));
+
+        VersionTimelineWindow {
+            wind,
+            versions_browser,
+            version_paths: Vec::new()
+        }
+    }
+
+    /// Lists the versions of `live_file_path` that remain in the backup destination of `settings`, selecting the newest
+    pub fn set_versions(&mut self, settings: &Settings, live_file_path: &PathBuf) -> Result<(), FileError> {
+        self.wind.set_label(&format!("Versions of {}", live_file_path.file_name_str()));
+        self.versions_browser.clear();
+        self.version_paths = get_backed_up_versions_of_live_file(settings, live_file_path)?;
+        let mut errs = Vec::new();
+        for version_path in &self.version_paths {
+            let version = get_backed_up_version(version_path).map_or("?".to_string(), |version| version.to_string());
+            let (date, size) = match get_file_metadata(version_path) {
+                Ok((metadata, modified)) => {
+                    let modified: DateTime<Local> = modified.into();
+                    (format_list_time(&modified, &settings.date_format), format_list_size(metadata.len()))
+                }
+                Err(FWarning(mut file_errs)) | Err(FError(mut file_errs)) | Err(FFatal(mut file_errs)) => {
+                    errs.append(&mut file_errs);
+                    ("?".to_string(), "?".to_string())
+                }
+            };
+            let note = match read_note(version_path) {
+                // On one line, and without the separator of the columns
+                Ok(note) => note.unwrap_or_default().replace(|c| c == '|' || c == '\n' || c == '\r', " "),
+                Err(FWarning(note_errs)) | Err(FError(note_errs)) | Err(FFatal(note_errs)) => {
+                    note_errs.iter().for_each(|err_msg| error!("{}", err_msg));
+                    "?".to_string()
+                }
+            };
+            self.versions_browser.add(&format!("{}|{}|{}|{}", version, date, size, note));
+        }
+        if !self.version_paths.is_empty() {
+            self.versions_browser.select(1);
+        }
+        if errs.is_empty() {
+            Ok(())
+        } else {
+            Err(FWarning(errs))
+        }
+    }
+
+    /// The backed up file of the selected version, if one is selected
+    pub fn get_selected_version_path(&self) -> Option<PathBuf> {
+        match self.versions_browser.value() {
+            0 => None,
+            line => self.version_paths.get(line as usize - 1).cloned()
+        }
+    }
+}
\ No newline at end of file
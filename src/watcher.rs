@@ -4,44 +4,208 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
-use std::path::PathBuf;
-use std::sync::mpsc;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, mpsc};
+use std::sync::mpsc::RecvTimeoutError;
 use std::thread::JoinHandle;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::Error;
 use fltk::app;
-use log::{debug, error, warn};
-use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
-use thiserror::Error;
+use log::{debug, error, info, warn};
+use notify::{DebouncedEvent, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use parking_lot::{const_mutex, Mutex};
+use sysinfo::{System, SystemExt};
+use valbak::dest_marker::ensure_dest_marker;
+use valbak::file::{backup_all_changed_files, backup_live_file, BackupChange, BackupVersion, delete_old_backups, get_backed_up_version, get_backup_change, get_live_file_fingerprint, is_disabled_live_file, is_internal_dest_path, is_restored_copy, live_file_has_backup, PathExt};
+use valbak::hook::run_post_backup_command;
+use valbak::mirror::{count_pending_mirrors, MIRROR_RETRY_INTERVAL, start_mirroring, take_mirror_errors};
+use valbak::settings::Settings;
 
 use FileError::{FError, FFatal, FWarning};
 
-use crate::{FileError, MainState, UiMessage};
-use crate::file::{backup_live_file, delete_old_backups, live_file_has_backup, PathExt};
-use crate::settings::Settings;
+use crate::{FileError, MainState, UiBackupListener, UiMessage};
 
 const STOP_WATCHER_ERROR: &str = "STOP";
+const WATCHER_IDLE_WAIT: Duration = Duration::from_secs(60);
+const BACKUP_IDLE_WAIT: Duration = Duration::from_secs(60);
+const MAX_REWATCH_ATTEMPTS: u32 = 6;
+const PROCESS_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Events for the same file within this long are handled as one change, e.g. a `Create` followed by a `Write`
+const EVENT_COALESCE_WINDOW: Duration = Duration::from_millis(250);
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+/// How long the UI waits for a heartbeat before considering the backup thread unresponsive. Long enough to cover the
+/// backup of a large file.
+pub const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(60);
+/// Changes to the backup destination that arrive within the watcher delay plus this long after Valbak's own changes to
+/// it are taken to be Valbak's own
+const OWN_DEST_CHANGE_MARGIN: Duration = Duration::from_secs(2);
+
+// When Valbak itself last added or removed backed up files
+static LAST_OWN_DEST_CHANGE: Mutex<Option<Instant>> = const_mutex(None);
 
 #[derive(Debug)]
 pub enum BackupMessage {
     Run { settings: Settings },
-    Stop {},
+    /// Stops watching, reporting `final_state` once stopped
+    Stop { final_state: WatcherState },
+    /// Re-establishes the watch on a source directory after the watcher reported a problem with it
+    Rewatch { source_dir: PathBuf },
+    /// A directory was created in a watched directory, possibly a source directory that did not exist yet
+    DirectoryCreated { dir: PathBuf },
+    /// The watched process exited, which is when its files are most likely to be completely saved
+    ProcessExited { process_name: String },
 }
 
-#[derive(Error, Debug)]
-pub enum BackupStatus {
-    Status(String),
+/// What the watcher is doing, as opposed to the transient progress of individual backups
+#[derive(Clone, Debug, PartialEq)]
+pub enum WatcherState {
+    Running,
+    /// Temporarily stopped, e.g. while the settings are being edited
+    Paused,
+    Stopped,
+    /// Running, but no backup patterns are enabled, so nothing is watched
+    Idle,
     Error(String),
 }
 
+impl Display for WatcherState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WatcherState::Running => write!(f, "Running"),
+            WatcherState::Paused => write!(f, "Paused"),
+            WatcherState::Stopped => write!(f, "Stopped"),
+            WatcherState::Idle => write!(f, "Idle: no file patterns are enabled"),
+            WatcherState::Error(err_msg) => write!(f, "Error: {}", err_msg)
+        }
+    }
+}
+
+/// What the backup thread has done, for the UI to keep track of
+#[derive(Clone, Debug)]
+pub enum BackupStatus {
+    /// The source directories currently being watched, and those that do not exist, e.g. because they were deleted,
+    /// whose closest existing ancestors are watched until they are created again
+    Watching { dirs: Vec<PathBuf>, missing_dirs: Vec<PathBuf> },
+    /// The watcher was set up with `backend`, sending events `delay` after the changes that caused them
+    WatcherStarted { backend: String, delay: Duration },
+    /// The watcher sent an event, as described by `event`
+    EventReceived { event: String },
+    /// The live file at `path` was backed up as `version`, which differs from the previous version by `change`
+    BackedUp { path: PathBuf, version: BackupVersion, change: Option<BackupChange> },
+    /// Old backed up files were deleted
+    Pruned { count: usize },
+    /// A change that was not backed up, e.g. of a file larger than the maximum backup file size
+    Warning { msg: String },
+    Error { msg: String },
+    /// A backed up file could not be copied to the mirror destination, as described by `msg`
+    MirrorFailed { msg: String },
+    /// The number of backed up files waiting for the mirror destination changed to `count`
+    MirrorPending { count: usize },
+    /// Sent every [`HEARTBEAT_INTERVAL`] while both the backup and watcher threads are responsive
+    Heartbeat,
+}
+
 impl Display for BackupStatus {
-    fn fmt(&self, _f: &mut Formatter<'_>) -> std::fmt::Result {
-        todo!()
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackupStatus::Watching { dirs, missing_dirs } if missing_dirs.is_empty() =>
+                write!(f, "Watching {} folders", dirs.len()),
+            BackupStatus::Watching { dirs, missing_dirs } =>
+                write!(f, "Watching {} folders, {} missing", dirs.len(), missing_dirs.len()),
+            BackupStatus::WatcherStarted { backend, delay } =>
+                write!(f, "Watching with {}, delay {}s", backend, delay.as_secs()),
+            BackupStatus::EventReceived { event } => write!(f, "Received {}", event),
+            BackupStatus::BackedUp { path, version, change: None } =>
+                write!(f, "Backed up {} as version {}", path.str(), version),
+            BackupStatus::BackedUp { path, version, change: Some(change) } =>
+                write!(f, "Backed up {} as version {}, {}", path.str(), version, change),
+            BackupStatus::Pruned { count } => write!(f, "Removed {} old backups", count),
+            BackupStatus::Warning { msg } => write!(f, "Warning: {}", msg),
+            BackupStatus::Error { msg } => write!(f, "Error: {}", msg),
+            BackupStatus::MirrorFailed { msg } => write!(f, "Mirroring failed: {}", msg),
+            BackupStatus::MirrorPending { count } => write!(f, "{} backups waiting to be mirrored", count),
+            BackupStatus::Heartbeat => write!(f, "Heartbeat")
+        }
+    }
+}
+
+/// Watches folders either with the native notifications of the platform, or by polling them where notifications are
+/// unreliable or missing, e.g. on network drives
+enum FolderWatcher {
+    Native(RecommendedWatcher),
+    Polling(PollWatcher),
+}
+
+impl FolderWatcher {
+    /// Polls when `settings` have a poll interval, which then also serves as the delay before events are sent, since
+    /// polling reports changes no sooner anyway
+    fn new(tx: mpsc::Sender<DebouncedEvent>, settings: &Settings) -> notify::Result<FolderWatcher> {
+        let delay = FolderWatcher::get_delay(settings);
+        if settings.poll_interval_sec.is_some() {
+            info!("Polling for changes every {} seconds", delay.as_secs());
+            Ok(FolderWatcher::Polling(Watcher::new(tx, delay)?))
+        } else {
+            Ok(FolderWatcher::Native(Watcher::new(tx, delay)?))
+        }
+    }
+
+    /// How long after a change its event is sent
+    fn get_delay(settings: &Settings) -> Duration {
+        match settings.poll_interval_sec {
+            Some(poll_interval_sec) => Duration::from_secs(poll_interval_sec.max(1) as u64),
+            None => Duration::from_secs(settings.backup_delay_sec as u64)
+        }
+    }
+
+    fn get_backend_name(&self) -> String {
+        match self {
+            FolderWatcher::Native(_) if cfg!(target_os = "linux") => "native notifications (inotify)".to_string(),
+            FolderWatcher::Native(_) if cfg!(target_os = "macos") => "native notifications (FSEvents)".to_string(),
+            FolderWatcher::Native(_) if cfg!(windows) =>
+                "native notifications (ReadDirectoryChangesW)".to_string(),
+            FolderWatcher::Native(_) => "native notifications".to_string(),
+            FolderWatcher::Polling(_) => "polling".to_string()
+        }
+    }
+
+    fn watch(&mut self, path: &Path, recursive_mode: RecursiveMode) -> notify::Result<()> {
+        match self {
+            FolderWatcher::Native(watcher) => watcher.watch(path, recursive_mode),
+            FolderWatcher::Polling(watcher) => watcher.watch(path, recursive_mode)
+        }
+    }
+
+    fn unwatch(&mut self, path: &Path) -> notify::Result<()> {
+        match self {
+            FolderWatcher::Native(watcher) => watcher.unwatch(path),
+            FolderWatcher::Polling(watcher) => watcher.unwatch(path)
+        }
     }
 }
 
+/// A live file change waiting for its quiet period to elapse before being backed up
+struct PendingChange {
+    last_change: Instant,
+    len: u64,
+    modified: SystemTime,
+}
+
+/// A source directory that does not exist yet, and its closest existing ancestor being watched for its creation
+struct AwaitedSourceDir {
+    source_dir: PathBuf,
+    watched_ancestor: PathBuf,
+}
+
+/// A source directory whose watch was lost and is waiting to be re-established
+struct PendingRewatch {
+    source_dir: PathBuf,
+    attempts: u32,
+    next_attempt: Instant,
+}
+
 pub fn start_backup_thread(state: &mut MainState) {
     debug!("Starting backup thread");
     assert!(state.settings.is_some(), "illegal state");
@@ -49,10 +213,14 @@ pub fn start_backup_thread(state: &mut MainState) {
 
     let (backup_message_tx, backup_message_rx) = mpsc::channel();
     state.backup_thread_tx = Some(backup_message_tx.clone());
+    let (backup_status_tx, backup_status_rx) = mpsc::channel();
+    state.backup_thread_rx = Some(backup_status_rx);
+    state.last_backup_heartbeat = Instant::now();
+    state.backup_thread_unresponsive = None;
     let ui_thread_tx_copy = state.ui_thread_tx.clone();
     state.backup_thread = Some(
         std::thread::spawn(
-            move || backup_thread_main(backup_message_rx, ui_thread_tx_copy))
+            move || backup_thread_main(backup_message_tx, backup_message_rx, backup_status_tx, ui_thread_tx_copy))
     );
 
     if let Err(err) = state.backup_thread_tx.as_ref().unwrap().send(
@@ -64,12 +232,12 @@ pub fn start_backup_thread(state: &mut MainState) {
     }
 }
 
-pub fn stop_backup_thread(state: &mut MainState) -> JoinHandle<()> {
+pub fn stop_backup_thread(state: &mut MainState, final_state: WatcherState) -> JoinHandle<()> {
     debug!("Signaling backup thread to stop");
     assert!(state.backup_thread.is_some(), "illegal state");
     assert!(state.backup_thread_tx.is_some(), "illegal state");
 
-    if let Err(err) = state.backup_thread_tx.as_ref().unwrap().send(BackupMessage::Stop {}) {
+    if let Err(err) = state.backup_thread_tx.as_ref().unwrap().send(BackupMessage::Stop { final_state }) {
         panic!("Error sending stop message to backup thread: {}", err);
     }
     let mut backup_thread = None;
@@ -77,19 +245,53 @@ pub fn stop_backup_thread(state: &mut MainState) -> JoinHandle<()> {
     backup_thread.unwrap()
 }
 
+/// Sends `status` to the UI thread, waking it up so that it is handled promptly
+fn send_status(status_tx: &mpsc::Sender<BackupStatus>, status: BackupStatus) {
+    if !matches!(status, BackupStatus::Heartbeat) {
+        debug!("Backup status: {}", status);
+    }
+    // Fails only once the UI thread has replaced this backup thread, when the status no longer matters
+    if status_tx.send(status).is_ok() {
+        app::awake();
+    }
+}
+
 fn backup_thread_main(
+    backup_thread_tx: mpsc::Sender<BackupMessage>,
     backup_thread_rx: mpsc::Receiver<BackupMessage>,
+    status_tx: mpsc::Sender<BackupStatus>,
     ui_thread_tx: app::Sender<UiMessage>
 ) {
     debug!("Backup thread started");
-    let mut current_watcher = None;
+    let mut current_watcher: Option<FolderWatcher> = None;
     let mut current_watcher_thread: Option<JoinHandle<()>> = None;
     let mut current_watcher_thread_tx: Option<mpsc::Sender<DebouncedEvent>> = None;
+    let mut pending_rewatches: Vec<PendingRewatch> = Vec::new();
+    let mut awaited_source_dirs: Vec<AwaitedSourceDir> = Vec::new();
+    let mut source_dirs: Vec<PathBuf> = Vec::new();
+    // Source directories that could not be watched at all
+    let mut unwatched_source_dirs: Vec<PathBuf> = Vec::new();
+    let mut current_settings: Option<Settings> = None;
+    // Dropping the sender stops the process watcher thread
+    let mut current_process_watcher: Option<(mpsc::Sender<()>, JoinHandle<()>)> = None;
+    let mut current_dest_watcher: Option<(FolderWatcher, mpsc::Sender<DebouncedEvent>, JoinHandle<()>)> = None;
+    // When the watcher thread last checked in, since it does not otherwise report being stuck
+    let mut current_watcher_check_in: Option<Arc<Mutex<Instant>>> = None;
+    let mut last_heartbeat = Instant::now();
+    let mut last_mirror_attempt = Instant::now();
+    let mut last_pending_mirror_count = 0;
 
     loop {
-        match backup_thread_rx.recv() {
+        // Wake up in time for the next attempt to re-establish a lost watch, or the next heartbeat
+        let wait = pending_rewatches.iter()
+            .map(|rewatch| rewatch.next_attempt.saturating_duration_since(Instant::now()))
+            .min()
+            .unwrap_or(BACKUP_IDLE_WAIT)
+            .min(HEARTBEAT_INTERVAL.saturating_sub(last_heartbeat.elapsed()));
+        match backup_thread_rx.recv_timeout(wait) {
+            Err(RecvTimeoutError::Timeout) => {}
             Err(err) => {
-                ui_thread_tx.send(UiMessage::SetStatus(format!("Error: {}", err)));
+                ui_thread_tx.send(UiMessage::SetWatcherState(WatcherState::Error(err.to_string())));
                 debug!("Backup thread stopped");
                 // Drops current_watcher if it exists, which will drop watcher_thread_tx, which will return an error
                 // from watcher_thread_rx.recv(), which will cause watcher_thread_main to return
@@ -97,23 +299,42 @@ fn backup_thread_main(
             }
             Ok(msg) => {
                 match msg {
-                    BackupMessage::Stop {} => {
+                    BackupMessage::Stop { final_state } => {
                         debug!("Stopping backup thread");
-                        if current_watcher_thread_tx.is_some() {
-                            if let Err(err) = current_watcher_thread_tx.unwrap().send(
+                        if let Some(watcher_thread_tx) = current_watcher_thread_tx {
+                            if let Err(err) = watcher_thread_tx.send(
                                 DebouncedEvent::Error(
                                     notify::Error::Generic(STOP_WATCHER_ERROR.to_string()),
                                     None)
                             ) {
-                                panic!("Error sending stop message to watcher thread: {}", err);
+                                error!("Error sending stop message to watcher thread: {}", err);
                             }
                         }
-                        if current_watcher_thread.is_some() {
-                            if let Err(err) = current_watcher_thread.unwrap().join() {
-                                panic!("Panic from watcher thread: {:?}", err);
+                        if let Some(watcher_thread) = current_watcher_thread {
+                            if let Err(err) = watcher_thread.join() {
+                                error!("Panic from watcher thread: {:?}", err);
                             }
                         }
-                        ui_thread_tx.send(UiMessage::SetStatus("Stopped".to_string()));
+                        if let Some((process_watcher_tx, process_watcher_thread)) = current_process_watcher {
+                            drop(process_watcher_tx);
+                            if let Err(err) = process_watcher_thread.join() {
+                                error!("Panic from process watcher thread: {:?}", err);
+                            }
+                        }
+                        if let Some((_watcher, dest_watcher_thread_tx, dest_watcher_thread)) = current_dest_watcher {
+                            if let Err(err) = dest_watcher_thread_tx.send(
+                                DebouncedEvent::Error(
+                                    notify::Error::Generic(STOP_WATCHER_ERROR.to_string()),
+                                    None)
+                            ) {
+                                error!("Error sending stop message to backup destination watcher thread: {}", err);
+                            }
+                            if let Err(err) = dest_watcher_thread.join() {
+                                error!("Panic from backup destination watcher thread: {:?}", err);
+                            }
+                        }
+                        send_status(&status_tx, BackupStatus::Watching { dirs: Vec::new(), missing_dirs: Vec::new() });
+                        ui_thread_tx.send(UiMessage::SetWatcherState(final_state));
                         debug!("Backup thread stopped");
                         return;
                     }
@@ -125,56 +346,535 @@ fn backup_thread_main(
 
                         current_watcher_thread_tx = Some(watcher_thread_tx.clone());
 
-                        let new_watcher = Watcher::new(
-                            watcher_thread_tx, Duration::from_secs(settings.backup_delay_sec as u64));
+                        let new_watcher = FolderWatcher::new(watcher_thread_tx, &settings);
 
                         if let Err(err) = new_watcher {
-                            ui_thread_tx.send(UiMessage::SetStatus(format!("Error: {}", err)));
+                            ui_thread_tx.send(UiMessage::SetWatcherState(WatcherState::Error(err.to_string())));
                             debug!("Backup thread stopped");
                             // Drops current_watcher if it exists, which will drop watcher_thread_tx, which will return
                             // an error from watcher_thread_rx.recv(), which will cause watcher_thread_main to return
                             return;
                         }
-                        let mut new_watcher: RecommendedWatcher = new_watcher.unwrap();
+                        let mut new_watcher = new_watcher.unwrap();
+                        send_status(&status_tx, BackupStatus::WatcherStarted {
+                            backend: new_watcher.get_backend_name(),
+                            delay: FolderWatcher::get_delay(&settings)
+                        });
 
-                        //TODO dedup directories - multiple patterns will use the same source dir
-                        for backup_pattern in &settings.backup_patterns {
-                            if let Err(err) = new_watcher.watch(&backup_pattern.source_dir, RecursiveMode::NonRecursive) {
-                                panic!("Error watching directory {}: {}", backup_pattern.source_dir.str(), err);
+                        if settings.backup_dest_path.is_dir() {
+                            if let Err(err) = ensure_dest_marker(&settings.backup_dest_path) {
+                                handle_error(&status_tx, &ui_thread_tx, &err.into());
                             }
-                            debug!("Watching {} for {}",
-                                backup_pattern.source_dir.str(),
-                                backup_pattern.filename_pattern.as_str()
-                            );
                         }
 
+                        source_dirs = get_source_dirs(&settings);
+                        watch_source_dirs(
+                            &mut new_watcher, &settings, &source_dirs, &mut awaited_source_dirs,
+                            &mut unwatched_source_dirs, &status_tx);
+                        send_watching_status(
+                            &status_tx, &source_dirs, &awaited_source_dirs, &pending_rewatches, &unwatched_source_dirs);
+
+                        if let Some(process_name) = settings.watch_process_name.clone() {
+                            let (process_watcher_tx, process_watcher_rx) = mpsc::channel();
+                            let backup_thread_tx_copy = backup_thread_tx.clone();
+                            let process_watcher_thread = std::thread::spawn(
+                                move || process_watcher_thread_main(
+                                    process_name, process_watcher_rx, backup_thread_tx_copy));
+                            current_process_watcher = Some((process_watcher_tx, process_watcher_thread));
+                        }
+                        if settings.watch_backup_dest {
+                            match start_dest_watcher(&settings, &ui_thread_tx) {
+                                Ok(dest_watcher) => current_dest_watcher = Some(dest_watcher),
+                                Err(err) => {
+                                    // Backing up works regardless, only the list may go stale
+                                    let err_msg = format!("Error watching backup folder {}: {}",
+                                        settings.backup_dest_path.str(), err);
+                                    error!("{}", err_msg);
+                                    send_status(&status_tx, BackupStatus::Error { msg: err_msg });
+                                }
+                            }
+                        }
+                        current_settings = Some(settings.clone());
+
                         let ui_thread_tx_copy = ui_thread_tx.clone();
+                        let backup_thread_tx_copy = backup_thread_tx.clone();
+                        let status_tx_copy = status_tx.clone();
+                        let watcher_check_in = Arc::new(Mutex::new(Instant::now()));
+                        current_watcher_check_in = Some(watcher_check_in.clone());
                         current_watcher_thread = Some(
                             std::thread::spawn(
-                                move || watcher_thread_main(settings, watcher_thread_rx, ui_thread_tx_copy))
+                                move || watcher_thread_main(
+                                    settings, watcher_thread_rx, backup_thread_tx_copy, status_tx_copy,
+                                    ui_thread_tx_copy, watcher_check_in))
                         );
 
                         current_watcher = Some(new_watcher);
-                        ui_thread_tx.send(UiMessage::SetStatus("Running".to_string()));
+                        let watcher_state = if source_dirs.is_empty() {
+                            WatcherState::Idle
+                        } else {
+                            WatcherState::Running
+                        };
+                        ui_thread_tx.send(UiMessage::SetWatcherState(watcher_state));
+                    }
+                    BackupMessage::DirectoryCreated { dir } => {
+                        if let (Some(watcher), Some(settings)) = (current_watcher.as_mut(), &current_settings) {
+                            on_directory_created(
+                                watcher, settings, dir, &source_dirs, &mut awaited_source_dirs,
+                                &mut unwatched_source_dirs, &status_tx);
+                            send_watching_status(
+                                &status_tx, &source_dirs, &awaited_source_dirs, &pending_rewatches,
+                                &unwatched_source_dirs);
+                        }
+                    }
+                    BackupMessage::ProcessExited { process_name } => {
+                        if let Some(settings) = &current_settings {
+                            info!("{} exited, backing up changed files", process_name);
+                            let (sweep, err) = backup_all_changed_files(
+                                settings.clone(), &UiBackupListener(ui_thread_tx.clone()));
+                            if !sweep.backed_up_files.is_empty() || sweep.pruned_count > 0 {
+                                note_own_dest_change();
+                            }
+                            for (live_file_path, backed_up_file_path) in sweep.backed_up_files {
+                                send_backed_up_status(settings, &status_tx, live_file_path, &backed_up_file_path);
+                            }
+                            if let Some(err) = err {
+                                handle_error(&status_tx, &ui_thread_tx, &err.into());
+                            }
+                            prune_old_backups(settings.clone(), &status_tx, &ui_thread_tx);
+                            ui_thread_tx.send(UiMessage::RefreshFilesLists);
+                        }
+                    }
+                    BackupMessage::Rewatch { source_dir } => {
+                        if !pending_rewatches.iter().any(|rewatch| rewatch.source_dir == source_dir) {
+                            warn!("Lost watch on {}, re-establishing it", source_dir.str());
+                            pending_rewatches.push(PendingRewatch {
+                                source_dir,
+                                attempts: 0,
+                                next_attempt: Instant::now()
+                            });
+                        }
                     }
                 }
             }
         }
+        if let (Some(watcher), Some(settings)) = (current_watcher.as_mut(), &current_settings) {
+            if !pending_rewatches.is_empty() {
+                retry_rewatches(
+                    watcher, settings, &mut pending_rewatches, &mut awaited_source_dirs, &mut unwatched_source_dirs,
+                    &status_tx, &ui_thread_tx);
+                send_watching_status(
+                    &status_tx, &source_dirs, &awaited_source_dirs, &pending_rewatches, &unwatched_source_dirs);
+            }
+        }
+        if let Some(settings) = &current_settings {
+            if count_pending_mirrors() > 0 && last_mirror_attempt.elapsed() >= MIRROR_RETRY_INTERVAL {
+                start_mirroring(settings);
+                last_mirror_attempt = Instant::now();
+            }
+        }
+        // Also of the mirroring after backups started from the UI thread
+        for msg in take_mirror_errors() {
+            send_status(&status_tx, BackupStatus::MirrorFailed { msg });
+        }
+        let pending_mirror_count = count_pending_mirrors();
+        if pending_mirror_count != last_pending_mirror_count {
+            send_status(&status_tx, BackupStatus::MirrorPending { count: pending_mirror_count });
+            last_pending_mirror_count = pending_mirror_count;
+        }
+        if last_heartbeat.elapsed() >= HEARTBEAT_INTERVAL {
+            // A watcher thread that missed a check in may be stuck, so let the UI time out
+            let watcher_silence = current_watcher_check_in.as_ref().map(|check_in| check_in.lock().elapsed());
+            match watcher_silence {
+                Some(silence) if silence >= HEARTBEAT_INTERVAL * 2 =>
+                    warn!("Watcher thread has not checked in for {}s", silence.as_secs()),
+                _ =>
+                    send_status(&status_tx, BackupStatus::Heartbeat)
+            }
+            last_heartbeat = Instant::now();
+        }
+    }
+}
+
+/// Polls for `process_name` and tells the backup thread each time it stops running, until `stop_rx` is disconnected
+fn process_watcher_thread_main(
+    process_name: String,
+    stop_rx: mpsc::Receiver<()>,
+    backup_thread_tx: mpsc::Sender<BackupMessage>
+) {
+    debug!("Process watcher thread started for {}", process_name);
+    let mut system = System::new();
+    let mut was_running = false;
+    loop {
+        system.refresh_processes();
+        let is_running = system.processes_by_exact_name(&process_name).next().is_some();
+        if was_running && !is_running {
+            let msg = BackupMessage::ProcessExited { process_name: process_name.clone() };
+            if let Err(err) = backup_thread_tx.send(msg) {
+                error!("Error sending process exited message to backup thread: {}", err);
+                return;
+            }
+        }
+        was_running = is_running;
+        match stop_rx.recv_timeout(PROCESS_POLL_INTERVAL) {
+            Err(RecvTimeoutError::Timeout) => {}
+            _ => {
+                debug!("Process watcher thread stopped");
+                return;
+            }
+        }
     }
 }
 
-fn watcher_thread_main(settings: Settings, watcher_thread_rx: mpsc::Receiver<DebouncedEvent>, ui_thread_tx: app::Sender<UiMessage>) {
+/// Watches the backup destination on behalf of [`dest_watcher_thread_main`], returning the watcher, which stops
+/// watching once dropped, and the sender and handle of the thread
+fn start_dest_watcher(
+    settings: &Settings, ui_thread_tx: &app::Sender<UiMessage>
+) -> notify::Result<(FolderWatcher, mpsc::Sender<DebouncedEvent>, JoinHandle<()>)> {
+    let (dest_watcher_thread_tx, dest_watcher_thread_rx) = mpsc::channel();
+    let mut dest_watcher = FolderWatcher::new(dest_watcher_thread_tx.clone(), settings)?;
+    dest_watcher.watch(&settings.backup_dest_path, RecursiveMode::Recursive)?;
+    info!("Watching backup folder {}", settings.backup_dest_path.str());
+
+    let settings_copy = settings.clone();
+    let ui_thread_tx_copy = ui_thread_tx.clone();
+    let dest_watcher_thread = std::thread::spawn(
+        move || dest_watcher_thread_main(settings_copy, dest_watcher_thread_rx, ui_thread_tx_copy));
+    Ok((dest_watcher, dest_watcher_thread_tx, dest_watcher_thread))
+}
+
+/// Refreshes the lists of files whenever versions are added to or removed from the backup destination by something
+/// other than Valbak. Changes are collected for [`OWN_DEST_CHANGE_MARGIN`] after the first one, giving Valbak time to
+/// note its own changes, and then ignored if Valbak changed the destination around the time they happened.
+fn dest_watcher_thread_main(
+    settings: Settings,
+    dest_watcher_thread_rx: mpsc::Receiver<DebouncedEvent>,
+    ui_thread_tx: app::Sender<UiMessage>
+) {
+    debug!("Backup destination watcher thread started");
+    // Events arrive after the watcher delay, so Valbak's own changes may have been noted that long before
+    let own_change_window = Duration::from_secs(settings.backup_delay_sec as u64) + OWN_DEST_CHANGE_MARGIN;
+    // When the first of the changes not reported yet was received
+    let mut first_change: Option<Instant> = None;
+    loop {
+        let wait = match first_change {
+            Some(received) => OWN_DEST_CHANGE_MARGIN.saturating_sub(received.elapsed()),
+            None => WATCHER_IDLE_WAIT
+        };
+        let changed_paths = match dest_watcher_thread_rx.recv_timeout(wait) {
+            Err(RecvTimeoutError::Timeout) => vec![],
+            Err(err) => {
+                error!("Backup destination watcher error: {}", err);
+                debug!("Backup destination watcher thread stopped");
+                return;
+            }
+            Ok(DebouncedEvent::Error(notify::Error::Generic(err_msg), _)) if err_msg == STOP_WATCHER_ERROR => {
+                debug!("Backup destination watcher thread stopped");
+                return;
+            }
+            Ok(DebouncedEvent::Error(err, path)) => {
+                error!("Backup destination watcher error for {:?}: {}", path, err);
+                vec![]
+            }
+            Ok(DebouncedEvent::Create(path)) | Ok(DebouncedEvent::Remove(path)) => vec![path],
+            Ok(DebouncedEvent::Rename(from_path, to_path)) => vec![from_path, to_path],
+            Ok(_) => vec![]
+        };
+        let versions_changed = changed_paths.iter()
+            .any(|path| get_backed_up_version(path).is_some() && !is_internal_dest_path(&settings, path));
+        if versions_changed && first_change.is_none() {
+            first_change = Some(Instant::now());
+        }
+
+        if let Some(received) = first_change {
+            if received.elapsed() >= OWN_DEST_CHANGE_MARGIN {
+                first_change = None;
+                let own_change = LAST_OWN_DEST_CHANGE.lock()
+                    .map_or(false, |own_change| own_change + own_change_window >= received);
+                if own_change {
+                    debug!("Ignoring Valbak's own changes to the backup folder");
+                } else {
+                    info!("Backed up files were changed outside of Valbak, refreshing");
+                    ui_thread_tx.send(UiMessage::RefreshFilesLists);
+                }
+            }
+        }
+    }
+}
+
+/// Notes that Valbak itself just added or removed backed up files, so that the backup destination watcher does not
+/// report them as external changes
+pub fn note_own_dest_change() {
+    *LAST_OWN_DEST_CHANGE.lock() = Some(Instant::now());
+}
+
+/// Attempts to re-establish each lost watch that is due, backing off exponentially between attempts and giving up after
+/// [`MAX_REWATCH_ATTEMPTS`]
+fn retry_rewatches(
+    watcher: &mut FolderWatcher,
+    settings: &Settings,
+    pending_rewatches: &mut Vec<PendingRewatch>,
+    awaited_source_dirs: &mut Vec<AwaitedSourceDir>,
+    unwatched_source_dirs: &mut Vec<PathBuf>,
+    status_tx: &mpsc::Sender<BackupStatus>,
+    ui_thread_tx: &app::Sender<UiMessage>
+) {
+    let now = Instant::now();
+    pending_rewatches.retain_mut(|rewatch| {
+        if rewatch.next_attempt > now {
+            return true;
+        }
+        // The old watch may or may not still be registered
+        let _ = watcher.unwatch(&rewatch.source_dir);
+        match watch_source_dir(watcher, settings, &rewatch.source_dir, awaited_source_dirs) {
+            Ok(()) => {
+                ui_thread_tx.send(UiMessage::SetWatcherState(WatcherState::Running));
+                false
+            }
+            Err(err) => {
+                rewatch.attempts += 1;
+                if rewatch.attempts >= MAX_REWATCH_ATTEMPTS {
+                    let err_msg = format!("Stopped watching directory {}: {}", rewatch.source_dir.str(), err);
+                    error!("{}", err_msg);
+                    send_status(status_tx, BackupStatus::Error { msg: err_msg });
+                    unwatched_source_dirs.push(rewatch.source_dir.clone());
+                    return false;
+                }
+                let backoff = Duration::from_secs(1 << rewatch.attempts);
+                warn!("Error watching directory {}, retrying in {}s: {}",
+                    rewatch.source_dir.str(), backoff.as_secs(), err);
+                ui_thread_tx.send(UiMessage::SetWatcherState(
+                    WatcherState::Error(format!("Waiting for {}", rewatch.source_dir.str()))));
+                rewatch.next_attempt = now + backoff;
+                true
+            }
+        }
+    });
+}
+
+/// Watches each of `source_dirs`. Those that cannot be watched at all are reported as errors, which the UI alerts, and
+/// added to `unwatched_source_dirs`, while the others are still watched.
+fn watch_source_dirs(
+    watcher: &mut FolderWatcher,
+    settings: &Settings,
+    source_dirs: &[PathBuf],
+    awaited_source_dirs: &mut Vec<AwaitedSourceDir>,
+    unwatched_source_dirs: &mut Vec<PathBuf>,
+    status_tx: &mpsc::Sender<BackupStatus>
+) {
+    for source_dir in source_dirs {
+        if let Err(err) = watch_source_dir(watcher, settings, source_dir, awaited_source_dirs) {
+            let err_msg = format!("Error watching directory {}: {}", source_dir.str(), err);
+            error!("{}", err_msg);
+            send_status(status_tx, BackupStatus::Error { msg: err_msg });
+            unwatched_source_dirs.push(source_dir.clone());
+        }
+    }
+}
+
+/// Watches `source_dir`, or when it does not exist yet, its closest existing ancestor so that its creation can be
+/// detected. Source directories with folder patterns are watched recursively, to see changes inside the folders.
+fn watch_source_dir(
+    watcher: &mut FolderWatcher,
+    settings: &Settings,
+    source_dir: &PathBuf,
+    awaited_source_dirs: &mut Vec<AwaitedSourceDir>
+) -> notify::Result<()> {
+    if source_dir.is_dir() {
+        let recursive_mode = if has_folder_patterns(settings, source_dir) {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        watcher.watch(source_dir, recursive_mode)?;
+        info!("Watching {}", source_dir.str());
+        return Ok(());
+    }
+    let watched_ancestor = match source_dir.ancestors().skip(1).find(|ancestor| ancestor.is_dir()) {
+        Some(ancestor) => ancestor.to_path_buf(),
+        None => return Err(notify::Error::PathNotFound)
+    };
+    watcher.watch(&watched_ancestor, RecursiveMode::NonRecursive)?;
+    info!("Watching {} until {} is created", watched_ancestor.str(), source_dir.str());
+    awaited_source_dirs.push(AwaitedSourceDir {
+        source_dir: source_dir.clone(),
+        watched_ancestor
+    });
+    Ok(())
+}
+
+/// Moves the watch of each awaited source directory that `dir` leads to from its ancestor down to `dir`, or all the way
+/// to the source directory itself when `dir` is the source directory
+fn on_directory_created(
+    watcher: &mut FolderWatcher,
+    settings: &Settings,
+    dir: PathBuf,
+    source_dirs: &[PathBuf],
+    awaited_source_dirs: &mut Vec<AwaitedSourceDir>,
+    unwatched_source_dirs: &mut Vec<PathBuf>,
+    status_tx: &mpsc::Sender<BackupStatus>
+) {
+    let (created_source_dirs, still_awaited_source_dirs): (Vec<AwaitedSourceDir>, Vec<AwaitedSourceDir>) =
+        awaited_source_dirs.drain(..).partition(|awaited| awaited.source_dir.starts_with(&dir));
+    *awaited_source_dirs = still_awaited_source_dirs;
+
+    for created in created_source_dirs {
+        if let Err(err) = watch_source_dir(watcher, settings, &created.source_dir, awaited_source_dirs) {
+            let err_msg = format!("Error watching directory {}: {}", created.source_dir.str(), err);
+            error!("{}", err_msg);
+            send_status(status_tx, BackupStatus::Error { msg: err_msg });
+            unwatched_source_dirs.push(created.source_dir.clone());
+        }
+        // The ancestor may still be needed by another source directory
+        let ancestor_in_use = source_dirs.contains(&created.watched_ancestor)
+            || awaited_source_dirs.iter().any(|awaited| awaited.watched_ancestor == created.watched_ancestor);
+        if !ancestor_in_use {
+            let _ = watcher.unwatch(&created.watched_ancestor);
+        }
+    }
+}
+
+/// Reports which source directories are being watched and which are missing
+fn send_watching_status(
+    status_tx: &mpsc::Sender<BackupStatus>,
+    source_dirs: &[PathBuf],
+    awaited_source_dirs: &[AwaitedSourceDir],
+    pending_rewatches: &[PendingRewatch],
+    unwatched_source_dirs: &[PathBuf]
+) {
+    let missing_dirs: Vec<PathBuf> = awaited_source_dirs.iter()
+        .map(|awaited| awaited.source_dir.clone())
+        .collect();
+    if !missing_dirs.is_empty() {
+        warn!("Waiting for missing source folders: {:?}", missing_dirs);
+    }
+    send_status(status_tx, BackupStatus::Watching {
+        dirs: get_watched_source_dirs(source_dirs, awaited_source_dirs, pending_rewatches, unwatched_source_dirs),
+        missing_dirs
+    });
+}
+
+/// The source directories that are actually being watched, as opposed to waiting to be created or re-watched
+fn get_watched_source_dirs(
+    source_dirs: &[PathBuf],
+    awaited_source_dirs: &[AwaitedSourceDir],
+    pending_rewatches: &[PendingRewatch],
+    unwatched_source_dirs: &[PathBuf]
+) -> Vec<PathBuf> {
+    source_dirs.iter()
+        .filter(|source_dir| {
+            !awaited_source_dirs.iter().any(|awaited| awaited.source_dir == **source_dir)
+                && !pending_rewatches.iter().any(|rewatch| rewatch.source_dir == **source_dir)
+                && !unwatched_source_dirs.contains(source_dir)
+        })
+        .cloned()
+        .collect()
+}
+
+/// Returns each distinct source directory of the enabled patterns in `settings`, since multiple patterns may use the same source dir
+fn get_source_dirs(settings: &Settings) -> Vec<PathBuf> {
+    let mut source_dirs: Vec<PathBuf> = Vec::new();
+    for backup_pattern in settings.backup_patterns.iter().filter(|backup_pattern| backup_pattern.enabled) {
+        if !source_dirs.contains(&backup_pattern.source_dir) {
+            source_dirs.push(backup_pattern.source_dir.clone());
+        }
+    }
+    source_dirs
+}
+
+fn has_folder_patterns(settings: &Settings, source_dir: &PathBuf) -> bool {
+    settings.backup_patterns.iter()
+        .any(|backup_pattern| backup_pattern.enabled && backup_pattern.directory_mode
+            && backup_pattern.source_dir == *source_dir)
+}
+
+/// Finds the live folder that a change to `path` is a change to, being either the folder itself or something inside of
+/// it, when `path` is in a source directory with folder patterns
+fn find_changed_folder(settings: &Settings, path: &PathBuf) -> Option<PathBuf> {
+    // The closest source directory wins, in case source directories are nested
+    let live_folder = path.ancestors()
+        .find(|ancestor| ancestor.parent().map_or(false, |parent| {
+            settings.backup_patterns.iter()
+                .any(|backup_pattern| backup_pattern.enabled && backup_pattern.source_dir == parent)
+        }))?;
+    let is_folder = live_folder != path.as_path() || path.is_dir();
+    if is_folder && has_folder_patterns(settings, &live_folder.parent().unwrap().to_path_buf()) {
+        Some(live_folder.to_path_buf())
+    } else {
+        None
+    }
+}
+
+/// Finds the watched source directory that `path` is, or is directly inside of
+fn find_source_dir(settings: &Settings, path: &PathBuf) -> Option<PathBuf> {
+    get_source_dirs(settings).into_iter()
+        .find(|source_dir| source_dir == path || path.parent() == Some(source_dir.as_path()))
+}
+
+fn watcher_thread_main(
+    settings: Settings,
+    watcher_thread_rx: mpsc::Receiver<DebouncedEvent>,
+    backup_thread_tx: mpsc::Sender<BackupMessage>,
+    status_tx: mpsc::Sender<BackupStatus>,
+    ui_thread_tx: app::Sender<UiMessage>,
+    check_in: Arc<Mutex<Instant>>
+) {
     debug!("Watcher thread started");
+    // Even without a quiet period, changes wait long enough for any further events for the same file to be coalesced
+    let quiet_period = Duration::from_millis(settings.quiet_period_ms as u64).max(EVENT_COALESCE_WINDOW);
+    let mut pending_changes = HashMap::new();
+    let mut last_backups = HashMap::new();
+    // Changes within the minimum backup interval of the previous backup, and when that interval ends
+    let mut deferred_backups = HashMap::new();
     loop {
-        match watcher_thread_rx.recv() {
+        *check_in.lock() = Instant::now();
+        // Wake up in time for the earliest pending change to reach the end of its quiet period, for the earliest
+        // deferred backup, or to check in
+        let wait = pending_changes.values()
+            .map(|change: &PendingChange| quiet_period.saturating_sub(change.last_change.elapsed()))
+            .chain(deferred_backups.values().map(|due: &Instant| due.saturating_duration_since(Instant::now())))
+            .min()
+            .unwrap_or(WATCHER_IDLE_WAIT)
+            .min(HEARTBEAT_INTERVAL);
+        match watcher_thread_rx.recv_timeout(wait) {
+            Err(RecvTimeoutError::Timeout) => {}
             Err(err) => {
-                panic!("Watcher error: {}", err);
+                // The watcher itself is gone, so there is nothing left to recover here
+                error!("Watcher error: {}", err);
+                debug!("Watcher thread stopped");
+                return;
             }
             Ok(file_event) => {
+                let is_stop_event = matches!(
+                    &file_event, DebouncedEvent::Error(notify::Error::Generic(err_msg), _) if err_msg == STOP_WATCHER_ERROR);
+                if !is_stop_event {
+                    send_status(&status_tx, BackupStatus::EventReceived { event: format!("{:?}", file_event) });
+                }
                 match file_event {
+                    DebouncedEvent::Create(file_path)
+                    | DebouncedEvent::Write(file_path) if find_changed_folder(&settings, &file_path).is_some() => {
+                        let live_folder = find_changed_folder(&settings, &file_path).unwrap();
+                        if is_restored_copy(&live_folder) {
+                            debug!("Ignoring change to restored copy {}", live_folder.str());
+                        } else {
+                            record_pending_change(&mut pending_changes, live_folder);
+                        }
+                    }
+                    DebouncedEvent::Create(file_path)
+                    | DebouncedEvent::Write(file_path) if file_path.is_dir() => {
+                        if let Err(err) = backup_thread_tx.send(BackupMessage::DirectoryCreated { dir: file_path }) {
+                            error!("Error sending directory created message to backup thread: {}", err);
+                        }
+                    }
                     DebouncedEvent::Create(file_path)
                     | DebouncedEvent::Write(file_path) => {
-                        on_file_change(file_path, &settings, ui_thread_tx.clone());
+                        if find_source_dir(&settings, &file_path).is_none() {
+                            // A change in the ancestor of a source directory that does not exist yet
+                            debug!("Ignoring change outside of source directories {}", file_path.str());
+                        } else if is_restored_copy(&file_path) {
+                            debug!("Ignoring change to restored copy {}", file_path.str());
+                        } else {
+                            record_pending_change(&mut pending_changes, file_path);
+                        }
                     }
                     DebouncedEvent::Error(err, path) => {
                         match err {
@@ -196,47 +896,279 @@ fn watcher_thread_main(settings: Settings, watcher_thread_rx: mpsc::Receiver<Deb
                                 error!("Watcher watch not found error for {:?}", path);
                             }
                         }
+                        if let Some(path) = path {
+                            request_rewatch(&settings, &path, &backup_thread_tx);
+                        }
+                    }
+                    DebouncedEvent::Remove(path)
+                    | DebouncedEvent::Rename(path, _) => {
+                        // Removing something from a live folder changes it
+                        if let Some(live_folder) = find_changed_folder(&settings, &path) {
+                            if live_folder != path && live_folder.is_dir() {
+                                record_pending_change(&mut pending_changes, live_folder);
+                            }
+                        }
+                        request_rewatch(&settings, &path, &backup_thread_tx);
                     }
                     _ => {}
                 }
             }
         }
+        back_up_quiet_changes(
+            &mut pending_changes, &mut last_backups, &mut deferred_backups, quiet_period, &settings, &status_tx,
+            &ui_thread_tx);
+        back_up_deferred_changes(&mut deferred_backups, &mut last_backups, &settings, &status_tx, &ui_thread_tx);
+    }
+}
+
+/// Asks the backup thread to re-establish the watch on the source directory related to `path`, if any. Removed files
+/// are ignored, since only the loss of a source directory itself affects the watch.
+fn request_rewatch(settings: &Settings, path: &PathBuf, backup_thread_tx: &mpsc::Sender<BackupMessage>) {
+    if let Some(source_dir) = find_source_dir(settings, path) {
+        if source_dir == *path || (!path.exists() && !source_dir.is_dir()) {
+            if let Err(err) = backup_thread_tx.send(BackupMessage::Rewatch { source_dir }) {
+                error!("Error sending rewatch message to backup thread: {}", err);
+            }
+        }
     }
 }
 
-fn on_file_change( backup_file_path: PathBuf, settings: &Settings, ui_thread_tx: app::Sender<UiMessage> ) {
-    let file_has_backup = match live_file_has_backup(settings.clone(), backup_file_path.clone()) {
+/// Records the current size and timestamp of `file_path`, restarting its quiet period
+fn record_pending_change(pending_changes: &mut HashMap<PathBuf, PendingChange>, file_path: PathBuf) {
+    match get_live_file_fingerprint(&file_path) {
+        Ok((len, modified)) => {
+            pending_changes.insert(file_path, PendingChange {
+                last_change: Instant::now(),
+                len,
+                modified
+            });
+        }
+        Err(_) => {
+            debug!("Ignoring change to unreadable file {}", file_path.str());
+            pending_changes.remove(&file_path);
+        }
+    }
+}
+
+/// Backs up each pending change whose quiet period has elapsed, provided the file's size and timestamp have not changed
+/// since they were recorded. Files that are still changing start a new quiet period.
+fn back_up_quiet_changes(
+    pending_changes: &mut HashMap<PathBuf, PendingChange>,
+    last_backups: &mut HashMap<PathBuf, Instant>,
+    deferred_backups: &mut HashMap<PathBuf, Instant>,
+    quiet_period: Duration,
+    settings: &Settings,
+    status_tx: &mpsc::Sender<BackupStatus>,
+    ui_thread_tx: &app::Sender<UiMessage>
+) {
+    let quiet_file_paths: Vec<PathBuf> = pending_changes.iter()
+        .filter(|(_file_path, change)| change.last_change.elapsed() >= quiet_period)
+        .map(|(file_path, _change)| file_path.clone())
+        .collect();
+    for file_path in quiet_file_paths {
+        let change = pending_changes.remove(&file_path).unwrap();
+        match get_live_file_fingerprint(&file_path) {
+            Ok((len, modified)) if len == change.len && modified == change.modified => {
+                on_file_change(file_path, settings, last_backups, deferred_backups, status_tx, ui_thread_tx.clone());
+            }
+            Ok(_) => {
+                debug!("{} is still changing", file_path.str());
+                record_pending_change(pending_changes, file_path);
+            }
+            Err(_) => {
+                debug!("{} disappeared before it could be backed up", file_path.str());
+            }
+        }
+    }
+}
+
+/// Backs up each deferred change whose minimum backup interval has ended, if the file still has no backup by then
+fn back_up_deferred_changes(
+    deferred_backups: &mut HashMap<PathBuf, Instant>,
+    last_backups: &mut HashMap<PathBuf, Instant>,
+    settings: &Settings,
+    status_tx: &mpsc::Sender<BackupStatus>,
+    ui_thread_tx: &app::Sender<UiMessage>
+) {
+    let due_file_paths: Vec<PathBuf> = deferred_backups.iter()
+        .filter(|(_file_path, due)| **due <= Instant::now())
+        .map(|(file_path, _due)| file_path.clone())
+        .collect();
+    for file_path in due_file_paths {
+        deferred_backups.remove(&file_path);
+        if file_path.exists() {
+            on_file_change(file_path, settings, last_backups, deferred_backups, status_tx, ui_thread_tx.clone());
+        } else {
+            debug!("{} disappeared before its deferred backup", file_path.str());
+        }
+    }
+}
+
+/// Backs up `backup_file_path` if it has no backup yet. When it was already backed up within the minimum backup
+/// interval, the backup is deferred to the end of the interval instead, by adding it to `deferred_backups`.
+/// `last_backups` holds when each live file was last backed up.
+fn on_file_change(
+    backup_file_path: PathBuf,
+    settings: &Settings,
+    last_backups: &mut HashMap<PathBuf, Instant>,
+    deferred_backups: &mut HashMap<PathBuf, Instant>,
+    status_tx: &mpsc::Sender<BackupStatus>,
+    ui_thread_tx: app::Sender<UiMessage>
+) {
+    if is_disabled_live_file(settings, &backup_file_path) {
+        debug!("Ignoring change to {}, its backup pattern is disabled", backup_file_path.str());
+        return;
+    }
+    let min_backup_interval = Duration::from_secs(settings.min_backup_interval_sec as u64);
+    if let Some(last_backup) = last_backups.get(&backup_file_path) {
+        if last_backup.elapsed() < min_backup_interval {
+            debug!("Deferring backup of {}, it was backed up less than {} seconds ago",
+                backup_file_path.str(), settings.min_backup_interval_sec);
+            deferred_backups.insert(backup_file_path, *last_backup + min_backup_interval);
+            return;
+        }
+    }
+    let file_has_backup = match live_file_has_backup(
+        settings.clone(), backup_file_path.clone(), &UiBackupListener(ui_thread_tx.clone())
+    ) {
         Ok(has_backup) => has_backup,
         Err(err) => {
-            handle_error(&ui_thread_tx, &err.into());
+            handle_error(status_tx, &ui_thread_tx, &err.into());
             return;
         }
     };
     if !file_has_backup {
-        if let Err(err) = backup_live_file(settings.clone(), backup_file_path.clone()) {
-            handle_error(&ui_thread_tx, &err.into());
-        }
-        if let Err(err) = delete_old_backups(settings.clone()) {
-            handle_error(&ui_thread_tx, &err.into());
+        match backup_live_file(settings.clone(), backup_file_path.clone(), &UiBackupListener(ui_thread_tx.clone())) {
+            Ok(backed_up_file_path) => {
+                last_backups.insert(backup_file_path.clone(), Instant::now());
+                if let Some(backed_up_file_path) = backed_up_file_path {
+                    note_own_dest_change();
+                    run_post_backup_command(settings, &backup_file_path, &backed_up_file_path);
+                    send_backed_up_status(settings, status_tx, backup_file_path, &backed_up_file_path);
+                }
+            }
+            Err(err) => handle_error(status_tx, &ui_thread_tx, &err.into())
         }
+        prune_old_backups(settings.clone(), status_tx, &ui_thread_tx);
         ui_thread_tx.send(UiMessage::RefreshFilesLists);
     }
 }
 
-fn handle_error(ui_thread_tx: &app::Sender<UiMessage>, err: &Error) {
+fn send_backed_up_status(
+    settings: &Settings, status_tx: &mpsc::Sender<BackupStatus>, live_file_path: PathBuf, backed_up_file_path: &PathBuf
+) {
+    if let Some(status) = get_backed_up_status(settings, live_file_path, backed_up_file_path) {
+        info!("{}", status);
+        send_status(status_tx, status);
+    }
+}
+
+/// Describes the new backed up file `backed_up_file_path` of `live_file_path`, including how it differs from the
+/// previous version. Needs to be called before pruning, which may remove the previous version.
+pub fn get_backed_up_status(
+    settings: &Settings, live_file_path: PathBuf, backed_up_file_path: &PathBuf
+) -> Option<BackupStatus> {
+    let version = match get_backed_up_version(backed_up_file_path) {
+        Some(version) => version,
+        None => {
+            error!("Invalid backup file name {}", backed_up_file_path.str());
+            return None;
+        }
+    };
+    let change = match get_backup_change(settings, backed_up_file_path) {
+        Ok(change) => change,
+        Err(err) => {
+            warn!("Error comparing {} with its previous version: {}", backed_up_file_path.str(), err);
+            None
+        }
+    };
+    Some(BackupStatus::BackedUp { path: live_file_path, version, change })
+}
+
+fn prune_old_backups(settings: Settings, status_tx: &mpsc::Sender<BackupStatus>, ui_thread_tx: &app::Sender<UiMessage>) {
+    match delete_old_backups(settings, &UiBackupListener(ui_thread_tx.clone())) {
+        Ok(0) => {}
+        Ok(count) => {
+            note_own_dest_change();
+            send_status(status_tx, BackupStatus::Pruned { count });
+        }
+        Err(err) => handle_error(status_tx, ui_thread_tx, &err.into())
+    }
+}
+
+/// Reports `err` according to its severity: warnings, such as a file skipped for its size, are counted in the main
+/// window, errors, such as a single file that cannot be read, are alerted while watching continues, and only fatal
+/// errors, such as the backup destination being gone, quit
+fn handle_error(status_tx: &mpsc::Sender<BackupStatus>, ui_thread_tx: &app::Sender<UiMessage>, err: &Error) {
     if let Some(file_err) = err.downcast_ref::<FileError>() {
         match file_err {
             FWarning(errs) => {
-                errs.iter().for_each(|err_msg| warn!("{}", err_msg))
+                errs.iter().for_each(|err_msg| {
+                    warn!("{}", err_msg);
+                    send_status(status_tx, BackupStatus::Warning { msg: err_msg.clone() });
+                })
             }
             FError(errs) => {
-                errs.iter().for_each(|err_msg| ui_thread_tx.send(UiMessage::Alert(err_msg.clone())));
+                errs.iter().for_each(|err_msg| send_status(status_tx, BackupStatus::Error { msg: err_msg.clone() }));
             }
             FFatal(errs) => {
                 errs.iter().for_each(|err_msg| ui_thread_tx.send(UiMessage::AlertQuit(err_msg.clone())));
             }
         }
     } else {
-        ui_thread_tx.send(UiMessage::AlertQuit(err.to_string()));
+        send_status(status_tx, BackupStatus::Error { msg: err.to_string() });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::mpsc;
+
+    use valbak::settings::{BackupFilePattern, Settings};
+
+    use crate::watcher::{BackupStatus, FolderWatcher, watch_source_dirs};
+
+    fn backup_pattern(source_dir: PathBuf) -> BackupFilePattern {
+        BackupFilePattern {
+            source_dir,
+            filename_pattern: "*.sav".to_string(),
+            dest_subdir: None,
+            dest_override: None,
+            directory_mode: false,
+            enabled: true
+        }
+    }
+
+    #[test]
+    fn unwatchable_source_folder_is_reported() {
+        let test_dir = std::env::temp_dir().join(format!("valbak-test-watch-{}", std::process::id()));
+        let existing_dir = test_dir.join("existing");
+        std::fs::create_dir_all(&existing_dir).unwrap();
+        // Not created later either, since not even an ancestor of it exists to be watched meanwhile
+        let unwatchable_dir = PathBuf::from("valbak-test-missing").join("saves");
+        // Created later, so that its parent is watched meanwhile
+        let missing_dir = test_dir.join("missing");
+        let source_dirs = vec![unwatchable_dir.clone(), existing_dir.clone(), missing_dir.clone()];
+        let settings = Settings::new(
+            source_dirs.iter().map(|source_dir| backup_pattern(source_dir.clone())).collect(),
+            test_dir.join("dest"));
+
+        let (watcher_tx, _watcher_rx) = mpsc::channel();
+        let mut watcher = FolderWatcher::new(watcher_tx, &settings).unwrap();
+        let (status_tx, status_rx) = mpsc::channel();
+        let mut awaited_source_dirs = Vec::new();
+        let mut unwatched_source_dirs = Vec::new();
+        watch_source_dirs(
+            &mut watcher, &settings, &source_dirs, &mut awaited_source_dirs, &mut unwatched_source_dirs, &status_tx);
+        let _ = std::fs::remove_dir_all(&test_dir);
+
+        assert_eq!(unwatched_source_dirs, vec![unwatchable_dir]);
+        let statuses: Vec<BackupStatus> = status_rx.try_iter().collect();
+        assert_eq!(statuses.len(), 1);
+        assert!(matches!(&statuses[0], BackupStatus::Error { msg } if msg.contains("valbak-test-missing")));
+        assert_eq!(awaited_source_dirs.len(), 1);
+        assert_eq!(awaited_source_dirs[0].source_dir, missing_dir);
+        assert_eq!(awaited_source_dirs[0].watched_ancestor, test_dir);
     }
 }
@@ -4,13 +4,29 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
+use std::cell::RefCell;
 use std::cmp::max;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
+use fltk::{app, draw};
+use fltk::app::Sender;
 use fltk::browser::MultiBrowser;
-use fltk::enums::Font;
+use fltk::button::Button;
+use fltk::enums::{Align, Event, Font, FrameType, Key};
 use fltk::frame::Frame;
-use fltk::group::{Pack, PackType};
-use fltk::prelude::{BrowserExt, GroupExt, WidgetExt};
+use fltk::group::{Group, Pack, PackType};
+use fltk::misc::Tooltip;
+use fltk::prelude::{BrowserExt, DisplayExt, GroupExt, WidgetBase, WidgetExt, WindowExt};
+use fltk::text::{TextBuffer, TextDisplay};
+use fltk::window::Window;
+use valbak::file::PathExt;
+use valbak::settings::DEFAULT_ERROR_SUMMARY_LEN;
+
+use crate::UiMessage;
+
+static SUMMARY_LEN: AtomicUsize = AtomicUsize::new(DEFAULT_ERROR_SUMMARY_LEN);
 
 pub fn make_section_header(header_text: &str, space_before: bool) {
     if space_before {
@@ -32,34 +48,35 @@ pub fn make_section_header(header_text: &str, space_before: bool) {
     section_label_pack.end();
 }
 
-pub fn column_headers(column_header_texts: &Vec<&str>, column_header_widths: &'static[i32]) {
-    let column_header_widths = Vec::from(column_header_widths);
-    let mut max_header_width = 0;
-    let mut max_header_height = 0;
-
-    let mut column_headers_pack = Pack::default()
-        .size_of_parent()
-        .with_type(PackType::Horizontal);
-
-    for (header_text, header_width) in column_header_texts.iter().zip(column_header_widths) {
-        let mut adjusted_header_text = String::from(" ");
-        adjusted_header_text.push_str(header_text);
+/// Lays out the headers of the columns of a list made by [`make_list_browser`] with the same `column_widths`. The headers
+/// are placed in a group rather than a pack, so that each starts exactly where the list draws its column: inside the
+/// frame of the list, after the widths of the columns before it, whatever the length of the header texts.
+pub fn column_headers(column_header_texts: &Vec<&str>, column_widths: &'static[i32]) {
+    let mut column_headers_group = Group::default()
+        .size_of_parent();
+    let mut column_x = column_headers_group.x() + FrameType::DownBox.dx();
+    let mut header_height = 0;
+    let mut label_frames = Vec::new();
 
+    for (header_text, column_width) in column_header_texts.iter().zip(column_widths) {
         let mut label_frame = Frame::default()
-            .with_label(&adjusted_header_text);
+            .with_label(header_text)
+            .with_pos(column_x, column_headers_group.y());
         label_frame.set_label_size(12);
-        let text_size = label_frame.measure_label();
-        label_frame.set_size(text_size.0, text_size.1);
-
-        Frame::default()
-            .with_size(header_width - text_size.0, text_size.1);
-
-        max_header_width = max(text_size.0, max_header_width);
-        max_header_height = max(text_size.1, max_header_height);
+        // Drawn 3 pixels in from the left, like the text of the columns
+        label_frame.set_align(Align::Left | Align::Inside | Align::Clip);
+        header_height = max(label_frame.measure_label().1, header_height);
+        label_frames.push((label_frame, *column_width));
+        column_x += column_width;
     }
-    column_headers_pack.set_size(max_header_width, max_header_height);
+    for (label_frame, column_width) in label_frames.iter_mut() {
+        label_frame.set_size(*column_width, header_height);
+    }
+    column_headers_group.set_size(column_x - column_headers_group.x(), header_height);
+    // Keeps the headers at the widths of the columns, which do not change with the window either
+    column_headers_group.make_resizable(false);
 
-    column_headers_pack.end();
+    column_headers_group.end();
 }
 
 pub fn make_list_browser(column_widths: &'static[i32], list_height: i32) -> MultiBrowser {
@@ -69,4 +86,174 @@ pub fn make_list_browser(column_widths: &'static[i32], list_height: i32) -> Mult
     list.set_column_char('|');
     list.set_column_widths(column_widths);
     list
+}
+
+/// Ends `content`, which must be the current group and already sized to `content_width`, with a row of buttons below
+/// it. The buttons labeled `left_labels` stay at the left and those labeled `right_labels` at the right when the window
+/// is resized. Returns the buttons in the order of `left_labels` followed by `right_labels`, for their callbacks to be
+/// set.
+pub fn make_bottom_button_group(
+    content: &mut Pack, content_width: i32, left_labels: &[&str], right_labels: &[&str]
+) -> Vec<Button> {
+    let mut bottom_button_group_pad = Frame::default();
+    bottom_button_group_pad.set_size(0, 10);
+
+    let mut bottom_button_group = Group::default();
+
+    content.end();
+
+    let make_button = |label: &str| {
+        let mut button = Button::default()
+            .with_label(label);
+        let text_size = button.measure_label();
+        button.set_size(text_size.0 + 50, text_size.1 + 14);
+        button
+    };
+    let mut left_buttons = Vec::new();
+    let mut left_x = 5;
+    for label in left_labels {
+        let mut button = make_button(label);
+        button.set_pos(left_x, 0);
+        left_x += button.width() + 5;
+        left_buttons.push(button);
+    }
+    // Laid out from the right edge
+    let mut right_buttons = Vec::new();
+    let mut right_x = content_width - 5;
+    for label in right_labels.iter().rev() {
+        let mut button = make_button(label);
+        right_x -= button.width();
+        button.set_pos(right_x, 0);
+        right_x -= 5;
+        right_buttons.insert(0, button);
+    }
+
+    let button_height = left_buttons.iter().chain(&right_buttons).map(|button| button.height()).max().unwrap_or(0);
+    bottom_button_group.set_size(content_width, button_height);
+    // Takes up the width gained when the window is resized, keeping the buttons at their sides
+    let spacer_x = if left_buttons.is_empty() { 0 } else { left_x };
+    let bottom_button_group_spacer = Frame::default()
+        .with_pos(spacer_x, 0)
+        .with_size(right_x - spacer_x, button_height);
+    for button in &left_buttons {
+        bottom_button_group.add(button);
+    }
+    bottom_button_group.add(&bottom_button_group_spacer);
+    bottom_button_group.resizable(&bottom_button_group_spacer);
+    for button in &right_buttons {
+        bottom_button_group.add(button);
+    }
+
+    left_buttons.append(&mut right_buttons);
+    left_buttons
+}
+
+/// Shows the full path of the row under the mouse as a tooltip, since long paths are truncated by the column widths.
+/// With `enter_message`, pressing Enter sends it for the selected rows, or for the row with the keyboard focus when no
+/// row is selected, e.g. after moving to it with the arrow keys.
+pub fn handle_list_events(
+    list: &mut MultiBrowser, row_paths: Rc<RefCell<Vec<PathBuf>>>, enter_message: Option<(Sender<UiMessage>, UiMessage)>
+) {
+    list.handle(move |list, event| {
+        if event == Event::KeyDown && matches!(app::event_key(), Key::Enter | Key::KPEnter) {
+            if let Some((tx, message)) = &enter_message {
+                let has_selection = (1..=list.size()).any(|line| list.selected(line));
+                // The focused row, which FLTK reports as the value of a multi browser
+                let focused_line = list.value();
+                if !has_selection && focused_line > 0 {
+                    list.select(focused_line);
+                }
+                if has_selection || focused_line > 0 {
+                    tx.send(message.clone());
+                }
+                // Otherwise FLTK would select only the focused row, dropping the rest of the selection
+                return true;
+            }
+        }
+        if event == Event::Move {
+            // FLTK does not expose the line under the mouse, so derive it from the line height and scroll position,
+            // allowing for the 2 pixel border of the default browser frame. Browsers have no accessor for their text
+            // font, but draw it in the first font slot, which app::set_font replaces with the app's font.
+            draw::set_font(Font::by_index(0), list.text_size());
+            let row_height = draw::height() + 2;
+            let row_y = app::event_y() - list.y() - 2 + list.position();
+            let line = row_y / row_height;
+            let row_paths = row_paths.borrow();
+            if row_y >= 0 && (line as usize) < row_paths.len() {
+                Tooltip::enter_area(
+                    list,
+                    list.x(),
+                    list.y() + 2 + line * row_height - list.position(),
+                    list.width(),
+                    row_height,
+                    row_paths[line as usize].str());
+            }
+        }
+        false
+    });
+}
+
+pub fn set_summary_len(summary_len: usize) {
+    SUMMARY_LEN.store(summary_len, Ordering::Relaxed);
+}
+
+/// Shortens `text` to the configured summary length by replacing its middle with "...", so that both the start of a
+/// message and the file name at the end of a path stay visible
+pub fn summarize(text: &str) -> String {
+    const ELLIPSIS: &str = "...";
+    let summary_len = SUMMARY_LEN.load(Ordering::Relaxed);
+    let char_count = text.chars().count();
+    if char_count <= summary_len {
+        return text.to_string();
+    }
+    let kept_len = summary_len.saturating_sub(ELLIPSIS.len());
+    // Favor the end, which holds the file name
+    let start_len = kept_len / 2;
+    let end_len = kept_len - start_len;
+    let start: String = text.chars().take(start_len).collect();
+    let end: String = text.chars().skip(char_count - end_len).collect();
+    start + ELLIPSIS + &end
+}
+
+/// Shows every message of `errs` in full in a scrollable list under `heading`, and waits until it is closed. Used
+/// instead of an alert when there are too many errors to read in one.
+pub fn show_error_list(heading: &str, errs: &[String]) {
+    static WINDOW_SIZE: (i32, i32) = (800, 480);
+    static CONTENT_SIZE: (i32, i32) = (WINDOW_SIZE.0 - 20, WINDOW_SIZE.1 - 20);
+
+    let mut wind = Window::default().with_label("Errors");
+    wind.make_modal(true);
+    wind.set_size(WINDOW_SIZE.0, WINDOW_SIZE.1);
+
+    let mut content = Pack::default()
+        .with_pos(10, 10);
+    content.set_spacing(5);
+
+    let mut heading_frame = Frame::default()
+        .with_label(heading);
+    heading_frame.set_size(0, 30);
+    heading_frame.set_align(Align::Left | Align::Inside | Align::Wrap);
+
+    let mut buffer = TextBuffer::default();
+    buffer.set_text(&errs.join("\n"));
+    let mut display = TextDisplay::default();
+    display.set_buffer(buffer);
+    display.set_size(0, CONTENT_SIZE.1 - 90);
+    // Takes up the height gained when the window is resized
+    content.resizable(&display);
+
+    content.set_size(CONTENT_SIZE.0, display.y() + display.height());
+
+    let mut buttons = make_bottom_button_group(&mut content, CONTENT_SIZE.0, &[], &["OK"]);
+    let mut ok_wind = wind.clone();
+    buttons[0].set_callback(move |_button| ok_wind.hide());
+
+    wind.end();
+    wind.resizable(&content);
+    wind.size_range(WINDOW_SIZE.0, WINDOW_SIZE.1, 0, 0);
+    wind.show();
+
+    while wind.shown() {
+        app::wait();
+    }
 }
\ No newline at end of file